@@ -5,7 +5,11 @@
 
 typedef enum {
   MVCC_OK = 0,
+  MVCC_ERROR = 1,
+  MVCC_NOT_FOUND = 12,
+  MVCC_CONSTRAINT = 19,
   MVCC_IO_ERROR_READ = 266,
+  MVCC_BUSY_SNAPSHOT = 517,
   MVCC_IO_ERROR_WRITE = 778,
 } MVCCError;
 
@@ -38,6 +42,15 @@ MVCCError MVCCDatabaseInsert(MVCCDatabaseRef db,
                              const void *value_ptr,
                              uintptr_t value_len);
 
+MVCCError MVCCDatabaseUpdate(MVCCDatabaseRef db,
+                             uint64_t tx_id,
+                             uint64_t table_id,
+                             uint64_t row_id,
+                             const void *value_ptr,
+                             uintptr_t value_len);
+
+MVCCError MVCCDatabaseDelete(MVCCDatabaseRef db, uint64_t tx_id, uint64_t table_id, uint64_t row_id);
+
 MVCCError MVCCDatabaseRead(MVCCDatabaseRef db,
                            uint64_t tx_id,
                            uint64_t table_id,
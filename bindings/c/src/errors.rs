@@ -1,6 +1,32 @@
+use mvcc_rs::errors::DatabaseError;
+
 #[repr(C)]
 pub enum MVCCError {
     MVCC_OK = 0,
+    MVCC_ERROR = 1,
+    MVCC_NOT_FOUND = 12,
+    MVCC_CONSTRAINT = 19,
     MVCC_IO_ERROR_READ = 266,
+    MVCC_BUSY_SNAPSHOT = 517,
     MVCC_IO_ERROR_WRITE = 778,
 }
+
+impl MVCCError {
+    /// Returns the error code of a failed write or commit. Only storage
+    /// failures are reported as I/O errors, conflicts with concurrent
+    /// transactions are reported as `MVCC_BUSY_SNAPSHOT`, so that callers
+    /// know to retry the transaction.
+    pub fn from_write_error(e: &DatabaseError) -> Self {
+        match e {
+            DatabaseError::WriteWriteConflict { .. }
+            | DatabaseError::PhantomRead
+            | DatabaseError::WriteSkewConflict
+            | DatabaseError::SerializationFailure => MVCCError::MVCC_BUSY_SNAPSHOT,
+            DatabaseError::RowAlreadyExists(_) | DatabaseError::ForeignKeyError { .. } => {
+                MVCCError::MVCC_CONSTRAINT
+            }
+            DatabaseError::StorageError(_) | DatabaseError::Io(_) => MVCCError::MVCC_IO_ERROR_WRITE,
+            _ => MVCCError::MVCC_ERROR,
+        }
+    }
+}
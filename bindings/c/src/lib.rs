@@ -5,7 +5,8 @@ mod errors;
 mod types;
 
 use errors::MVCCError;
-use mvcc_rs::persistent_storage::{s3, Storage};
+use mvcc_rs::database::TxID;
+use mvcc_rs::persistent_storage::{s3, JsonOnDisk, Noop, Storage, WalStorage};
 use mvcc_rs::*;
 use types::{DbContext, MVCCDatabaseRef, MVCCScanCursorRef, ScanCursorContext};
 
@@ -22,20 +23,26 @@ type ScanCursor = cursor::ScanCursor<'static, Clock, String>;
 
 static INIT_RUST_LOG: std::sync::Once = std::sync::Once::new();
 
-fn storage_for(main_db_path: &str) -> database::Result<Storage> {
+fn storage_for(main_db_path: &str) -> database::Result<Box<dyn Storage<String>>> {
     // TODO: let's accept an URL instead of main_db_path here, so we can
     // pass custom S3 endpoints, options, etc.
     if cfg!(feature = "json_on_disk_storage") {
         tracing::info!("JSONonDisk storage stored in {main_db_path}-mvcc");
-        return Ok(Storage::new_json_on_disk(format!("{main_db_path}-mvcc")));
+        return Ok(Box::new(JsonOnDisk::new(format!("{main_db_path}-mvcc"))));
+    }
+    if cfg!(feature = "wal_storage") {
+        tracing::info!("WAL storage stored in {main_db_path}-mvcc-wal");
+        return Ok(Box::new(WalStorage::new(format!(
+            "{main_db_path}-mvcc-wal"
+        ))));
     }
     if cfg!(feature = "s3_storage") {
         tracing::info!("S3 storage for {main_db_path}");
         let options = s3::Options::with_create_bucket_if_not_exists(true);
-        return Storage::new_s3(options);
+        return Ok(Box::new(s3::Replicator::new_blocking(options)?));
     }
     tracing::info!("No persistent storage for {main_db_path}");
-    Ok(Storage::new_noop())
+    Ok(Box::new(Noop))
 }
 
 #[no_mangle]
@@ -87,18 +94,18 @@ pub unsafe extern "C" fn MVCCTransactionBegin(db: MVCCDatabaseRef) -> u64 {
     let db = db.get_ref();
     let tx_id = db.begin_tx();
     tracing::debug!("MVCCTransactionBegin: {tx_id}");
-    tx_id
+    tx_id.into()
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn MVCCTransactionCommit(db: MVCCDatabaseRef, tx_id: u64) -> MVCCError {
     let db = db.get_ref();
     tracing::debug!("MVCCTransactionCommit: {tx_id}");
-    match db.commit_tx(tx_id) {
+    match db.commit_tx(TxID(tx_id)) {
         Ok(()) => MVCCError::MVCC_OK,
         Err(e) => {
             tracing::error!("MVCCTransactionCommit: {e}");
-            MVCCError::MVCC_IO_ERROR_WRITE
+            MVCCError::from_write_error(&e)
         }
     }
 }
@@ -107,7 +114,7 @@ pub unsafe extern "C" fn MVCCTransactionCommit(db: MVCCDatabaseRef, tx_id: u64)
 pub unsafe extern "C" fn MVCCTransactionRollback(db: MVCCDatabaseRef, tx_id: u64) -> MVCCError {
     let db = db.get_ref();
     tracing::debug!("MVCCTransactionRollback: {tx_id}");
-    db.rollback_tx(tx_id);
+    db.rollback_tx(TxID(tx_id));
     MVCCError::MVCC_OK
 }
 
@@ -121,26 +128,87 @@ pub unsafe extern "C" fn MVCCDatabaseInsert(
     value_len: usize,
 ) -> MVCCError {
     let db = db.get_ref();
-    let value = std::slice::from_raw_parts(value_ptr as *const u8, value_len);
-    let data = match std::str::from_utf8(value) {
-        Ok(value) => value.to_string(),
-        Err(_) => {
-            tracing::info!("Invalid UTF-8, let's base64 this fellow");
-            use base64::{engine::general_purpose, Engine as _};
-            general_purpose::STANDARD.encode(value)
-        }
-    };
-    let id = database::RowID { table_id, row_id };
+    let data = row_data(value_ptr, value_len);
+    let id = database::RowID::from_u64(table_id, row_id);
     let row = database::Row { id, data };
     tracing::debug!("MVCCDatabaseInsert: {row:?}");
-    match db.insert(tx_id, row) {
+    match db.insert(TxID(tx_id), row) {
         Ok(_) => {
             tracing::debug!("MVCCDatabaseInsert: success");
             MVCCError::MVCC_OK
         }
         Err(e) => {
             tracing::error!("MVCCDatabaseInsert: {e}");
-            MVCCError::MVCC_IO_ERROR_WRITE
+            MVCCError::from_write_error(&e)
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn MVCCDatabaseUpdate(
+    db: MVCCDatabaseRef,
+    tx_id: u64,
+    table_id: u64,
+    row_id: u64,
+    value_ptr: *const std::ffi::c_void,
+    value_len: usize,
+) -> MVCCError {
+    let db = db.get_ref();
+    let data = row_data(value_ptr, value_len);
+    let id = database::RowID::from_u64(table_id, row_id);
+    let row = database::Row { id, data };
+    tracing::debug!("MVCCDatabaseUpdate: {row:?}");
+    match db.update(TxID(tx_id), row) {
+        Ok(true) => {
+            tracing::debug!("MVCCDatabaseUpdate: success");
+            MVCCError::MVCC_OK
+        }
+        Ok(false) => {
+            tracing::debug!("MVCCDatabaseUpdate: not found");
+            MVCCError::MVCC_NOT_FOUND
+        }
+        Err(e) => {
+            tracing::error!("MVCCDatabaseUpdate: {e}");
+            MVCCError::from_write_error(&e)
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn MVCCDatabaseDelete(
+    db: MVCCDatabaseRef,
+    tx_id: u64,
+    table_id: u64,
+    row_id: u64,
+) -> MVCCError {
+    let db = db.get_ref();
+    let id = database::RowID::from_u64(table_id, row_id);
+    tracing::debug!("MVCCDatabaseDelete: {id:?}");
+    match db.delete(TxID(tx_id), id) {
+        Ok(true) => {
+            tracing::debug!("MVCCDatabaseDelete: success");
+            MVCCError::MVCC_OK
+        }
+        Ok(false) => {
+            tracing::debug!("MVCCDatabaseDelete: not found");
+            MVCCError::MVCC_NOT_FOUND
+        }
+        Err(e) => {
+            tracing::error!("MVCCDatabaseDelete: {e}");
+            MVCCError::from_write_error(&e)
+        }
+    }
+}
+
+/// Converts a value passed from C to row data, base64-encoding it if it's not valid UTF-8.
+unsafe fn row_data(value_ptr: *const std::ffi::c_void, value_len: usize) -> String {
+    let value = std::slice::from_raw_parts(value_ptr as *const u8, value_len);
+    match std::str::from_utf8(value) {
+        Ok(value) => value.to_string(),
+        Err(_) => {
+            tracing::info!("Invalid UTF-8, let's base64 this fellow");
+            use base64::{engine::general_purpose, Engine as _};
+            general_purpose::STANDARD.encode(value)
         }
     }
 }
@@ -156,9 +224,9 @@ pub unsafe extern "C" fn MVCCDatabaseRead(
 ) -> MVCCError {
     let db = db.get_ref();
 
-    match {
-        let id = database::RowID { table_id, row_id };
-        let maybe_row = db.read(tx_id, id);
+    let result = {
+        let id = database::RowID::from_u64(table_id, row_id);
+        let maybe_row = db.read(TxID(tx_id), id);
         match maybe_row {
             Ok(Some(row)) => {
                 tracing::debug!("Found row {row:?}");
@@ -172,7 +240,8 @@ pub unsafe extern "C" fn MVCCDatabaseRead(
             _ => unsafe { *value_len = -1 },
         };
         Ok::<(), mvcc_rs::errors::DatabaseError>(())
-    } {
+    };
+    match result {
         Ok(_) => {
             tracing::debug!("MVCCDatabaseRead: success");
             MVCCError::MVCC_OK
@@ -202,7 +271,7 @@ pub unsafe extern "C" fn MVCCScanCursorOpen(
     // Reference is transmuted to &'static in order to be able to pass the cursor back to C.
     // The contract with C is to never use a cursor after MVCCDatabaseClose() has been called.
     let db = unsafe { std::mem::transmute::<&Db, &'static Db>(db.get_ref()) };
-    match mvcc_rs::cursor::ScanCursor::new(db, tx_id, table_id) {
+    match mvcc_rs::cursor::ScanCursor::new(db, TxID(tx_id), table_id) {
         Ok(cursor) => {
             if cursor.is_empty() {
                 tracing::debug!("Cursor is empty");
@@ -248,7 +317,7 @@ pub unsafe extern "C" fn MVCCScanCursorRead(
     }
     let cursor = cursor.get_ref();
 
-    match {
+    let result = {
         let maybe_row = cursor.current_row();
         match maybe_row {
             Ok(Some(row)) => {
@@ -263,7 +332,8 @@ pub unsafe extern "C" fn MVCCScanCursorRead(
             _ => unsafe { *value_len = -1 },
         };
         Ok::<(), mvcc_rs::errors::DatabaseError>(())
-    } {
+    };
+    match result {
         Ok(_) => {
             tracing::debug!("MVCCDatabaseRead: success");
             MVCCError::MVCC_OK
@@ -293,6 +363,6 @@ pub unsafe extern "C" fn MVCCScanCursorPosition(cursor: MVCCScanCursorRef) -> u6
     let cursor = cursor.get_ref();
     cursor
         .current_row_id()
-        .map(|row_id| row_id.row_id)
+        .and_then(|row_id| row_id.as_u64())
         .unwrap_or(0)
 }
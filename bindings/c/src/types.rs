@@ -73,7 +73,6 @@ impl MVCCScanCursorRef {
 
     #[allow(clippy::mut_from_ref)]
     pub fn get_ref_mut(&self) -> &mut crate::ScanCursor {
-        let ptr_mut = self.ptr as *mut ScanCursorContext;
-        &mut unsafe { &mut (*ptr_mut) }.cursor
+        &mut unsafe { &mut (*self.ptr) }.cursor
     }
 }
@@ -0,0 +1,39 @@
+//! A page visit counter that keeps its database in the browser's `localStorage`.
+//!
+//! Build it with [wasm-pack](https://rustwasm.github.io/wasm-pack/):
+//!
+//! ```console
+//! wasm-pack build --target web examples/wasm
+//! ```
+//!
+//! and serve `examples/wasm` with any static file server, for example
+//! `python3 -m http.server`, to open `index.html`.
+
+use mvcc_rs::clock::LocalClock;
+use mvcc_rs::database::{Database, Row, RowID};
+use mvcc_rs::persistent_storage::WasmStorage;
+use wasm_bindgen::prelude::*;
+
+const VISITS: RowID = RowID {
+    table_id: 1,
+    key: Vec::new(),
+};
+
+/// Counts a visit and returns the number of visits so far.
+#[wasm_bindgen]
+pub fn visit() -> Result<u64, JsError> {
+    let db: Database<LocalClock, u64> =
+        Database::new(LocalClock::new(), WasmStorage::new("mvcc-wasm-example"));
+    db.recover()?;
+    let tx_id = db.begin_tx();
+    let visits = db.read(tx_id, VISITS)?.map_or(0, |row| row.data) + 1;
+    db.upsert(
+        tx_id,
+        Row {
+            id: VISITS,
+            data: visits,
+        },
+    )?;
+    db.commit_tx(tx_id)?;
+    Ok(visits)
+}
@@ -1,12 +1,12 @@
 use criterion::async_executor::FuturesExecutor;
-use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
 use mvcc_rs::clock::LocalClock;
 use mvcc_rs::database::{Database, Row, RowID};
 use pprof::criterion::{Output, PProfProfiler};
 
 fn bench_db() -> Database<LocalClock, String> {
     let clock = LocalClock::default();
-    let storage = mvcc_rs::persistent_storage::Storage::new_noop();
+    let storage = mvcc_rs::persistent_storage::Noop;
     Database::new(clock, storage)
 }
 
@@ -41,14 +41,7 @@ fn bench(c: &mut Criterion) {
     group.bench_function("begin_tx-read-commit_tx", |b| {
         b.to_async(FuturesExecutor).iter(|| async {
             let tx_id = db.begin_tx();
-            db.read(
-                tx_id,
-                RowID {
-                    table_id: 1,
-                    row_id: 1,
-                },
-            )
-            .unwrap();
+            db.read(tx_id, RowID::from_u64(1, 1)).unwrap();
             db.commit_tx(tx_id)
         })
     });
@@ -60,10 +53,7 @@ fn bench(c: &mut Criterion) {
             db.update(
                 tx_id,
                 Row {
-                    id: RowID {
-                        table_id: 1,
-                        row_id: 1,
-                    },
+                    id: RowID::from_u64(1, 1),
                     data: "World".to_string(),
                 },
             )
@@ -77,24 +67,14 @@ fn bench(c: &mut Criterion) {
     db.insert(
         tx,
         Row {
-            id: RowID {
-                table_id: 1,
-                row_id: 1,
-            },
+            id: RowID::from_u64(1, 1),
             data: "Hello".to_string(),
         },
     )
     .unwrap();
     group.bench_function("read", |b| {
         b.to_async(FuturesExecutor).iter(|| async {
-            db.read(
-                tx,
-                RowID {
-                    table_id: 1,
-                    row_id: 1,
-                },
-            )
-            .unwrap();
+            db.read(tx, RowID::from_u64(1, 1)).unwrap();
         })
     });
 
@@ -103,10 +83,7 @@ fn bench(c: &mut Criterion) {
     db.insert(
         tx,
         Row {
-            id: RowID {
-                table_id: 1,
-                row_id: 1,
-            },
+            id: RowID::from_u64(1, 1),
             data: "Hello".to_string(),
         },
     )
@@ -116,10 +93,7 @@ fn bench(c: &mut Criterion) {
             db.update(
                 tx,
                 Row {
-                    id: RowID {
-                        table_id: 1,
-                        row_id: 1,
-                    },
+                    id: RowID::from_u64(1, 1),
                     data: "World".to_string(),
                 },
             )
@@ -128,9 +102,51 @@ fn bench(c: &mut Criterion) {
     });
 }
 
+fn bench_batch(c: &mut Criterion) {
+    const ROWS: u64 = 100;
+    let rows = || {
+        (0..ROWS)
+            .map(|row_id| Row {
+                id: RowID::from_u64(1, row_id),
+                data: "Hello".to_string(),
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut group = c.benchmark_group("mvcc-batch-throughput");
+    group.throughput(Throughput::Elements(ROWS));
+
+    let db = bench_db();
+    group.bench_function("begin_tx-insert-rollback_tx", |b| {
+        b.iter_batched(
+            rows,
+            |rows| {
+                let tx_id = db.begin_tx();
+                for row in rows {
+                    db.insert(tx_id, row).unwrap();
+                }
+                db.rollback_tx(tx_id)
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    let db = bench_db();
+    group.bench_function("begin_tx-insert_batch-rollback_tx", |b| {
+        b.iter_batched(
+            rows,
+            |rows| {
+                let tx_id = db.begin_tx();
+                db.insert_batch(tx_id, rows).unwrap();
+                db.rollback_tx(tx_id)
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
-    targets = bench
+    targets = bench, bench_batch
 }
 criterion_main!(benches);
@@ -0,0 +1,69 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use mvcc_rs::clock::LocalClock;
+use mvcc_rs::database::{Database, Row, RowID};
+use mvcc_rs::persistent_storage::{BincodeOnDisk, JsonOnDisk, Storage};
+use pprof::criterion::{Output, PProfProfiler};
+use std::path::{Path, PathBuf};
+
+const TXS: u64 = 10000;
+
+fn log_path(name: &str) -> PathBuf {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-recovery-bench-{name}-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn populate(storage: Box<dyn Storage<String>>) {
+    let db: Database<LocalClock, String> = Database::new(LocalClock::default(), storage);
+    for row_id in 0..TXS {
+        let tx = db.begin_tx();
+        db.insert(
+            tx,
+            Row {
+                id: RowID::from_u64(1, row_id),
+                data: format!("Hello, world #{row_id}"),
+            },
+        )
+        .unwrap();
+        db.commit_tx(tx).unwrap();
+    }
+}
+
+fn bench_recover(
+    c: &mut Criterion,
+    name: &str,
+    path: &Path,
+    storage: fn(&Path) -> Box<dyn Storage<String>>,
+) {
+    populate(storage(path));
+    c.benchmark_group("mvcc-recovery")
+        .throughput(Throughput::Elements(TXS))
+        .bench_function(name, |b| {
+            b.iter_batched(
+                || Database::<LocalClock, String>::new(LocalClock::default(), storage(path)),
+                |db| db.recover().unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    let _ = std::fs::remove_file(path);
+}
+
+fn bench(c: &mut Criterion) {
+    bench_recover(c, "json_on_disk", &log_path("json"), |path| {
+        Box::new(JsonOnDisk::new(path))
+    });
+    bench_recover(c, "bincode_on_disk", &log_path("bincode"), |path| {
+        Box::new(BincodeOnDisk::new(path))
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
+    targets = bench
+}
+criterion_main!(benches);
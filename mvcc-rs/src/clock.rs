@@ -1,9 +1,36 @@
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Logical clock.
 pub trait LogicalClock {
     fn get_timestamp(&self) -> u64;
+    /// Advances the clock so that subsequent timestamps are at least `ts`.
+    /// The clock never moves backwards.
     fn reset(&self, ts: u64);
+
+    /// Returns a timestamp greater than every timestamp handed out so far,
+    /// without advancing the clock if it can.
+    ///
+    /// Clocks that know their next timestamp return it. The default
+    /// implementation hands out a new timestamp with `get_timestamp`.
+    fn peek(&self) -> u64 {
+        self.get_timestamp()
+    }
+}
+
+impl<C: LogicalClock + ?Sized> LogicalClock for std::sync::Arc<C> {
+    fn get_timestamp(&self) -> u64 {
+        (**self).get_timestamp()
+    }
+
+    fn reset(&self, ts: u64) {
+        (**self).reset(ts)
+    }
+
+    fn peek(&self) -> u64 {
+        (**self).peek()
+    }
 }
 
 /// A node-local clock backed by an atomic counter.
@@ -26,6 +53,423 @@ impl LogicalClock for LocalClock {
     }
 
     fn reset(&self, ts: u64) {
-        self.ts_sequence.store(ts, Ordering::SeqCst);
+        self.ts_sequence.fetch_max(ts, Ordering::SeqCst);
+    }
+
+    fn peek(&self) -> u64 {
+        self.ts_sequence.load(Ordering::SeqCst)
+    }
+}
+
+/// A hybrid logical clock (HLC).
+///
+/// Timestamps encode the physical time in milliseconds since the Unix epoch
+/// in the upper 48 bits and a logical counter in the lower 16 bits. They
+/// correlate with wall-clock time, but still advance monotonically if the
+/// physical clock stalls or goes backwards.
+#[derive(Debug, Default)]
+pub struct HybridLogicalClock {
+    last_ts: AtomicU64,
+}
+
+impl HybridLogicalClock {
+    const LOGICAL_BITS: u32 = 16;
+
+    pub fn new() -> Self {
+        Self {
+            last_ts: AtomicU64::new(0),
+        }
+    }
+
+    /// Advances the clock past a timestamp received from another node.
+    pub fn update(&self, received_ts: u64) {
+        self.last_ts.fetch_max(received_ts, Ordering::SeqCst);
+    }
+
+    /// Returns the physical time component of `ts` in milliseconds since the Unix epoch.
+    pub fn physical_time(ts: u64) -> u64 {
+        ts >> Self::LOGICAL_BITS
+    }
+
+    /// Returns the logical counter component of `ts`.
+    pub fn logical_counter(ts: u64) -> u16 {
+        ts as u16
+    }
+
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    fn now() -> u64 {
+        let physical_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        physical_ms << Self::LOGICAL_BITS
+    }
+
+    // The system time is not available in browsers, so ask JavaScript instead.
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    fn now() -> u64 {
+        let physical_ms = js_sys::Date::now() as u64;
+        physical_ms << Self::LOGICAL_BITS
+    }
+}
+
+impl LogicalClock for HybridLogicalClock {
+    fn get_timestamp(&self) -> u64 {
+        let now = Self::now();
+        let mut last_ts = self.last_ts.load(Ordering::SeqCst);
+        loop {
+            let ts = now.max(last_ts + 1);
+            match self.last_ts.compare_exchange_weak(
+                last_ts,
+                ts,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return ts,
+                Err(current) => last_ts = current,
+            }
+        }
+    }
+
+    fn reset(&self, ts: u64) {
+        self.last_ts.fetch_max(ts, Ordering::SeqCst);
+    }
+}
+
+/// A clock that counts nanoseconds of monotonic wall-clock time.
+///
+/// Timestamps are the nanoseconds elapsed since the clock was created,
+/// measured with `std::time::Instant`, so they are not affected by the system
+/// clock being stepped. Resetting the clock to a timestamp ahead of it, for
+/// example when recovering a log written by a previous process, records an
+/// offset so that timestamps keep advancing at wall-clock rate from there.
+/// If the clock ever reads a time before its last timestamp, it stalls,
+/// advancing by one nanosecond per timestamp until wall time catches up.
+#[derive(Debug)]
+pub struct MonotonicWallClock {
+    epoch: std::time::Instant,
+    offset: AtomicU64,
+    last_ts: AtomicU64,
+}
+
+impl MonotonicWallClock {
+    pub fn new() -> Self {
+        Self {
+            epoch: std::time::Instant::now(),
+            offset: AtomicU64::new(0),
+            last_ts: AtomicU64::new(0),
+        }
+    }
+
+    fn elapsed(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+}
+
+impl Default for MonotonicWallClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogicalClock for MonotonicWallClock {
+    fn get_timestamp(&self) -> u64 {
+        let now = self.elapsed() + self.offset.load(Ordering::SeqCst);
+        let mut last_ts = self.last_ts.load(Ordering::SeqCst);
+        loop {
+            let ts = now.max(last_ts + 1);
+            match self.last_ts.compare_exchange_weak(
+                last_ts,
+                ts,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return ts,
+                Err(current) => last_ts = current,
+            }
+        }
+    }
+
+    fn reset(&self, ts: u64) {
+        self.offset
+            .fetch_max(ts.saturating_sub(self.elapsed()), Ordering::SeqCst);
+        self.last_ts.fetch_max(ts, Ordering::SeqCst);
+    }
+}
+
+/// A clock for tests that controls exactly which timestamps are handed out.
+///
+/// Like `LocalClock`, every timestamp is one greater than the previous one,
+/// but tests can move the clock to any timestamp with `set` or `advance`, for
+/// example to reproduce the timestamps of a scenario from the paper. Share
+/// the clock with the database by wrapping it in an `Arc`.
+#[derive(Debug, Default)]
+pub struct SimClock {
+    next_ts: AtomicU64,
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        Self {
+            next_ts: AtomicU64::new(0),
+        }
+    }
+
+    /// Makes `ts` the next timestamp, even if the clock has to go backwards.
+    pub fn set(&self, ts: u64) {
+        self.next_ts.store(ts, Ordering::SeqCst);
+    }
+
+    /// Skips the next `delta` timestamps.
+    pub fn advance(&self, delta: u64) {
+        self.next_ts.fetch_add(delta, Ordering::SeqCst);
+    }
+
+    /// Returns the next timestamp without advancing the clock.
+    pub fn snapshot(&self) -> u64 {
+        self.next_ts.load(Ordering::SeqCst)
+    }
+}
+
+impl LogicalClock for SimClock {
+    fn get_timestamp(&self) -> u64 {
+        self.next_ts.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn reset(&self, ts: u64) {
+        self.next_ts.fetch_max(ts, Ordering::SeqCst);
+    }
+
+    fn peek(&self) -> u64 {
+        self.snapshot()
+    }
+}
+
+/// The ID of a node in a multi-node deployment.
+pub type NodeID = u64;
+
+/// A vector clock for tracking causal dependencies between nodes.
+///
+/// The clock keeps a counter for every node it has heard from. It advances
+/// the counter of its own node for every timestamp, and takes the
+/// component-wise maximum with vectors received from other nodes.
+/// Timestamps are the sum of all counters, so a timestamp is always greater
+/// than the timestamps of the events it causally depends on. Use `vector`
+/// and `happened_before` to tell concurrent events apart.
+#[derive(Debug)]
+pub struct VectorClock {
+    node_id: NodeID,
+    counters: Mutex<BTreeMap<NodeID, u64>>,
+}
+
+impl VectorClock {
+    pub fn new(node_id: NodeID) -> Self {
+        Self {
+            node_id,
+            counters: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn node_id(&self) -> NodeID {
+        self.node_id
+    }
+
+    /// Returns the counters of all nodes the clock has heard from, ordered by node ID.
+    pub fn vector(&self) -> Vec<(NodeID, u64)> {
+        let counters = self.counters.lock();
+        counters
+            .iter()
+            .map(|(&node_id, &counter)| (node_id, counter))
+            .collect()
+    }
+
+    /// Advances the clock past a vector received from another node.
+    pub fn update(&self, received: &[(NodeID, u64)]) {
+        let mut counters = self.counters.lock();
+        for &(node_id, counter) in received {
+            let current = counters.entry(node_id).or_default();
+            *current = (*current).max(counter);
+        }
+    }
+
+    /// Returns true if the event with vector `a` happened before the event
+    /// with vector `b`. If neither happened before the other, the events
+    /// are concurrent.
+    pub fn happened_before(a: &[(NodeID, u64)], b: &[(NodeID, u64)]) -> bool {
+        let counter = |vector: &[(NodeID, u64)], node_id| {
+            vector
+                .iter()
+                .find(|(id, _)| *id == node_id)
+                .map_or(0, |(_, counter)| *counter)
+        };
+        a.iter().all(|&(node_id, c)| c <= counter(b, node_id))
+            && b.iter().any(|&(node_id, c)| c > counter(a, node_id))
+    }
+}
+
+impl LogicalClock for VectorClock {
+    fn get_timestamp(&self) -> u64 {
+        let mut counters = self.counters.lock();
+        *counters.entry(self.node_id).or_default() += 1;
+        counters.values().sum()
+    }
+
+    fn reset(&self, ts: u64) {
+        let mut counters = self.counters.lock();
+        let sum: u64 = counters.values().sum();
+        if sum < ts {
+            *counters.entry(self.node_id).or_default() += ts - sum;
+        }
+    }
+
+    fn peek(&self) -> u64 {
+        self.counters.lock().values().sum::<u64>() + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_local_clock_reset() {
+        let clock = LocalClock::new();
+        clock.reset(10);
+        clock.reset(5);
+        assert_eq!(clock.get_timestamp(), 10);
+        assert_eq!(clock.get_timestamp(), 11);
+    }
+
+    #[test]
+    fn test_monotonic_wall_clock() {
+        let clock = MonotonicWallClock::new();
+        let ts = clock.get_timestamp();
+        assert!(clock.get_timestamp() > ts);
+
+        // Resetting the clock to the future moves wall time forward.
+        let future = ts + 60_000_000_000;
+        clock.reset(future);
+        let ts = clock.get_timestamp();
+        assert!(ts >= future);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(clock.get_timestamp() >= ts + 10_000_000);
+
+        // Resetting the clock to the past does not move it back.
+        clock.reset(1);
+        assert!(clock.get_timestamp() > ts);
+    }
+
+    #[test]
+    fn test_hlc_monotonic() {
+        let clock = Arc::new(HybridLogicalClock::new());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let clock = clock.clone();
+                std::thread::spawn(move || {
+                    (0..10000)
+                        .map(|_| clock.get_timestamp())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut all = Vec::new();
+        for th in threads {
+            let timestamps = th.join().unwrap();
+            assert!(timestamps.windows(2).all(|w| w[0] < w[1]));
+            all.extend(timestamps);
+        }
+        let len = all.len();
+        all.sort();
+        all.dedup();
+        assert_eq!(len, all.len());
+    }
+
+    #[test]
+    fn test_hlc_reset_and_update() {
+        let clock = HybridLogicalClock::new();
+        let ts = clock.get_timestamp();
+        assert!(HybridLogicalClock::physical_time(ts) > 0);
+
+        // A timestamp far in the future only advances the logical counter.
+        let future = (HybridLogicalClock::physical_time(ts) + 60_000) << 16 | 41;
+        clock.reset(future);
+        let ts = clock.get_timestamp();
+        assert_eq!(
+            HybridLogicalClock::physical_time(ts),
+            HybridLogicalClock::physical_time(future)
+        );
+        assert_eq!(HybridLogicalClock::logical_counter(ts), 42);
+
+        // An older timestamp received from another node does not move the clock back.
+        clock.update(future - 100);
+        assert!(clock.get_timestamp() > ts);
+    }
+
+    #[test]
+    fn test_vector_clock() {
+        let a = VectorClock::new(1);
+        let b = VectorClock::new(2);
+        let ts = a.get_timestamp();
+        let sent = a.vector();
+        assert_eq!(sent, vec![(1, 1)]);
+
+        // Receiving a vector makes later events causally depend on it.
+        b.get_timestamp();
+        b.update(&sent);
+        assert!(b.get_timestamp() > ts);
+        let received = b.vector();
+        assert_eq!(received, vec![(1, 1), (2, 2)]);
+        assert!(VectorClock::happened_before(&sent, &received));
+        assert!(!VectorClock::happened_before(&received, &sent));
+
+        // Events on different nodes that did not exchange vectors are concurrent.
+        a.get_timestamp();
+        let concurrent = a.vector();
+        assert!(!VectorClock::happened_before(&concurrent, &received));
+        assert!(!VectorClock::happened_before(&received, &concurrent));
+
+        a.reset(10);
+        a.reset(5);
+        assert_eq!(a.get_timestamp(), 11);
+    }
+
+    #[test]
+    fn test_clock_peek() {
+        let clock = LocalClock::new();
+        clock.reset(10);
+        assert_eq!(clock.peek(), 10);
+        assert_eq!(clock.peek(), 10);
+        assert_eq!(clock.get_timestamp(), 10);
+
+        let clock = Arc::new(SimClock::new());
+        clock.set(5);
+        assert_eq!(clock.peek(), 5);
+        assert_eq!(clock.get_timestamp(), 5);
+
+        let clock = VectorClock::new(1);
+        let ts = clock.get_timestamp();
+        assert_eq!(clock.peek(), ts + 1);
+        assert_eq!(clock.get_timestamp(), ts + 1);
+
+        // Clocks without a peek of their own hand out a new timestamp.
+        let clock = HybridLogicalClock::new();
+        let ts = clock.peek();
+        assert!(clock.get_timestamp() > ts);
+    }
+
+    #[test]
+    fn test_sim_clock() {
+        let clock = SimClock::new();
+        clock.set(60);
+        assert_eq!(clock.snapshot(), 60);
+        assert_eq!(clock.get_timestamp(), 60);
+        assert_eq!(clock.get_timestamp(), 61);
+        clock.advance(10);
+        assert_eq!(clock.get_timestamp(), 72);
+        clock.set(30);
+        assert_eq!(clock.get_timestamp(), 30);
+        clock.reset(20);
+        assert_eq!(clock.snapshot(), 31);
     }
 }
@@ -2,7 +2,7 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use crate::clock::LogicalClock;
-use crate::database::{Database, Result, Row, RowID};
+use crate::database::{Database, Result, Row, RowID, TableEntries, TxID};
 use std::fmt::Debug;
 
 #[derive(Debug)]
@@ -10,16 +10,16 @@ pub struct ScanCursor<'a, Clock: LogicalClock, T: Sync + Send + Clone + Serializ
     pub db: &'a Database<Clock, T>,
     pub row_ids: Vec<RowID>,
     pub index: usize,
-    tx_id: u64,
+    tx_id: TxID,
 }
 
 impl<'a, Clock: LogicalClock, T: Sync + Send + Clone + Serialize + DeserializeOwned + Debug + 'static> ScanCursor<'a, Clock, T> {
     pub fn new(
         db: &'a Database<Clock, T>,
-        tx_id: u64,
+        tx_id: TxID,
         table_id: u64,
     ) -> Result<ScanCursor<'a, Clock, T>> {
-        let row_ids = db.scan_row_ids_for_table(table_id)?;
+        let row_ids = db.scan_row_ids_for_table(tx_id, table_id)?;
         Ok(Self {
             db,
             tx_id,
@@ -32,14 +32,14 @@ impl<'a, Clock: LogicalClock, T: Sync + Send + Clone + Serialize + DeserializeOw
         if self.index >= self.row_ids.len() {
             return None;
         }
-        Some(self.row_ids[self.index])
+        Some(self.row_ids[self.index].clone())
     }
 
     pub fn current_row(&self) -> Result<Option<Row<T>>> {
         if self.index >= self.row_ids.len() {
             return Ok(None);
         }
-        let id = self.row_ids[self.index];
+        let id = self.row_ids[self.index].clone();
         self.db.read(self.tx_id, id)
     }
 
@@ -56,3 +56,44 @@ impl<'a, Clock: LogicalClock, T: Sync + Send + Clone + Serialize + DeserializeOw
         self.index >= self.row_ids.len()
     }
 }
+
+/// A cursor that lazily scans the rows of a table that are visible to a transaction.
+///
+/// Unlike `ScanCursor`, the row IDs are not collected up front: the cursor
+/// walks the table as it is advanced, and visibility is always checked against
+/// the snapshot of the transaction, no matter how slowly the cursor is driven.
+pub struct LazyScanCursor<'a, Clock: LogicalClock, T: Sync + Send + Clone + Serialize + DeserializeOwned + Debug> {
+    db: &'a Database<Clock, T>,
+    entries: TableEntries<'a, T>,
+    tx_id: TxID,
+}
+
+impl<'a, Clock: LogicalClock, T: Sync + Send + Clone + Serialize + DeserializeOwned + Debug + 'static> LazyScanCursor<'a, Clock, T> {
+    pub fn new(
+        db: &'a Database<Clock, T>,
+        tx_id: TxID,
+        table_id: u64,
+    ) -> Result<LazyScanCursor<'a, Clock, T>> {
+        db.record_table_scan(tx_id, table_id)?;
+        Ok(Self {
+            db,
+            entries: db.table_entries(table_id),
+            tx_id,
+        })
+    }
+}
+
+impl<'a, Clock: LogicalClock, T: Sync + Send + Clone + Serialize + DeserializeOwned + Debug + 'static> Iterator for LazyScanCursor<'a, Clock, T> {
+    type Item = Result<Row<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entry in self.entries.by_ref() {
+            match self.db.read_entry(self.tx_id, &entry) {
+                Ok(Some(row)) => return Some(Ok(row)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
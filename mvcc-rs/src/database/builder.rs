@@ -0,0 +1,99 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use crate::clock::LogicalClock;
+use crate::database::{Database, DatabaseConfig, IsolationLevel};
+use crate::persistent_storage::{Noop, Storage};
+
+/// A builder for a database with settings other than the defaults.
+///
+/// The clock defaults to `Clock::default()` and the storage to `Noop`.
+#[derive(Debug)]
+pub struct DatabaseBuilder<
+    Clock: LogicalClock,
+    T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned,
+> {
+    clock: Clock,
+    storage: Box<dyn Storage<T>>,
+    config: DatabaseConfig,
+}
+
+impl<
+        Clock: LogicalClock + Default,
+        T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + 'static,
+    > DatabaseBuilder<Clock, T>
+{
+    pub fn new() -> Self {
+        Self {
+            clock: Clock::default(),
+            storage: Box::new(Noop),
+            config: DatabaseConfig::default(),
+        }
+    }
+}
+
+impl<
+        Clock: LogicalClock + Default,
+        T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + 'static,
+    > Default for DatabaseBuilder<Clock, T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<
+        Clock: LogicalClock,
+        T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + 'static,
+    > DatabaseBuilder<Clock, T>
+{
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn storage(mut self, storage: impl Storage<T> + 'static) -> Self {
+        self.storage = Box::new(storage);
+        self
+    }
+
+    /// Sets the maximum number of rows visited by the garbage collection
+    /// pass that runs on commit, see `Database::set_gc_batch_size`.
+    pub fn gc_batch_size(mut self, gc_batch_size: usize) -> Self {
+        self.config.gc_batch_size = gc_batch_size;
+        self
+    }
+
+    /// Sets the isolation level of transactions started with `begin_tx`.
+    pub fn isolation_level(mut self, isolation_level: IsolationLevel) -> Self {
+        self.config.isolation_level = isolation_level;
+        self
+    }
+
+    /// Sets the number of versions above which a row is garbage collected
+    /// right away when a new version is added to it.
+    pub fn max_version_chain_length(mut self, max_version_chain_length: usize) -> Self {
+        self.config.max_version_chain_length = Some(max_version_chain_length);
+        self
+    }
+
+    /// Sets the age above which active transactions are killed when a new
+    /// transaction begins, see `Database::kill_stale_transactions`.
+    pub fn max_tx_age(mut self, max_tx_age: Duration) -> Self {
+        self.config.max_tx_age = Some(max_tx_age);
+        self
+    }
+
+    /// Sets the maximum number of recent commits kept for validating
+    /// serializable transactions, see `DatabaseConfig::recent_commits_window`.
+    pub fn recent_commits_window(mut self, recent_commits_window: usize) -> Self {
+        self.config.recent_commits_window = recent_commits_window;
+        self
+    }
+
+    pub fn build(self) -> Database<Clock, T> {
+        Database::with_config(self.clock, self.storage, self.config)
+    }
+}
@@ -0,0 +1,119 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+use crate::clock::LogicalClock;
+use crate::database::{Database, Result, Row, RowID, TxID};
+use crate::errors::DatabaseError;
+
+/// Statistics about a CSV import.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    /// The number of rows inserted.
+    pub rows_inserted: u64,
+    /// The number of lines skipped, because they were malformed or the row
+    /// already exists.
+    pub rows_skipped: u64,
+}
+
+impl<
+        Clock: LogicalClock,
+        T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + FromStr + 'static,
+    > Database<Clock, T>
+{
+    /// Inserts the rows of `csv` to the table `table_id` within the
+    /// transaction `tx_id`.
+    ///
+    /// Every line has the form `id,data`, where `id` is the row ID within
+    /// the table and `data` is parsed with `FromStr`. Lines of the form
+    /// `table_id,row_id,data` written by `export_csv` are accepted as well,
+    /// and their table ID is ignored. A row ID is either an integer key or
+    /// a raw key in hex prefixed with `0x`. Lines that cannot be parsed,
+    /// including a header line, are skipped, as are rows that already exist.
+    /// This is meant for loading test data, not as a general purpose import.
+    pub fn import_csv(&self, tx_id: TxID, table_id: u64, csv: &str) -> Result<ImportStats> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(csv.as_bytes());
+        let mut stats = ImportStats::default();
+        for record in reader.records() {
+            let row = record.ok().and_then(|record| {
+                let (id, data) = match record.len() {
+                    2 => (&record[0], &record[1]),
+                    3 => (&record[1], &record[2]),
+                    _ => return None,
+                };
+                Some(Row {
+                    id: parse_row_id(table_id, id.trim())?,
+                    data: data.parse().ok()?,
+                })
+            });
+            let Some(row) = row else {
+                stats.rows_skipped += 1;
+                continue;
+            };
+            let (_, inserted) = self.get_or_insert(tx_id, row)?;
+            if inserted {
+                stats.rows_inserted += 1;
+            } else {
+                stats.rows_skipped += 1;
+            }
+        }
+        tracing::trace!("IMPORT    {stats:?}");
+        Ok(stats)
+    }
+}
+
+impl<
+        Clock: LogicalClock,
+        T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + Display + 'static,
+    > Database<Clock, T>
+{
+    /// Returns the rows of the table `table_id` that are visible to the
+    /// transaction `tx_id` as CSV text.
+    ///
+    /// The text starts with a `table_id,row_id,data` header, followed by the
+    /// rows in row ID order with their data formatted with `Display`. Keys
+    /// of row IDs not created with `RowID::from_u64` are written in hex with
+    /// a `0x` prefix. The text can be loaded back with `import_csv`.
+    pub fn export_csv(&self, tx_id: TxID, table_id: u64) -> Result<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .write_record(["table_id", "row_id", "data"])
+            .map_err(|e| DatabaseError::Io(e.to_string()))?;
+        for row in self.scan_rows_for_table(tx_id, table_id)? {
+            let row_id = match row.id.as_u64() {
+                Some(row_id) => row_id.to_string(),
+                None => row.id.key.iter().fold("0x".to_string(), |mut hex, b| {
+                    hex.push_str(&format!("{b:02x}"));
+                    hex
+                }),
+            };
+            writer
+                .write_record([table_id.to_string(), row_id, row.data.to_string()])
+                .map_err(|e| DatabaseError::Io(e.to_string()))?;
+        }
+        let csv = writer
+            .into_inner()
+            .map_err(|e| DatabaseError::Io(e.to_string()))?;
+        String::from_utf8(csv).map_err(|e| DatabaseError::Io(e.to_string()))
+    }
+}
+
+/// Parses a row ID written by `export_csv`, which is either an integer key
+/// or a raw key in hex prefixed with `0x`.
+fn parse_row_id(table_id: u64, id: &str) -> Option<RowID> {
+    let Some(hex) = id.strip_prefix("0x") else {
+        return Some(RowID::from_u64(table_id, id.parse().ok()?));
+    };
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    let key = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(RowID::new(table_id, key))
+}
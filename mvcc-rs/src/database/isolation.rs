@@ -0,0 +1,210 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+
+use crate::clock::LogicalClock;
+use crate::database::{Database, IsolationLevel, Result, Row, RowID};
+use crate::errors::DatabaseError;
+
+/// A concurrency anomaly that `Database::assert_isolation` reproduces.
+///
+/// Every scenario interleaves the operations of two transactions, T1 and
+/// T2, which are started with the isolation level of the database. The
+/// rows a scenario starts from are written and committed before T1 begins.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IsolationScenario<T> {
+    /// T1 updates the row `id` from `initial` to `update` without
+    /// committing, and T2 reads the row. T2 must see `initial` at every
+    /// isolation level.
+    DirtyRead { id: RowID, initial: T, update: T },
+    /// T1 reads the row `id` twice, and T2 updates it from `initial` to
+    /// `update` and commits in between. The second read sees `update` only
+    /// with read committed, and T1 fails to commit with repeatable read and
+    /// serializable isolation.
+    FuzzyRead { id: RowID, initial: T, update: T },
+    /// T1 updates the row `id` to `first` and T2 updates it to `second`
+    /// before T1 commits. T2 must fail with a write-write conflict at every
+    /// isolation level, so that `first` is not overwritten.
+    LostUpdate {
+        id: RowID,
+        initial: T,
+        first: T,
+        second: T,
+    },
+    /// T1 scans the table of `row`, and T2 inserts `row` and commits before
+    /// T1 scans it again. The second scan sees `row` only with read
+    /// committed, and T1 fails to commit with serializable isolation. The
+    /// row must not exist yet.
+    PhantomRead { row: Row<T> },
+    /// T1 and T2 both read the rows `a` and `b`, which start out as
+    /// `initial`. T1 then updates `a` and T2 updates `b` to `update`. T2
+    /// fails to commit with repeatable read and serializable isolation.
+    WriteSkew {
+        a: RowID,
+        b: RowID,
+        initial: T,
+        update: T,
+    },
+}
+
+impl<
+        Clock: LogicalClock,
+        T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + PartialEq + 'static,
+    > Database<Clock, T>
+{
+    /// Runs `scenario` and asserts that the database prevents or allows the
+    /// anomaly as its configured isolation level promises.
+    ///
+    /// This is a test helper, so a violated guarantee panics. Errors that
+    /// are not part of the scenario are returned. Use a `SimClock` to make
+    /// the timestamps of the scenario deterministic.
+    pub fn assert_isolation(&self, scenario: IsolationScenario<T>) -> Result<()> {
+        let level = self.config.isolation_level;
+        tracing::trace!("ISOLATION {level:?} {scenario:?}");
+        match scenario {
+            IsolationScenario::DirtyRead {
+                id,
+                initial,
+                update,
+            } => {
+                self.write_initial_rows(&[(&id, &initial)])?;
+                let tx1 = self.begin_tx();
+                let tx2 = self.begin_tx();
+                self.update(tx1, row(&id, &update))?;
+                assert_eq!(
+                    Some(row(&id, &initial)),
+                    self.read(tx2, id.clone())?,
+                    "dirty read with {level:?}"
+                );
+                self.rollback_tx(tx1);
+                self.commit_tx(tx2)?;
+            }
+            IsolationScenario::FuzzyRead {
+                id,
+                initial,
+                update,
+            } => {
+                self.write_initial_rows(&[(&id, &initial)])?;
+                let tx1 = self.begin_tx();
+                assert_eq!(Some(row(&id, &initial)), self.read(tx1, id.clone())?);
+                let tx2 = self.begin_tx();
+                self.update(tx2, row(&id, &update))?;
+                self.commit_tx(tx2)?;
+                let expected = match level {
+                    IsolationLevel::ReadCommitted => &update,
+                    _ => &initial,
+                };
+                assert_eq!(
+                    Some(row(&id, expected)),
+                    self.read(tx1, id.clone())?,
+                    "fuzzy read with {level:?}"
+                );
+                let expected = match level {
+                    IsolationLevel::RepeatableRead | IsolationLevel::Serializable => {
+                        Err(DatabaseError::WriteSkewConflict)
+                    }
+                    _ => Ok(()),
+                };
+                assert_eq!(expected, self.commit_tx(tx1), "fuzzy read with {level:?}");
+            }
+            IsolationScenario::LostUpdate {
+                id,
+                initial,
+                first,
+                second,
+            } => {
+                self.write_initial_rows(&[(&id, &initial)])?;
+                let tx1 = self.begin_tx();
+                let tx2 = self.begin_tx();
+                self.update(tx1, row(&id, &first))?;
+                assert_eq!(
+                    Err(DatabaseError::WriteWriteConflict { with_tx_id: tx1 }),
+                    self.update(tx2, row(&id, &second)),
+                    "lost update with {level:?}"
+                );
+                self.commit_tx(tx1)?;
+                assert_eq!(Err(DatabaseError::TxTerminated), self.commit_tx(tx2));
+                self.assert_committed(&id, &first)?;
+            }
+            IsolationScenario::PhantomRead { row } => {
+                let table_id = row.id.table_id;
+                let tx1 = self.begin_tx();
+                let before = self.scan_rows_for_table(tx1, table_id)?;
+                assert!(!before.contains(&row), "row {:?} already exists", row.id);
+                let tx2 = self.begin_tx();
+                self.insert(tx2, row.clone())?;
+                self.commit_tx(tx2)?;
+                let after = self.scan_rows_for_table(tx1, table_id)?;
+                assert_eq!(
+                    level == IsolationLevel::ReadCommitted,
+                    after.contains(&row),
+                    "phantom read with {level:?}"
+                );
+                let expected = match level {
+                    IsolationLevel::Serializable => Err(DatabaseError::PhantomRead),
+                    _ => Ok(()),
+                };
+                assert_eq!(expected, self.commit_tx(tx1), "phantom read with {level:?}");
+            }
+            IsolationScenario::WriteSkew {
+                a,
+                b,
+                initial,
+                update,
+            } => {
+                self.write_initial_rows(&[(&a, &initial), (&b, &initial)])?;
+                let tx1 = self.begin_tx();
+                let tx2 = self.begin_tx();
+                for tx in [tx1, tx2] {
+                    assert_eq!(Some(row(&a, &initial)), self.read(tx, a.clone())?);
+                    assert_eq!(Some(row(&b, &initial)), self.read(tx, b.clone())?);
+                }
+                self.update(tx1, row(&a, &update))?;
+                self.update(tx2, row(&b, &update))?;
+                self.commit_tx(tx1)?;
+                let expected = match level {
+                    IsolationLevel::RepeatableRead | IsolationLevel::Serializable => {
+                        Err(DatabaseError::WriteSkewConflict)
+                    }
+                    _ => Ok(()),
+                };
+                assert_eq!(expected, self.commit_tx(tx2), "write skew with {level:?}");
+                self.assert_committed(&a, &update)?;
+                if expected.is_err() {
+                    self.assert_committed(&b, &initial)?;
+                } else {
+                    self.assert_committed(&b, &update)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the rows a scenario starts from in a transaction of its own.
+    fn write_initial_rows(&self, rows: &[(&RowID, &T)]) -> Result<()> {
+        let tx_id = self.begin_tx();
+        for (id, data) in rows {
+            if let Err(e) = self.upsert(tx_id, row(id, data)) {
+                self.rollback_tx(tx_id);
+                return Err(e);
+            }
+        }
+        self.commit_tx(tx_id)
+    }
+
+    /// Asserts that `data` is the latest committed version of the row `id`.
+    fn assert_committed(&self, id: &RowID, data: &T) -> Result<()> {
+        let tx_id = self.begin_read_only_tx();
+        let committed = self.read(tx_id, id.clone());
+        self.rollback_tx(tx_id);
+        assert_eq!(Some(row(id, data)), committed?);
+        Ok(())
+    }
+}
+
+fn row<T: Clone>(id: &RowID, data: &T) -> Row<T> {
+    Row {
+        id: id.clone(),
+        data: data.clone(),
+    }
+}
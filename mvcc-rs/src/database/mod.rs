@@ -1,22 +1,86 @@
 use crate::clock::LogicalClock;
+use crate::cursor::LazyScanCursor;
 use crate::errors::DatabaseError;
-use crate::persistent_storage::Storage;
+use crate::persistent_storage::{CompactionStats, Noop, Storage};
+use crate::table::Table;
+use crossbeam_skiplist::map::{Entry, Range};
 use crossbeam_skiplist::{SkipMap, SkipSet};
+use futures::channel::mpsc;
+use futures::Stream;
+use parking_lot::{Mutex, RwLock, RwLockWriteGuard};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::RwLock;
+use std::future::Future;
+use std::ops::{Bound, Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 pub type Result<T> = std::result::Result<T, DatabaseError>;
 
+/// The default maximum number of rows visited by an incremental garbage collection pass.
+pub const DEFAULT_GC_BATCH_SIZE: usize = 64;
+
+/// The default number of recent commits that serializable transactions are
+/// validated against.
+pub const DEFAULT_RECENT_COMMITS_WINDOW: usize = 1024;
+
+/// The number of change events buffered for a subscriber before it lags behind.
+pub const SUBSCRIBER_BUFFER_SIZE: usize = 1024;
+
+/// The number of most recent versions of every row kept by log compaction.
+pub const COMPACTION_KEPT_VERSIONS: usize = 2;
+
 #[cfg(test)]
 mod tests;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
+mod builder;
+#[cfg(feature = "csv_import")]
+mod csv_import;
+mod isolation;
+mod retry;
+
+pub use builder::DatabaseBuilder;
+#[cfg(feature = "csv_import")]
+pub use csv_import::ImportStats;
+pub use isolation::IsolationScenario;
+pub use retry::RetryPolicy;
+
+/// The ID of a row, which is the ID of its table and its key within the table.
+///
+/// Rows are ordered by table ID, and within a table by comparing the keys
+/// byte by byte, so composite keys can be encoded with order-preserving
+/// encodings.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
 pub struct RowID {
     pub table_id: u64,
-    pub row_id: u64,
+    pub key: Vec<u8>,
+}
+
+impl RowID {
+    pub fn new(table_id: u64, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            table_id,
+            key: key.into(),
+        }
+    }
+
+    /// Creates a row ID with an integer key, which is encoded in big-endian
+    /// bytes so that the rows are ordered by `row_id`.
+    pub fn from_u64(table_id: u64, row_id: u64) -> Self {
+        Self::new(table_id, row_id.to_be_bytes())
+    }
+
+    /// Returns the integer key of a row ID created with `from_u64`, or `None`
+    /// if the key is not eight bytes long.
+    pub fn as_u64(&self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.key.as_slice().try_into().ok()?))
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -26,30 +90,269 @@ pub struct Row<T> {
     pub data: T,
 }
 
+/// The outcome of an upsert.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpsertResult {
+    Inserted,
+    Updated,
+}
+
+/// The outcome of a compare-and-swap.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CasResult<T> {
+    /// The row had the expected value and was updated.
+    Swapped,
+    /// The row does not exist.
+    NotFound,
+    /// The row has a different value, which is returned.
+    Mismatch(Row<T>),
+}
+
+/// The outcome of a conditional update.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConditionalUpdateResult<T> {
+    /// The row matched the predicate and was updated.
+    Updated,
+    /// The row does not match the predicate, and its current value is returned.
+    PredicateFailed(Row<T>),
+    /// The row does not exist.
+    NotFound,
+}
+
+/// A function extracting the field to sort rows by from a row.
+pub type FieldExtractor<T> = Box<dyn Fn(&Row<T>) -> Cow<'_, str>>;
+
+/// The order in which `Database::scan_rows_ordered` returns rows.
+pub enum ScanOrder<T> {
+    /// In ascending row ID order, which is the order rows are stored in.
+    AscendingByRowId,
+    /// In descending row ID order.
+    DescendingByRowId,
+    /// In ascending order of the field extracted from each row. Rows with
+    /// equal fields stay in ascending row ID order.
+    ByDataField(FieldExtractor<T>),
+}
+
+impl<T> Debug for ScanOrder<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AscendingByRowId => write!(f, "AscendingByRowId"),
+            Self::DescendingByRowId => write!(f, "DescendingByRowId"),
+            Self::ByDataField(_) => write!(f, "ByDataField(..)"),
+        }
+    }
+}
+
+/// The isolation level of a transaction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IsolationLevel {
+    /// Every read sees the latest committed version at the time of the read.
+    ReadCommitted,
+    /// Reads see the snapshot taken when the transaction began, and rows
+    /// read by the transaction must not have been changed by the time it
+    /// commits. Phantoms are not detected.
+    RepeatableRead,
+    /// Reads see the snapshot taken when the transaction began.
+    #[default]
+    SnapshotIsolation,
+    /// Like snapshot isolation, but the transaction fails to commit if the
+    /// rows it read or the tables it scanned were changed concurrently.
+    Serializable,
+}
+
 /// A row version.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct RowVersion<T> {
     begin: TxTimestampOrID,
     end: Option<TxTimestampOrID>,
-    row: Row<T>,
+    pub(crate) row: Row<T>,
+}
+
+/// The version list of a row.
+///
+/// The versions are reference counted so that databases can share them. A
+/// mutable access makes a private copy of the versions if they are shared,
+/// so sharing is copy-on-write.
+#[derive(Clone, Debug)]
+pub(crate) struct RowManager<T> {
+    versions: Arc<Vec<RowVersion<T>>>,
+}
+
+impl<T> RowManager<T> {
+    fn new() -> Self {
+        Self {
+            versions: Arc::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> From<Vec<RowVersion<T>>> for RowManager<T> {
+    fn from(versions: Vec<RowVersion<T>>) -> Self {
+        Self {
+            versions: Arc::new(versions),
+        }
+    }
+}
+
+impl<T> Deref for RowManager<T> {
+    type Target = Vec<RowVersion<T>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.versions
+    }
+}
+
+impl<T: Clone> DerefMut for RowManager<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        Arc::make_mut(&mut self.versions)
+    }
+}
+
+/// The ID of a transaction.
+///
+/// Transaction IDs are displayed as `Tx#<id>`, so that they stand out from
+/// row IDs and timestamps in logs and error messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TxID(pub u64);
+
+impl std::fmt::Display for TxID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tx#{}", self.0)
+    }
+}
+
+impl Deref for TxID {
+    type Target = u64;
+
+    fn deref(&self) -> &u64 {
+        &self.0
+    }
+}
+
+impl From<u64> for TxID {
+    fn from(tx_id: u64) -> Self {
+        Self(tx_id)
+    }
 }
 
-pub type TxID = u64;
+impl From<TxID> for u64 {
+    fn from(tx_id: TxID) -> Self {
+        tx_id.0
+    }
+}
+
+/// A savepoint ID, unique within a transaction.
+pub type SavepointID = u64;
+
+/// The range of row IDs of a table.
+pub(crate) type TableRange = (Bound<RowID>, Bound<RowID>);
+
+/// The row versions of a table, in row ID order.
+pub(crate) type TableEntries<'a, T> = Range<'a, RowID, TableRange, RowID, RwLock<RowManager<T>>>;
 
 /// A log record contains all the versions inserted and deleted by a transaction.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LogRecord<T> {
-    pub(crate) tx_timestamp: TxID,
-    row_versions: Vec<RowVersion<T>>,
+    pub(crate) tx_timestamp: u64,
+    /// The begin timestamp of the transaction.
+    #[serde(default)]
+    begin_ts: u64,
+    /// The wall-clock duration of the transaction in microseconds.
+    #[serde(default)]
+    duration_us: u64,
+    /// An opaque token set by the application for causality tracking.
+    #[serde(default)]
+    causality_token: Option<[u8; 16]>,
+    pub(crate) row_versions: Vec<RowVersion<T>>,
 }
 
 impl<T> LogRecord<T> {
-    fn new(tx_timestamp: TxID) -> Self {
+    fn new(
+        tx_timestamp: u64,
+        begin_ts: u64,
+        duration_us: u64,
+        causality_token: Option<[u8; 16]>,
+    ) -> Self {
         Self {
             tx_timestamp,
+            begin_ts,
+            duration_us,
+            causality_token,
             row_versions: Vec::new(),
         }
     }
+
+    /// Returns the commit timestamp of the transaction.
+    pub fn tx_timestamp(&self) -> u64 {
+        self.tx_timestamp
+    }
+
+    /// Returns the begin timestamp of the transaction.
+    pub fn begin_ts(&self) -> u64 {
+        self.begin_ts
+    }
+
+    /// Returns the wall-clock duration of the transaction in microseconds.
+    pub fn duration_us(&self) -> u64 {
+        self.duration_us
+    }
+
+    /// Returns the causality token of the transaction, if the application set one.
+    pub fn causality_token(&self) -> Option<[u8; 16]> {
+        self.causality_token
+    }
+
+    /// Returns the number of row versions inserted or deleted by the transaction.
+    pub fn row_count(&self) -> usize {
+        self.row_versions.len()
+    }
+
+    /// Drops all but the `COMPACTION_KEPT_VERSIONS` most recent versions of
+    /// every row from `records`, which must be in log order, and then the
+    /// records left without versions.
+    ///
+    /// Recovery only needs the latest version of every row to rebuild the
+    /// rows, because a replayed version that ends a row version missing from
+    /// the log is inserted as it is. The most recent update is kept in full.
+    pub(crate) fn compact(records: Vec<LogRecord<T>>) -> (Vec<LogRecord<T>>, CompactionStats) {
+        let mut stats = CompactionStats {
+            records_before: records.len(),
+            ..Default::default()
+        };
+        let mut kept_versions: HashMap<RowID, usize> = HashMap::new();
+        let mut compacted = Vec::new();
+        for mut record in records.into_iter().rev() {
+            let versions = record.row_versions.len();
+            let mut row_versions: Vec<_> = std::mem::take(&mut record.row_versions)
+                .into_iter()
+                .rev()
+                .filter(|rv| {
+                    let kept = kept_versions.entry(rv.row.id.clone()).or_insert(0);
+                    *kept += 1;
+                    *kept <= COMPACTION_KEPT_VERSIONS
+                })
+                .collect();
+            stats.versions_dropped += versions - row_versions.len();
+            if !row_versions.is_empty() {
+                row_versions.reverse();
+                record.row_versions = row_versions;
+                compacted.push(record);
+            }
+        }
+        compacted.reverse();
+        stats.records_after = compacted.len();
+        (compacted, stats)
+    }
+}
+
+/// A snapshot of the rows of a database at a point in time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    /// The timestamp the snapshot was taken at, which is higher than the
+    /// commit timestamp of every row version in it.
+    pub(crate) timestamp: u64,
+    pub(crate) rows: Vec<Row<T>>,
 }
 
 /// A transaction timestamp or ID.
@@ -59,7 +362,7 @@ impl<T> LogRecord<T> {
 /// transaction ID in the `begin` and `end` fields. After a transaction commits,
 /// versions switch to tracking timestamps.
 #[derive(Clone, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
-enum TxTimestampOrID {
+pub enum TxTimestampOrID {
     Timestamp(u64),
     TxID(TxID),
 }
@@ -70,28 +373,69 @@ pub struct Transaction {
     /// The state of the transaction.
     state: AtomicTransactionState,
     /// The transaction ID.
-    tx_id: u64,
+    tx_id: TxID,
     /// The transaction begin timestamp.
     begin_ts: u64,
+    /// The isolation level of the transaction.
+    isolation_level: IsolationLevel,
+    /// Whether the transaction is read-only.
+    read_only: bool,
     /// The transaction write set.
-    #[serde(with = "skipset_rowid")]
+    #[serde(with = "skipset")]
     write_set: SkipSet<RowID>,
     /// The transaction read set.
-    #[serde(with = "skipset_rowid")]
+    #[serde(with = "skipset")]
     read_set: SkipSet<RowID>,
+    /// The transaction predicate set, i.e. the IDs of the tables the transaction has scanned.
+    #[serde(with = "skipset")]
+    predicate_set: SkipSet<u64>,
+    /// The rows the transaction added a lock marker to, see `Database::lock_row`.
+    #[serde(with = "skipset")]
+    lock_set: SkipSet<RowID>,
+    /// The savepoints of the transaction, indexed by savepoint ID.
+    savepoints: Vec<SavepointState>,
+    /// The wall-clock time the transaction began at.
+    #[serde(skip, default = "Instant::now")]
+    begin_wall_time: Instant,
+    /// An opaque token set by the application for causality tracking.
+    causality_token: Option<[u8; 16]>,
+}
+
+/// The state of a transaction captured by a savepoint.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SavepointState {
+    /// The transaction write set at the time of the savepoint.
+    write_set: Vec<RowID>,
+    /// The version chains of the rows in the write set.
+    rows: Vec<(RowID, SavepointRowState)>,
 }
 
-mod skipset_rowid {
+/// The versions of a row owned by a transaction at the time of a savepoint.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct SavepointRowState {
+    /// Whether each version inserted by the transaction, in insertion order,
+    /// was deleted by the transaction. The length is the number of versions
+    /// the transaction had inserted.
+    own_versions_deleted: Vec<bool>,
+    /// Whether the transaction had deleted a version inserted by another transaction.
+    foreign_version_deleted: bool,
+}
+
+mod skipset {
     use super::*;
     use serde::{de, ser, ser::SerializeSeq};
+    use std::marker::PhantomData;
 
-    struct SkipSetDeserializer;
+    struct SkipSetDeserializer<V>(PhantomData<V>);
 
-    impl<'de> serde::de::Visitor<'de> for SkipSetDeserializer {
-        type Value = SkipSet<RowID>;
+    impl<'de, V> serde::de::Visitor<'de> for SkipSetDeserializer<V>
+    where
+        V: Deserialize<'de> + Ord + Send + 'static,
+    {
+        type Value = SkipSet<V>;
 
         fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            formatter.write_str("SkipSet<RowID> key value sequence.")
+            formatter.write_str("SkipSet key value sequence.")
         }
 
         fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error>
@@ -107,8 +451,8 @@ mod skipset_rowid {
         }
     }
 
-    pub fn serialize<S: ser::Serializer>(
-        value: &SkipSet<RowID>,
+    pub fn serialize<S: ser::Serializer, V: Serialize + Ord + Send + 'static>(
+        value: &SkipSet<V>,
         ser: S,
     ) -> std::result::Result<S::Ok, S::Error> {
         let mut set = ser.serialize_seq(Some(value.len()))?;
@@ -118,21 +462,49 @@ mod skipset_rowid {
         set.end()
     }
 
-    pub fn deserialize<'de, D: de::Deserializer<'de>>(
+    pub fn deserialize<
+        'de,
+        D: de::Deserializer<'de>,
+        V: Deserialize<'de> + Ord + Send + 'static,
+    >(
         de: D,
-    ) -> std::result::Result<SkipSet<RowID>, D::Error> {
-        de.deserialize_seq(SkipSetDeserializer)
+    ) -> std::result::Result<SkipSet<V>, D::Error> {
+        de.deserialize_seq(SkipSetDeserializer(PhantomData))
     }
 }
 
 impl Transaction {
-    fn new(tx_id: u64, begin_ts: u64) -> Transaction {
+    fn new(
+        tx_id: TxID,
+        begin_ts: u64,
+        isolation_level: IsolationLevel,
+        read_only: bool,
+    ) -> Transaction {
         Transaction {
             state: TransactionState::Active.into(),
             tx_id,
             begin_ts,
+            isolation_level,
+            read_only,
             write_set: SkipSet::new(),
             read_set: SkipSet::new(),
+            predicate_set: SkipSet::new(),
+            lock_set: SkipSet::new(),
+            savepoints: Vec::new(),
+            begin_wall_time: Instant::now(),
+            causality_token: None,
+        }
+    }
+
+    /// Checks that the owner of the transaction can still use it, which
+    /// fails with `TransactionKilled` once it has been killed.
+    fn check_active(&self) -> Result<()> {
+        match self.state.load() {
+            TransactionState::Killed => Err(DatabaseError::TransactionKilled),
+            state => {
+                assert_eq!(state, TransactionState::Active);
+                Ok(())
+            }
         }
     }
 
@@ -140,6 +512,10 @@ impl Transaction {
         self.read_set.insert(id);
     }
 
+    fn insert_to_predicate_set(&self, table_id: u64) {
+        self.predicate_set.insert(table_id);
+    }
+
     fn insert_to_write_set(&mut self, id: RowID) {
         self.write_set.insert(id);
     }
@@ -156,24 +532,410 @@ impl std::fmt::Display for Transaction {
             // FIXME: I'm sorry, we obviously shouldn't be cloning here.
             self.write_set
                 .iter()
-                .map(|v| *v.value())
+                .map(|v| v.value().clone())
                 .collect::<Vec<RowID>>(),
             self.read_set
                 .iter()
-                .map(|v| *v.value())
+                .map(|v| v.value().clone())
                 .collect::<Vec<RowID>>()
         )
     }
 }
 
+/// A read-only view of a transaction, for monitoring and debugging.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionInfo {
+    pub tx_id: TxID,
+    pub begin_ts: u64,
+    pub write_set_size: usize,
+    pub read_set_size: usize,
+    pub state: TransactionState,
+}
+
+impl From<&Transaction> for TransactionInfo {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            tx_id: tx.tx_id,
+            begin_ts: tx.begin_ts,
+            write_set_size: tx.write_set.len(),
+            read_set_size: tx.read_set.len(),
+            state: tx.state.load(),
+        }
+    }
+}
+
+/// A row version, as returned by `Database::iter_versions`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionView<T> {
+    pub begin: TxTimestampOrID,
+    pub end: Option<TxTimestampOrID>,
+    pub data: T,
+}
+
+/// The kind of change a committed transaction made to a row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// An event delivered to the subscribers of a table, see `Database::subscribe`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChangeEvent<T> {
+    /// A committed transaction changed the row `row_id`. `new_row` is the
+    /// row after the change, or `None` if it was deleted.
+    Change {
+        table_id: u64,
+        row_id: RowID,
+        kind: ChangeKind,
+        new_row: Option<Row<T>>,
+    },
+    /// The subscriber fell behind and `n` events were dropped.
+    Lagged(u64),
+}
+
+/// A subscriber to the changes of a table.
+#[derive(Debug)]
+struct Subscriber<T> {
+    sender: mpsc::Sender<ChangeEvent<T>>,
+    /// The number of events dropped since the last event was delivered.
+    lagged: u64,
+}
+
+impl<T> Subscriber<T> {
+    /// Delivers `event`, or counts it as dropped if the subscriber is full.
+    /// Returns false if the subscriber has gone away.
+    fn send(&mut self, event: ChangeEvent<T>) -> bool {
+        if self.lagged > 0 {
+            match self.sender.try_send(ChangeEvent::Lagged(self.lagged)) {
+                Ok(()) => self.lagged = 0,
+                Err(e) if e.is_disconnected() => return false,
+                Err(_) => {
+                    self.lagged += 1;
+                    return true;
+                }
+            }
+        }
+        match self.sender.try_send(event) {
+            Ok(()) => true,
+            Err(e) if e.is_disconnected() => false,
+            Err(_) => {
+                self.lagged += 1;
+                true
+            }
+        }
+    }
+}
+
+/// The points in the life of a transaction at which hooks run, see
+/// `Database::install_hook`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HookEvent {
+    BeforeCommit,
+    AfterCommit,
+    BeforeRollback,
+    AfterRollback,
+}
+
+/// The transaction a hook runs for.
+#[derive(Debug)]
+pub struct HookContext<'a, T> {
+    pub tx_id: TxID,
+    /// The IDs of the rows written by the transaction.
+    pub write_set: &'a [RowID],
+    /// The log record of the transaction, for `AfterCommit` hooks of
+    /// read-write transactions.
+    pub log_record: Option<&'a LogRecord<T>>,
+}
+
+/// A function run at a `HookEvent`, see `Database::install_hook`.
+pub type Hook<T> = Box<dyn Fn(HookContext<'_, T>) -> Result<()> + Send + Sync>;
+
+/// The hooks installed for each event.
+struct Hooks<T> {
+    hooks: RwLock<HashMap<HookEvent, Vec<Hook<T>>>>,
+}
+
+impl<T> Hooks<T> {
+    fn new() -> Self {
+        Self {
+            hooks: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn install(&self, event: HookEvent, hook: Hook<T>) {
+        self.hooks.write().entry(event).or_default().push(hook);
+    }
+
+    fn has(&self, event: HookEvent) -> bool {
+        self.hooks.read().contains_key(&event)
+    }
+
+    /// Runs the hooks for `event` in the order they were installed, stopping
+    /// at the first one that fails.
+    fn run(
+        &self,
+        event: HookEvent,
+        tx_id: TxID,
+        write_set: &[RowID],
+        log_record: Option<&LogRecord<T>>,
+    ) -> Result<()> {
+        if let Some(hooks) = self.hooks.read().get(&event) {
+            for hook in hooks {
+                hook(HookContext {
+                    tx_id,
+                    write_set,
+                    log_record,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the hooks for an event that cannot be cancelled, logging the
+    /// errors of the hooks that fail.
+    fn notify(
+        &self,
+        event: HookEvent,
+        tx_id: TxID,
+        write_set: &[RowID],
+        log_record: Option<&LogRecord<T>>,
+    ) {
+        if let Some(hooks) = self.hooks.read().get(&event) {
+            for hook in hooks {
+                let context = HookContext {
+                    tx_id,
+                    write_set,
+                    log_record,
+                };
+                if let Err(e) = hook(context) {
+                    tracing::warn!("{event:?} hook of {tx_id} failed: {e}");
+                }
+            }
+        }
+    }
+}
+
+impl<T> Debug for Hooks<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hooks = self.hooks.read();
+        f.debug_map()
+            .entries(hooks.iter().map(|(event, hooks)| (event, hooks.len())))
+            .finish()
+    }
+}
+
+/// Why a row version is or isn't visible to a transaction, as returned by
+/// `Database::explain_visibility`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisibilityExplanation {
+    pub begin: TxTimestampOrID,
+    pub end: Option<TxTimestampOrID>,
+    pub is_visible: bool,
+    pub reason: String,
+}
+
+/// Statistics about the lengths of the row version chains in a database.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VersionChainStats {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub total_versions: usize,
+    pub total_rows: usize,
+}
+
+/// The number of row versions in a table, by state.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VersionCountStats {
+    /// The number of versions that have not ended.
+    pub current_versions: u64,
+    /// The number of versions ended by a committed transaction.
+    pub deleted_versions: u64,
+    /// The number of versions being ended by an uncommitted transaction.
+    pub in_flight_versions: u64,
+    /// The number of rows with at least one version.
+    pub total_rows: u64,
+}
+
+/// A handle to a prefetch started with `Database::prefetch`.
+///
+/// The handle is a future that resolves once the rows are loaded.
+#[derive(Debug)]
+#[must_use = "a prefetch handle does nothing unless polled or checked"]
+pub struct PrefetchHandle {
+    _private: (),
+}
+
+impl PrefetchHandle {
+    /// Returns whether the rows have been loaded.
+    pub fn is_complete(&self) -> bool {
+        // Rows are always in memory, so there is nothing to wait for.
+        true
+    }
+}
+
+impl Future for PrefetchHandle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+}
+
+/// The write and read sets of the transactions that committed while
+/// serializable transactions were in progress, by commit timestamp.
+#[derive(Debug, Default)]
+struct RecentCommits {
+    commits: BTreeMap<u64, (HashSet<RowID>, HashSet<RowID>)>,
+    /// The commit timestamp of the newest commit dropped to keep within the
+    /// window. Transactions that began before it cannot be validated.
+    evicted_ts: u64,
+}
+
+impl RecentCommits {
+    /// Returns true if a transaction that began at `begin_ts` read a row
+    /// written by a later commit, or wrote a row read by a later commit.
+    fn conflicts_with(
+        &self,
+        begin_ts: u64,
+        read_set: &[RowID],
+        write_set: &HashSet<RowID>,
+    ) -> bool {
+        begin_ts < self.evicted_ts
+            || self
+                .commits
+                .range((Bound::Excluded(begin_ts), Bound::Unbounded))
+                .any(|(_, (writes, reads))| {
+                    read_set.iter().any(|id| writes.contains(id))
+                        || write_set.iter().any(|id| reads.contains(id))
+                })
+    }
+
+    fn insert(&mut self, end_ts: u64, writes: HashSet<RowID>, reads: HashSet<RowID>) {
+        if !writes.is_empty() || !reads.is_empty() {
+            self.commits.insert(end_ts, (writes, reads));
+        }
+    }
+
+    /// Drops the commits that are not after `ts`.
+    fn retain_after(&mut self, ts: u64) {
+        self.commits.retain(|&end_ts, _| end_ts > ts);
+    }
+
+    /// Drops the oldest commits until at most `window` are left.
+    fn truncate(&mut self, window: usize) {
+        while self.commits.len() > window {
+            if let Some((end_ts, _)) = self.commits.pop_first() {
+                self.evicted_ts = self.evicted_ts.max(end_ts);
+            }
+        }
+    }
+}
+
+/// The tunable settings of a database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatabaseConfig {
+    /// The maximum number of rows visited by the garbage collection pass
+    /// that runs on commit. Zero disables automatic garbage collection.
+    pub gc_batch_size: usize,
+    /// The isolation level of transactions started with `begin_tx`.
+    pub isolation_level: IsolationLevel,
+    /// The number of versions above which a row is garbage collected right
+    /// away when a new version is added to it.
+    pub max_version_chain_length: Option<usize>,
+    /// The age above which active transactions are killed when a new
+    /// transaction begins.
+    pub max_tx_age: Option<Duration>,
+    /// The maximum number of recent commits kept for validating serializable
+    /// transactions. A serializable transaction that began before the oldest
+    /// of them fails to commit with `SerializationFailure`.
+    pub recent_commits_window: usize,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            gc_batch_size: DEFAULT_GC_BATCH_SIZE,
+            isolation_level: IsolationLevel::default(),
+            max_version_chain_length: None,
+            max_tx_age: None,
+            recent_commits_window: DEFAULT_RECENT_COMMITS_WINDOW,
+        }
+    }
+}
+
+/// Statistics about garbage collection.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GcStats {
+    /// The number of row versions dropped.
+    pub versions_dropped: u64,
+    /// The number of rows removed because all of their versions were dropped.
+    pub rows_removed: u64,
+    /// The time spent collecting garbage in microseconds.
+    pub duration_micros: u64,
+    /// The begin timestamp of the oldest active transaction, which is the GC watermark.
+    pub oldest_active_ts: u64,
+}
+
+/// The changes made by `Database::merge`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MergeStats {
+    /// The number of rows inserted from the other database.
+    pub rows_inserted: u64,
+    /// The number of rows skipped, because they have the same data in both
+    /// databases.
+    pub rows_unchanged: u64,
+    /// The IDs of the rows skipped, because they have different data in the
+    /// two databases.
+    pub conflicts: Vec<RowID>,
+}
+
+/// What `Database::merge_row` did with a row.
+enum MergeOutcome {
+    Inserted,
+    Unchanged,
+    Conflict(RowID),
+}
+
+/// The changes made by `Database::vacuum`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VacuumStats {
+    /// The number of row versions in memory before the vacuum.
+    pub versions_before: u64,
+    /// The number of row versions in memory after the vacuum.
+    pub versions_after: u64,
+    /// The number of bytes dropped from the transaction log, measured as the
+    /// bincode-encoded size of its records.
+    pub bytes_freed: u64,
+    /// The time spent vacuuming in microseconds.
+    pub duration_micros: u64,
+}
+
+/// The changes made by `Database::repair`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepairReport {
+    /// The number of versions removed because the transaction that inserted them never committed.
+    pub versions_removed: usize,
+    /// The number of versions whose deletion was undone because the deleting
+    /// transaction never committed.
+    pub deletions_undone: usize,
+    /// The number of rows removed because they were left without versions.
+    pub rows_removed: usize,
+}
+
 /// Transaction state.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-enum TransactionState {
+pub enum TransactionState {
     Active,
     Preparing,
     Aborted,
     Terminated,
     Committed(u64),
+    /// Killed by `Database::kill_transaction`, but not rolled back by its
+    /// owner yet.
+    Killed,
 }
 
 impl TransactionState {
@@ -183,6 +945,7 @@ impl TransactionState {
             TransactionState::Preparing => 1,
             TransactionState::Aborted => 2,
             TransactionState::Terminated => 3,
+            TransactionState::Killed => 4,
             TransactionState::Committed(ts) => {
                 // We only support 2*62 - 1 timestamps, because the extra bit
                 // is used to encode the type.
@@ -198,6 +961,7 @@ impl TransactionState {
             1 => TransactionState::Preparing,
             2 => TransactionState::Aborted,
             3 => TransactionState::Terminated,
+            4 => TransactionState::Killed,
             v if v & 0x8000_0000_0000_0000 != 0 => {
                 TransactionState::Committed(v & 0x7fff_ffff_ffff_ffff)
             }
@@ -241,6 +1005,7 @@ impl std::fmt::Display for TransactionState {
             TransactionState::Committed(ts) => write!(f, "Committed({ts})"),
             TransactionState::Aborted => write!(f, "Aborted"),
             TransactionState::Terminated => write!(f, "Terminated"),
+            TransactionState::Killed => write!(f, "Killed"),
         }
     }
 }
@@ -260,24 +1025,247 @@ pub struct Database<
     Clock: LogicalClock,
     T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned,
 > {
-    rows: SkipMap<RowID, RwLock<Vec<RowVersion<T>>>>,
+    rows: SkipMap<RowID, RwLock<RowManager<T>>>,
     txs: SkipMap<TxID, RwLock<Transaction>>,
     tx_ids: AtomicU64,
     clock: Clock,
-    storage: Storage,
+    storage: Box<dyn Storage<T>>,
+    /// The GC watermark at the time of the last garbage collection pass.
+    gc_epoch: AtomicU64,
+    /// The minimum begin timestamp of all active transactions.
+    gc_watermark: AtomicU64,
+    /// The watermark set with `set_gc_watermark`, or `u64::MAX` if unset.
+    external_gc_watermark: AtomicU64,
+    config: DatabaseConfig,
+    /// The last row visited by the previous garbage collection pass.
+    gc_cursor: Mutex<Option<RowID>>,
+    /// The garbage collection statistics accumulated over all passes.
+    gc_stats: Mutex<GcStats>,
+    /// The transactions that committed while serializable transactions were
+    /// in progress, which the serializable transactions are validated against.
+    recent_commits: Mutex<RecentCommits>,
+    /// The subscribers to the changes of each table.
+    subscribers: Mutex<HashMap<u64, Vec<Subscriber<T>>>>,
+    /// The transactions killed by `kill_transaction`, so that their owners
+    /// get `TransactionKilled` instead of an unknown transaction after the
+    /// rollback.
+    killed_txs: SkipSet<TxID>,
+    /// The number of committed live rows of each table, see `estimate_row_count`.
+    row_counts: SkipMap<u64, AtomicI64>,
+    /// The hooks installed with `install_hook`.
+    hooks: Hooks<T>,
 }
 
 impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + 'static>
     Database<Clock, T>
 {
-    /// Creates a new database.
-    pub fn new(clock: Clock, storage: Storage) -> Self {
+    /// Creates a new database with the default configuration.
+    ///
+    /// Use `DatabaseBuilder` to create a database with other settings.
+    pub fn new(clock: Clock, storage: impl Storage<T> + 'static) -> Self {
+        Self::with_config(clock, Box::new(storage), DatabaseConfig::default())
+    }
+
+    fn with_config(clock: Clock, storage: Box<dyn Storage<T>>, config: DatabaseConfig) -> Self {
         Self {
             rows: SkipMap::new(),
             txs: SkipMap::new(),
             tx_ids: AtomicU64::new(1), // let's reserve transaction 0 for special purposes
             clock,
             storage,
+            gc_epoch: AtomicU64::new(0),
+            gc_watermark: AtomicU64::new(0),
+            external_gc_watermark: AtomicU64::new(u64::MAX),
+            config,
+            gc_cursor: Mutex::new(None),
+            gc_stats: Mutex::new(GcStats::default()),
+            recent_commits: Mutex::new(RecentCommits::default()),
+            subscribers: Mutex::new(HashMap::new()),
+            killed_txs: SkipSet::new(),
+            row_counts: SkipMap::new(),
+            hooks: Hooks::new(),
+        }
+    }
+
+    /// Sets the maximum number of rows visited by the garbage collection
+    /// pass that runs on commit. A batch size of zero disables automatic
+    /// garbage collection.
+    pub fn set_gc_batch_size(&mut self, gc_batch_size: usize) {
+        self.config.gc_batch_size = gc_batch_size;
+    }
+
+    /// Sets a watermark that garbage collection must not go past, for
+    /// example the oldest begin timestamp of transactions on other nodes.
+    ///
+    /// Garbage collection keeps the versions visible to transactions that
+    /// began at or after the lower of this watermark and the oldest begin
+    /// timestamp of the local active transactions. Setting it to `u64::MAX`
+    /// removes the limit.
+    pub fn set_gc_watermark(&self, ts: u64) {
+        self.external_gc_watermark.store(ts, Ordering::SeqCst);
+    }
+
+    /// Creates a branch of the database.
+    ///
+    /// The branch starts out with the rows committed in this database at the
+    /// time of the call, but has its own transaction table and clock, which
+    /// starts where the clock of this database is. Changes made in either
+    /// database are not visible in the other one. The branch is not backed by
+    /// persistent storage.
+    pub fn branch(&self) -> Self
+    where
+        Clock: Default,
+    {
+        let clock = Clock::default();
+        clock.reset(self.get_timestamp());
+        let branch = Self::with_config(clock, Box::new(Noop), self.config.clone());
+        for entry in self.rows.iter() {
+            let row_versions = entry.value().read();
+            // Rows that only have committed versions are shared with the
+            // branch, and copied by whichever database writes them first.
+            if row_versions
+                .iter()
+                .all(|rv| rv.is_committed() && (!rv.is_deleted() || rv.end_timestamp().is_some()))
+            {
+                branch
+                    .rows
+                    .insert(entry.key().clone(), RwLock::new(row_versions.clone()));
+                continue;
+            }
+            let versions: Vec<RowVersion<T>> = row_versions
+                .iter()
+                .filter_map(|rv| {
+                    let begin = self.committed_timestamp(&rv.begin)?;
+                    let end = rv
+                        .end
+                        .as_ref()
+                        .and_then(|end| self.committed_timestamp(end));
+                    Some(RowVersion {
+                        begin,
+                        end,
+                        row: rv.row.clone(),
+                    })
+                })
+                .collect();
+            if !versions.is_empty() {
+                branch
+                    .rows
+                    .insert(entry.key().clone(), RwLock::new(versions.into()));
+            }
+        }
+        for entry in self.row_counts.iter() {
+            let count = entry.value().load(Ordering::Relaxed);
+            branch
+                .row_counts
+                .insert(*entry.key(), AtomicI64::new(count));
+        }
+        branch
+    }
+
+    /// Inserts the rows committed in `other` into this database in a single
+    /// transaction, for example to merge a branch back.
+    ///
+    /// Rows that are visible in this database are not merged. They are
+    /// skipped if their data is the same, as it is for rows that a branch
+    /// did not change, and reported in `MergeStats::conflicts` otherwise.
+    /// Rows deleted in `other` are not deleted here. Fails with
+    /// `WriteWriteConflict` if a transaction in progress updates or deletes
+    /// one of the rows, in which case nothing is merged.
+    pub fn merge(&self, other: Self) -> Result<MergeStats>
+    where
+        T: PartialEq,
+    {
+        let snapshot = other.export_snapshot()?;
+        let tx_id = self.begin_tx();
+        let mut stats = MergeStats::default();
+        for row in snapshot.rows {
+            match self.merge_row(tx_id, row) {
+                Ok(MergeOutcome::Inserted) => stats.rows_inserted += 1,
+                Ok(MergeOutcome::Unchanged) => stats.rows_unchanged += 1,
+                Ok(MergeOutcome::Conflict(id)) => stats.conflicts.push(id),
+                Err(e @ DatabaseError::WriteWriteConflict { .. }) => {
+                    // The conflicting write already rolled the transaction back.
+                    return Err(e);
+                }
+                Err(e) => {
+                    self.rollback_tx(tx_id);
+                    return Err(e);
+                }
+            }
+        }
+        self.commit_tx(tx_id)?;
+        tracing::debug!("MERGE     {stats:?}");
+        Ok(stats)
+    }
+
+    /// Inserts `row` within the transaction `tx_id` unless a version of it
+    /// is visible to the transaction, like `get_or_insert` does, but fails
+    /// on write-write conflicts.
+    fn merge_row(&self, tx_id: TxID, row: Row<T>) -> Result<MergeOutcome>
+    where
+        T: PartialEq,
+    {
+        let tx_entry = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let read_ts = self.read_ts(&tx_entry.value().read());
+        let id = row.id.clone();
+        loop {
+            let entry = self
+                .rows
+                .get_or_insert_with(id.clone(), || RwLock::new(RowManager::new()));
+            let mut row_versions = entry.value().write();
+            // The entry was removed because it became empty while we were
+            // waiting for the lock, so retry with a fresh one.
+            if entry.is_removed() {
+                continue;
+            }
+            let tx = tx_entry.value().read();
+            let visible = match self.visible_version_for_write(&tx, read_ts, &row_versions) {
+                Ok(visible) => visible,
+                Err(e @ DatabaseError::WriteWriteConflict { .. }) => {
+                    drop(row_versions);
+                    drop(entry);
+                    drop(tx);
+                    self.rollback_tx(tx_id);
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("mvcc_write_conflict_total").increment(1);
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(i) = visible {
+                let unchanged = row_versions[i].row.data == row.data;
+                tx.insert_to_read_set(id.clone());
+                return Ok(if unchanged {
+                    MergeOutcome::Unchanged
+                } else {
+                    MergeOutcome::Conflict(id)
+                });
+            }
+            tx.write_set.insert(id);
+            let row_version = RowVersion {
+                begin: TxTimestampOrID::TxID(tx_id),
+                end: None,
+                row,
+            };
+            self.insert_version_raw(&mut row_versions, row_version);
+            return Ok(MergeOutcome::Inserted);
+        }
+    }
+
+    /// Returns the commit timestamp of `ts_or_id` if it refers to a committed
+    /// transaction, and `None` otherwise.
+    fn committed_timestamp(&self, ts_or_id: &TxTimestampOrID) -> Option<TxTimestampOrID> {
+        match ts_or_id {
+            TxTimestampOrID::Timestamp(ts) => Some(TxTimestampOrID::Timestamp(*ts)),
+            TxTimestampOrID::TxID(tx_id) => {
+                match self.txs.get(tx_id)?.value().read().state.load() {
+                    TransactionState::Committed(ts) => Some(TxTimestampOrID::Timestamp(ts)),
+                    _ => None,
+                }
+            }
         }
     }
 
@@ -285,24 +1273,52 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
     fn get_begin_timestamp(&self, ts_or_id: &TxTimestampOrID) -> u64 {
         match ts_or_id {
             TxTimestampOrID::Timestamp(ts) => *ts,
-            TxTimestampOrID::TxID(tx_id) => {
-                self.txs
-                    .get(tx_id)
-                    .unwrap()
-                    .value()
-                    .read()
-                    .unwrap()
-                    .begin_ts
-            }
+            TxTimestampOrID::TxID(tx_id) => self.txs.get(tx_id).unwrap().value().read().begin_ts,
         }
     }
 
     /// Inserts a new row version into the database, while making sure that
     /// the row version is inserted in the correct order.
     fn insert_version(&self, id: RowID, row_version: RowVersion<T>) {
-        let versions = self.rows.get_or_insert_with(id, || RwLock::new(Vec::new()));
-        let mut versions = versions.value().write().unwrap();
-        self.insert_version_raw(&mut versions, row_version)
+        loop {
+            let entry = self
+                .rows
+                .get_or_insert_with(id.clone(), || RwLock::new(RowManager::new()));
+            let mut versions = entry.value().write();
+            // The entry was removed because it became empty while we were
+            // waiting for the lock, so retry with a fresh one.
+            if entry.is_removed() {
+                continue;
+            }
+            self.insert_version_raw(&mut versions, row_version);
+            let chain_length = versions.len();
+            drop(versions);
+            self.maybe_shorten_version_chain(&entry, chain_length);
+            return;
+        }
+    }
+
+    /// Garbage collects the row of `entry` right away if its `chain_length`
+    /// versions are more than `max_version_chain_length`. The row must not be
+    /// locked by the caller.
+    fn maybe_shorten_version_chain(
+        &self,
+        entry: &Entry<'_, RowID, RwLock<RowManager<T>>>,
+        chain_length: usize,
+    ) {
+        if self
+            .config
+            .max_version_chain_length
+            .is_some_and(|max| chain_length > max)
+        {
+            let watermark = self.update_gc_watermark();
+            let mut stats = GcStats {
+                oldest_active_ts: watermark,
+                ..Default::default()
+            };
+            self.drop_unused_versions_of(entry, watermark, &mut stats);
+            self.record_gc_stats(&stats);
+        }
     }
 
     /// Inserts a new row version into the internal data structure for versions,
@@ -317,7 +1333,7 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
         let position = versions
             .iter()
             .rposition(|v| {
-                self.get_begin_timestamp(&v.begin) < self.get_begin_timestamp(&row_version.begin)
+                self.get_begin_timestamp(&v.begin) <= self.get_begin_timestamp(&row_version.begin)
             })
             .map(|p| p + 1)
             .unwrap_or(0);
@@ -344,21 +1360,151 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
         let tx = self
             .txs
             .get(&tx_id)
-            .ok_or(DatabaseError::NoSuchTransactionID(tx_id))?;
-        let mut tx = tx.value().write().unwrap();
-        assert_eq!(tx.state, TransactionState::Active);
-        let id = row.id;
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let mut tx = tx.value().write();
+        tx.check_active()?;
+        if tx.read_only {
+            return Err(DatabaseError::ReadOnlyTransaction);
+        }
+        let id = row.id.clone();
         let row_version = RowVersion {
             begin: TxTimestampOrID::TxID(tx.tx_id),
             end: None,
             row,
         };
-        tx.insert_to_write_set(id);
+        tx.insert_to_write_set(id.clone());
         drop(tx);
         self.insert_version(id, row_version);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mvcc_insert_total").increment(1);
+        Ok(())
+    }
+
+    /// Inserts multiple rows into the database.
+    ///
+    /// This is equivalent to calling `insert` for every row, but the
+    /// transaction is looked up and locked only once. The transaction is
+    /// validated before any row is inserted, so on error no rows are inserted.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to insert the new rows.
+    /// * `rows` - the rows to be inserted.
+    ///
+    pub fn insert_batch(&self, tx_id: TxID, rows: Vec<Row<T>>) -> Result<()> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let mut tx = tx.value().write();
+        tx.check_active()?;
+        if tx.read_only {
+            return Err(DatabaseError::ReadOnlyTransaction);
+        }
+        for row in &rows {
+            tx.insert_to_write_set(row.id.clone());
+        }
+        drop(tx);
+        #[cfg(feature = "metrics")]
+        let inserted = rows.len() as u64;
+        for row in rows {
+            let row_version = RowVersion {
+                begin: TxTimestampOrID::TxID(tx_id),
+                end: None,
+                row,
+            };
+            self.insert_version(row_version.row.id.clone(), row_version);
+        }
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mvcc_insert_total").increment(inserted);
         Ok(())
     }
 
+    /// Returns the row with the ID of `row` if it is visible to the
+    /// transaction `tx_id`, or inserts `row` otherwise.
+    ///
+    /// The row is locked for the whole check-and-insert sequence, so no
+    /// other transaction can insert a version of the row in between.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to look up or insert the row.
+    /// * `row` - the row to insert if there is no visible row with the same ID.
+    ///
+    /// # Returns
+    ///
+    /// Returns the existing row and `false`, or the inserted row and `true`.
+    pub fn get_or_insert(&self, tx_id: TxID, row: Row<T>) -> Result<(Row<T>, bool)> {
+        let tx_entry = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let read_ts = {
+            let tx = tx_entry.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+            self.read_ts(&tx)
+        };
+        let id = row.id.clone();
+        loop {
+            let entry = self
+                .rows
+                .get_or_insert_with(id.clone(), || RwLock::new(RowManager::new()));
+            let mut row_versions = entry.value().write();
+            // The entry was removed because it became empty while we were
+            // waiting for the lock, so retry with a fresh one.
+            if entry.is_removed() {
+                continue;
+            }
+            let tx = tx_entry.value().read();
+            if let Some(existing) = self.visible_row(&tx, read_ts, &row_versions)? {
+                tx.insert_to_read_set(id);
+                return Ok((existing, false));
+            }
+            tx.write_set.insert(id);
+            let row_version = RowVersion {
+                begin: TxTimestampOrID::TxID(tx_id),
+                end: None,
+                row: row.clone(),
+            };
+            self.insert_version_raw(&mut row_versions, row_version);
+            let chain_length = row_versions.len();
+            drop(tx);
+            drop(row_versions);
+            self.maybe_shorten_version_chain(&entry, chain_length);
+            #[cfg(feature = "metrics")]
+            metrics::counter!("mvcc_insert_total").increment(1);
+            return Ok((row, true));
+        }
+    }
+
+    /// Copies the value of the row `src` to a new row `dst`.
+    ///
+    /// Like `get_or_insert`, the existence check and the insert of `dst`
+    /// happen while holding the row lock. `src` is recorded in the read set
+    /// and `dst` in the write set of the transaction.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the row was copied, and `false` if `src` does not
+    /// exist. Fails with `DatabaseError::RowAlreadyExists` if `dst` exists.
+    pub fn copy_row(&self, tx_id: TxID, src: RowID, dst: RowID) -> Result<bool> {
+        let Some(src_row) = self.read(tx_id, src)? else {
+            return Ok(false);
+        };
+        let row = Row {
+            id: dst.clone(),
+            data: src_row.data,
+        };
+        let (_, inserted) = self.get_or_insert(tx_id, row)?;
+        if !inserted {
+            return Err(DatabaseError::RowAlreadyExists(dst));
+        }
+        Ok(true)
+    }
+
     /// Updates a row in the database with new values.
     ///
     /// This function updates an existing row in the database within the
@@ -378,18 +1524,522 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
     ///
     /// Returns `true` if the row was successfully updated, and `false` otherwise.
     pub fn update(&self, tx_id: TxID, row: Row<T>) -> Result<bool> {
-        if !self.delete(tx_id, row.id)? {
+        if !self.delete(tx_id, row.id.clone())? {
             return Ok(false);
         }
         self.insert(tx_id, row)?;
         Ok(true)
     }
 
+    /// Locks a row for the rest of the transaction.
+    ///
+    /// A lock marker, a version that the transaction both begins and ends
+    /// and that has no data, is added to the row. The marker is never
+    /// visible, but other transactions that try to update, delete, or lock
+    /// the row see a write-write conflict right away, instead of the row
+    /// being contended until commit. Rows that do not exist can be locked
+    /// too. A row the transaction has already written is locked by the write
+    /// itself, so no marker is added to it.
+    ///
+    /// The marker is dropped when the transaction commits, leaving only the
+    /// versions the transaction wrote, if any, so a row that is locked but
+    /// not written is not logged or reported as changed. The marker is
+    /// removed when the transaction rolls back.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to lock the row.
+    /// * `id` - the ID of the row to lock.
+    pub fn lock_row(&self, tx_id: TxID, id: RowID) -> Result<()>
+    where
+        T: Default,
+    {
+        let tx_entry = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        {
+            let tx = tx_entry.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+        }
+        let own = TxTimestampOrID::TxID(tx_id);
+        loop {
+            let entry = self
+                .rows
+                .get_or_insert_with(id.clone(), || RwLock::new(RowManager::new()));
+            let mut row_versions = entry.value().write();
+            // The entry was removed because it became empty while we were
+            // waiting for the lock, so retry with a fresh one.
+            if entry.is_removed() {
+                continue;
+            }
+            let tx = tx_entry.value().read();
+            if row_versions
+                .iter()
+                .any(|rv| rv.begin == own || rv.end.as_ref() == Some(&own))
+            {
+                return Ok(());
+            }
+            // Unlike writes, which only look at the versions up to the visible
+            // one, a lock conflicts with the markers and pending deletes of all
+            // versions, as markers are not ordered by begin timestamp.
+            for rv in row_versions.iter() {
+                if let Some(with_tx_id) = is_write_write_conflict(&self.txs, &tx, rv)? {
+                    drop(row_versions);
+                    drop(entry);
+                    drop(tx);
+                    self.rollback_tx(tx_id);
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("mvcc_write_conflict_total").increment(1);
+                    return Err(DatabaseError::WriteWriteConflict { with_tx_id });
+                }
+            }
+            row_versions.push(RowVersion {
+                begin: own.clone(),
+                end: Some(own),
+                row: Row {
+                    id: id.clone(),
+                    data: T::default(),
+                },
+            });
+            tx.lock_set.insert(id);
+            tracing::trace!("LOCK      {tx_id}");
+            return Ok(());
+        }
+    }
+
+    /// Drops the lock markers that the transaction `tx_id` added to the rows
+    /// in `lock_set`, and removes the rows that are left without versions.
+    fn release_row_locks(&self, tx_id: TxID, lock_set: &[RowID]) {
+        let own = TxTimestampOrID::TxID(tx_id);
+        let is_marker = |rv: &RowVersion<T>| rv.begin == own && rv.end.as_ref() == Some(&own);
+        for id in lock_set {
+            if let Some(entry) = self.rows.get(id) {
+                let mut row_versions = entry.value().write();
+                // Only take a mutable reference if there is a marker, so that
+                // versions shared with a branch are not copied needlessly.
+                if row_versions.iter().any(is_marker) {
+                    row_versions.retain(|rv| !is_marker(rv));
+                }
+                if row_versions.is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+    }
+
+    /// Updates a row if its current value is `expected`.
+    ///
+    /// The current value is compared and the row updated while holding the
+    /// row lock, so no other transaction can update the row in between.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to update the row.
+    /// * `id` - the ID of the row to update.
+    /// * `expected` - the value the row must have for it to be updated.
+    /// * `new_row` - the row with the new value, which must have the ID `id`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `CasResult::Mismatch` with the current row if its value is not
+    /// `expected`, in which case the row is left alone. Fails with
+    /// `DatabaseError::RowIdMismatch` if `new_row` does not have the ID `id`.
+    pub fn compare_and_swap(
+        &self,
+        tx_id: TxID,
+        id: RowID,
+        expected: &T,
+        new_row: Row<T>,
+    ) -> Result<CasResult<T>>
+    where
+        T: PartialEq,
+    {
+        if new_row.id != id {
+            return Err(DatabaseError::RowIdMismatch {
+                expected: id,
+                actual: new_row.id,
+            });
+        }
+        let tx_entry = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let read_ts = {
+            let tx = tx_entry.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+            self.read_ts(&tx)
+        };
+        let Some(entry) = self.rows.get(&id) else {
+            return Ok(CasResult::NotFound);
+        };
+        let mut row_versions = entry.value().write();
+        let tx = tx_entry.value().read();
+        let mut visible = None;
+        for (i, rv) in row_versions.iter().enumerate().rev() {
+            if let Some(with_tx_id) = is_write_write_conflict(&self.txs, &tx, rv)? {
+                drop(row_versions);
+                drop(entry);
+                drop(tx);
+                self.rollback_tx(tx_id);
+                #[cfg(feature = "metrics")]
+                metrics::counter!("mvcc_write_conflict_total").increment(1);
+                return Err(DatabaseError::WriteWriteConflict { with_tx_id });
+            }
+            if is_version_visible(&self.txs, &tx, read_ts, rv)? {
+                visible = Some(i);
+                break;
+            }
+        }
+        let Some(i) = visible else {
+            return Ok(CasResult::NotFound);
+        };
+        if row_versions[i].row.data != *expected {
+            tx.insert_to_read_set(id);
+            return Ok(CasResult::Mismatch(row_versions[i].row.clone()));
+        }
+        row_versions[i].end = Some(TxTimestampOrID::TxID(tx_id));
+        tx.write_set.insert(id);
+        let row_version = RowVersion {
+            begin: TxTimestampOrID::TxID(tx_id),
+            end: None,
+            row: new_row,
+        };
+        self.insert_version_raw(&mut row_versions, row_version);
+        let chain_length = row_versions.len();
+        drop(tx);
+        drop(row_versions);
+        self.maybe_shorten_version_chain(&entry, chain_length);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mvcc_insert_total").increment(1);
+        Ok(CasResult::Swapped)
+    }
+
+    /// Updates a row if its current value matches `predicate`.
+    ///
+    /// Like `compare_and_swap`, the predicate is evaluated and the row
+    /// updated while holding the row lock, so no other transaction can update
+    /// the row in between.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to update the row.
+    /// * `id` - the ID of the row to update.
+    /// * `predicate` - the condition the current row must satisfy.
+    /// * `new_row` - the row with the new value, which must have the ID `id`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `ConditionalUpdateResult::PredicateFailed` with the current
+    /// row if it does not match `predicate`, in which case the row is left
+    /// alone. Fails with `DatabaseError::RowIdMismatch` if `new_row` does
+    /// not have the ID `id`.
+    pub fn update_if<F>(
+        &self,
+        tx_id: TxID,
+        id: RowID,
+        predicate: F,
+        new_row: Row<T>,
+    ) -> Result<ConditionalUpdateResult<T>>
+    where
+        F: FnOnce(&Row<T>) -> bool,
+    {
+        if new_row.id != id {
+            return Err(DatabaseError::RowIdMismatch {
+                expected: id,
+                actual: new_row.id,
+            });
+        }
+        let tx_entry = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let read_ts = {
+            let tx = tx_entry.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+            self.read_ts(&tx)
+        };
+        let Some(entry) = self.rows.get(&id) else {
+            return Ok(ConditionalUpdateResult::NotFound);
+        };
+        let mut row_versions = entry.value().write();
+        let tx = tx_entry.value().read();
+        let visible = match self.visible_version_for_write(&tx, read_ts, &row_versions) {
+            Ok(visible) => visible,
+            Err(e @ DatabaseError::WriteWriteConflict { .. }) => {
+                drop(row_versions);
+                drop(entry);
+                drop(tx);
+                self.rollback_tx(tx_id);
+                #[cfg(feature = "metrics")]
+                metrics::counter!("mvcc_write_conflict_total").increment(1);
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        let Some(i) = visible else {
+            return Ok(ConditionalUpdateResult::NotFound);
+        };
+        if !predicate(&row_versions[i].row) {
+            tx.insert_to_read_set(id);
+            return Ok(ConditionalUpdateResult::PredicateFailed(
+                row_versions[i].row.clone(),
+            ));
+        }
+        row_versions[i].end = Some(TxTimestampOrID::TxID(tx_id));
+        tx.write_set.insert(id);
+        let row_version = RowVersion {
+            begin: TxTimestampOrID::TxID(tx_id),
+            end: None,
+            row: new_row,
+        };
+        self.insert_version_raw(&mut row_versions, row_version);
+        let chain_length = row_versions.len();
+        drop(tx);
+        drop(row_versions);
+        self.maybe_shorten_version_chain(&entry, chain_length);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mvcc_insert_total").increment(1);
+        Ok(ConditionalUpdateResult::Updated)
+    }
+
+    /// Replaces the row `id` with the result of applying `f` to it.
+    ///
+    /// `f` is called with the row visible to the transaction, or `None` if
+    /// there is no such row. If `f` returns a row, it replaces the visible
+    /// row or is inserted if there was none. If `f` returns `None`, the
+    /// visible row is deleted. The row is locked from the read until the
+    /// write, so no other transaction can write the row in between, as it
+    /// could with a separate `read` and `update`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to modify the row.
+    /// * `id` - the ID of the row to modify.
+    /// * `f` - the function computing the new row, which must have the ID `id`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the row written by the transaction, or `None` if the row was
+    /// deleted or did not exist. Fails with `DatabaseError::RowIdMismatch`,
+    /// leaving the row alone, if `f` returns a row with another ID.
+    pub fn read_modify_write<F>(&self, tx_id: TxID, id: RowID, f: F) -> Result<Option<Row<T>>>
+    where
+        F: FnOnce(Option<Row<T>>) -> Option<Row<T>>,
+    {
+        let tx_entry = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let read_ts = {
+            let tx = tx_entry.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+            self.read_ts(&tx)
+        };
+        loop {
+            let entry = self
+                .rows
+                .get_or_insert_with(id.clone(), || RwLock::new(RowManager::new()));
+            let mut row_versions = entry.value().write();
+            // The entry was removed because it became empty while we were
+            // waiting for the lock, so retry with a fresh one.
+            if entry.is_removed() {
+                continue;
+            }
+            let tx = tx_entry.value().read();
+            let mut visible = None;
+            for (i, rv) in row_versions.iter().enumerate().rev() {
+                if let Some(with_tx_id) = is_write_write_conflict(&self.txs, &tx, rv)? {
+                    drop(row_versions);
+                    drop(entry);
+                    drop(tx);
+                    self.rollback_tx(tx_id);
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("mvcc_write_conflict_total").increment(1);
+                    return Err(DatabaseError::WriteWriteConflict { with_tx_id });
+                }
+                if is_version_visible(&self.txs, &tx, read_ts, rv)? {
+                    visible = Some(i);
+                    break;
+                }
+            }
+            let new_row = f(visible.map(|i| row_versions[i].row.clone()));
+            let mismatch = new_row.as_ref().filter(|row| row.id != id);
+            if mismatch.is_some() || (visible.is_none() && new_row.is_none()) {
+                if row_versions.is_empty() {
+                    entry.remove();
+                }
+                return match mismatch {
+                    Some(row) => Err(DatabaseError::RowIdMismatch {
+                        expected: id,
+                        actual: row.id.clone(),
+                    }),
+                    None => Ok(None),
+                };
+            }
+            if let Some(i) = visible {
+                row_versions[i].end = Some(TxTimestampOrID::TxID(tx_id));
+            }
+            tx.write_set.insert(id);
+            let Some(row) = &new_row else {
+                return Ok(None);
+            };
+            let row_version = RowVersion {
+                begin: TxTimestampOrID::TxID(tx_id),
+                end: None,
+                row: row.clone(),
+            };
+            self.insert_version_raw(&mut row_versions, row_version);
+            let chain_length = row_versions.len();
+            drop(tx);
+            drop(row_versions);
+            self.maybe_shorten_version_chain(&entry, chain_length);
+            #[cfg(feature = "metrics")]
+            metrics::counter!("mvcc_insert_total").increment(1);
+            return Ok(new_row);
+        }
+    }
+
+    /// Swaps the values of the rows `id_a` and `id_b`.
+    ///
+    /// Both rows are locked while they are read and updated, so no other
+    /// transaction can write either row in between. The rows are locked in
+    /// row ID order, so concurrent swaps of the same rows cannot deadlock.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to swap the rows.
+    /// * `id_a` - the ID of the first row.
+    /// * `id_b` - the ID of the second row.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the rows were swapped, and `false` if either of them
+    /// does not exist, in which case neither row is changed.
+    pub fn swap_rows(&self, tx_id: TxID, id_a: RowID, id_b: RowID) -> Result<bool> {
+        if id_a == id_b {
+            return Ok(self.read(tx_id, id_a)?.is_some());
+        }
+        let tx_entry = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let read_ts = {
+            let tx = tx_entry.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+            self.read_ts(&tx)
+        };
+        let (Some(entry_a), Some(entry_b)) = (self.rows.get(&id_a), self.rows.get(&id_b)) else {
+            return Ok(false);
+        };
+        let (mut versions_a, mut versions_b) = if id_a < id_b {
+            let versions_a = entry_a.value().write();
+            (versions_a, entry_b.value().write())
+        } else {
+            let versions_b = entry_b.value().write();
+            (entry_a.value().write(), versions_b)
+        };
+        let tx = tx_entry.value().read();
+        let visible = self
+            .visible_version_for_write(&tx, read_ts, &versions_a)
+            .and_then(|i| {
+                let j = self.visible_version_for_write(&tx, read_ts, &versions_b)?;
+                Ok(i.zip(j))
+            });
+        let (i, j) = match visible {
+            Ok(Some((i, j))) => (i, j),
+            Ok(None) => return Ok(false),
+            Err(e @ DatabaseError::WriteWriteConflict { .. }) => {
+                drop(versions_a);
+                drop(versions_b);
+                drop(entry_a);
+                drop(entry_b);
+                drop(tx);
+                self.rollback_tx(tx_id);
+                #[cfg(feature = "metrics")]
+                metrics::counter!("mvcc_write_conflict_total").increment(1);
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        let data_a = versions_a[i].row.data.clone();
+        let data_b = versions_b[j].row.data.clone();
+        for (row_versions, i, id, data) in [
+            (&mut versions_a, i, id_a, data_b),
+            (&mut versions_b, j, id_b, data_a),
+        ] {
+            row_versions[i].end = Some(TxTimestampOrID::TxID(tx_id));
+            tx.write_set.insert(id.clone());
+            let row_version = RowVersion {
+                begin: TxTimestampOrID::TxID(tx_id),
+                end: None,
+                row: Row { id, data },
+            };
+            self.insert_version_raw(row_versions, row_version);
+        }
+        let (length_a, length_b) = (versions_a.len(), versions_b.len());
+        drop(tx);
+        drop(versions_a);
+        drop(versions_b);
+        self.maybe_shorten_version_chain(&entry_a, length_a);
+        self.maybe_shorten_version_chain(&entry_b, length_b);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mvcc_insert_total").increment(2);
+        Ok(true)
+    }
+
+    /// Returns the index of the version in `row_versions` that is visible to
+    /// `tx` as of `read_ts`, for a write by `tx`.
+    ///
+    /// Fails with `DatabaseError::WriteWriteConflict` if another transaction
+    /// is writing the row, in which case the caller must roll back `tx`.
+    fn visible_version_for_write(
+        &self,
+        tx: &Transaction,
+        read_ts: u64,
+        row_versions: &[RowVersion<T>],
+    ) -> Result<Option<usize>> {
+        for (i, rv) in row_versions.iter().enumerate().rev() {
+            if let Some(with_tx_id) = is_write_write_conflict(&self.txs, tx, rv)? {
+                return Err(DatabaseError::WriteWriteConflict { with_tx_id });
+            }
+            if is_version_visible(&self.txs, tx, read_ts, rv)? {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+
     /// Inserts a row in the database with new values, previously deleting
     /// any old data if it existed. Bails on a delete error, e.g. write-write conflict.
-    pub fn upsert(&self, tx_id: TxID, row: Row<T>) -> Result<()> {
-        self.delete(tx_id, row.id)?;
-        self.insert(tx_id, row)
+    ///
+    /// # Returns
+    ///
+    /// Returns `UpsertResult::Updated` if a visible row was replaced, and
+    /// `UpsertResult::Inserted` otherwise.
+    pub fn upsert(&self, tx_id: TxID, row: Row<T>) -> Result<UpsertResult> {
+        let result = if self.delete(tx_id, row.id.clone())? {
+            UpsertResult::Updated
+        } else {
+            UpsertResult::Inserted
+        };
+        self.insert(tx_id, row)?;
+        Ok(result)
     }
 
     /// Deletes a row from the table with the given `id`.
@@ -407,24 +2057,41 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
     /// Returns `true` if the row was successfully deleted, and `false` otherwise.
     ///
     pub fn delete(&self, tx_id: TxID, id: RowID) -> Result<bool> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let read_ts = {
+            let tx = tx.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+            self.read_ts(&tx)
+        };
+        drop(tx);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mvcc_delete_total").increment(1);
         let row_versions_opt = self.rows.get(&id);
         if let Some(ref row_versions) = row_versions_opt {
-            let mut row_versions = row_versions.value().write().unwrap();
+            let mut row_versions = row_versions.value().write();
             for rv in row_versions.iter_mut().rev() {
                 let tx = self
                     .txs
                     .get(&tx_id)
-                    .ok_or(DatabaseError::NoSuchTransactionID(tx_id))?;
-                let tx = tx.value().read().unwrap();
-                assert_eq!(tx.state, TransactionState::Active);
-                if is_write_write_conflict(&self.txs, &tx, rv) {
+                    .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+                let tx = tx.value().read();
+                tx.check_active()?;
+                if let Some(with_tx_id) = is_write_write_conflict(&self.txs, &tx, rv)? {
                     drop(row_versions);
                     drop(row_versions_opt);
                     drop(tx);
                     self.rollback_tx(tx_id);
-                    return Err(DatabaseError::WriteWriteConflict);
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("mvcc_write_conflict_total").increment(1);
+                    return Err(DatabaseError::WriteWriteConflict { with_tx_id });
                 }
-                if is_version_visible(&self.txs, &tx, rv) {
+                if is_version_visible(&self.txs, &tx, read_ts, rv)? {
                     rv.end = Some(TxTimestampOrID::TxID(tx.tx_id));
                     drop(row_versions);
                     drop(row_versions_opt);
@@ -432,8 +2099,8 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
                     let tx = self
                         .txs
                         .get(&tx_id)
-                        .ok_or(DatabaseError::NoSuchTransactionID(tx_id))?;
-                    let mut tx = tx.value().write().unwrap();
+                        .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+                    let mut tx = tx.value().write();
                     tx.insert_to_write_set(id);
                     return Ok(true);
                 }
@@ -442,73 +2109,939 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
         Ok(false)
     }
 
-    /// Retrieves a row from the table with the given `id`.
+    /// Deletes multiple rows from the database.
     ///
-    /// This operation is performed within the scope of the transaction identified
-    /// by `tx_id`.
+    /// This is equivalent to calling `delete` for every row, but the
+    /// transaction is looked up only once. Like `delete`, the transaction is
+    /// rolled back if any of the rows is being updated by another transaction,
+    /// which also undoes the deletions made by the batch.
     ///
     /// # Arguments
     ///
-    /// * `tx_id` - The ID of the transaction to perform the read operation in.
-    /// * `id` - The ID of the row to retrieve.
+    /// * `tx_id` - the ID of the transaction in which to delete the rows.
+    /// * `ids` - the IDs of the rows to delete.
     ///
     /// # Returns
     ///
-    /// Returns `Some(row)` with the row data if the row with the given `id` exists,
-    /// and `None` otherwise.
-    pub fn read(&self, tx_id: TxID, id: RowID) -> Result<Option<Row<T>>> {
-        let tx = self.txs.get(&tx_id).unwrap();
-        let tx = tx.value().read().unwrap();
-        assert_eq!(tx.state, TransactionState::Active);
-        if let Some(row_versions) = self.rows.get(&id) {
-            let row_versions = row_versions.value().read().unwrap();
-            for rv in row_versions.iter().rev() {
-                if is_version_visible(&self.txs, &tx, rv) {
-                    tx.insert_to_read_set(id);
-                    return Ok(Some(rv.row.clone()));
+    /// Returns whether each of the rows was deleted, in the order of `ids`.
+    pub fn delete_batch(&self, tx_id: TxID, ids: Vec<RowID>) -> Result<Vec<bool>> {
+        let tx_entry = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let read_ts = {
+            let tx = tx_entry.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+            self.read_ts(&tx)
+        };
+        let mut deleted = Vec::with_capacity(ids.len());
+        for id in ids {
+            let Some(entry) = self.rows.get(&id) else {
+                deleted.push(false);
+                continue;
+            };
+            let mut row_versions = entry.value().write();
+            let tx = tx_entry.value().read();
+            let mut found = false;
+            for rv in row_versions.iter_mut().rev() {
+                if let Some(with_tx_id) = is_write_write_conflict(&self.txs, &tx, rv)? {
+                    drop(row_versions);
+                    drop(tx);
+                    drop(tx_entry);
+                    self.rollback_tx(tx_id);
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("mvcc_write_conflict_total").increment(1);
+                    return Err(DatabaseError::WriteWriteConflict { with_tx_id });
+                }
+                if is_version_visible(&self.txs, &tx, read_ts, rv)? {
+                    rv.end = Some(TxTimestampOrID::TxID(tx_id));
+                    tx.write_set.insert(id);
+                    found = true;
+                    break;
                 }
             }
+            deleted.push(found);
         }
-        Ok(None)
+        Ok(deleted)
     }
 
-    /// Gets all row ids in the database.
-    pub fn scan_row_ids(&self) -> Result<Vec<RowID>> {
-        let keys = self.rows.iter().map(|entry| *entry.key());
-        Ok(keys.collect())
+    /// Deletes all rows of the table `table_id`.
+    ///
+    /// This function deletes every row of the table that is visible to the
+    /// transaction `tx_id` in a single pass over the table, instead of
+    /// deleting the rows one by one. Like `delete`, the transaction is rolled
+    /// back if any of the rows is being updated by another transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to drop the table.
+    /// * `table_id` - the ID of the table to drop.
+    pub fn drop_table(&self, tx_id: TxID, table_id: u64) -> Result<()> {
+        let read_ts = {
+            let tx = self
+                .txs
+                .get(&tx_id)
+                .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+            let tx = tx.value().read();
+            tx.check_active()?;
+            if tx.read_only {
+                return Err(DatabaseError::ReadOnlyTransaction);
+            }
+            self.read_ts(&tx)
+        };
+        for entry in self.rows.range(table_range(table_id)) {
+            let mut row_versions = entry.value().write();
+            let tx = self
+                .txs
+                .get(&tx_id)
+                .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+            let tx = tx.value().read();
+            for rv in row_versions.iter_mut().rev() {
+                if let Some(with_tx_id) = is_write_write_conflict(&self.txs, &tx, rv)? {
+                    drop(row_versions);
+                    drop(tx);
+                    self.rollback_tx(tx_id);
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("mvcc_write_conflict_total").increment(1);
+                    return Err(DatabaseError::WriteWriteConflict { with_tx_id });
+                }
+                if is_version_visible(&self.txs, &tx, read_ts, rv)? {
+                    rv.end = Some(TxTimestampOrID::TxID(tx_id));
+                    tx.write_set.insert(entry.key().clone());
+                    break;
+                }
+            }
+        }
+        Ok(())
     }
 
-    /// Gets all row ids in the database for a given table.
-    pub fn scan_row_ids_for_table(&self, table_id: u64) -> Result<Vec<RowID>> {
-        Ok(self
-            .rows
-            .range(
-                RowID {
-                    table_id,
-                    row_id: 0,
-                }..RowID {
-                    table_id,
-                    row_id: u64::MAX,
+    /// Moves all rows of the table `old_id` to the table `new_id`.
+    ///
+    /// The rows visible to the transaction `tx_id` are deleted from the old
+    /// table and inserted to the new one with the same row IDs, so the rename
+    /// becomes visible to other transactions atomically when `tx_id`
+    /// commits. Like `drop_table`, the transaction is rolled back if any of
+    /// the rows is being updated by another transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - the ID of the transaction in which to rename the table.
+    /// * `old_id` - the ID of the table to rename.
+    /// * `new_id` - the new ID of the table, which must not have any rows
+    ///   visible to the transaction.
+    pub fn rename_table(&self, tx_id: TxID, old_id: u64, new_id: u64) -> Result<()> {
+        if old_id == new_id {
+            return Ok(());
+        }
+        if self.row_count(tx_id, new_id)? > 0 {
+            return Err(DatabaseError::TableNotEmpty(new_id));
+        }
+        let rows = self.scan_rows_for_table(tx_id, old_id)?;
+        self.drop_table(tx_id, old_id)?;
+        let rows = rows
+            .into_iter()
+            .map(|row| Row {
+                id: RowID {
+                    table_id: new_id,
+                    key: row.id.key,
                 },
-            )
-            .map(|entry| *entry.key())
-            .collect())
+                data: row.data,
+            })
+            .collect();
+        self.insert_batch(tx_id, rows)
     }
 
-    /// Begins a new transaction in the database.
+    /// Deletes all rows of the table `table_id` in a transaction of its own.
     ///
-    /// This function starts a new transaction in the database and returns a `TxID` value
-    /// that you can use to perform operations within the transaction. All changes made within the
-    /// transaction are isolated from other transactions until you commit the transaction.
+    /// Unlike `drop_table`, the table is truncated outside of any transaction
+    /// of the caller, and the deletion is committed before returning. Rows
+    /// inserted, updated, or deleted by transactions that have not committed
+    /// yet make the truncation fail with `DatabaseError::WriteWriteConflict`.
+    pub fn truncate_table(&self, table_id: u64) -> Result<()> {
+        self.transaction(|tx_id| {
+            for entry in self.rows.range(table_range(table_id)) {
+                let row_versions = entry.value().read();
+                // Uncommitted inserts and updates leave a TxID in `begin`, and
+                // uncommitted updates and deletes leave one in `end`.
+                let in_flight = row_versions
+                    .iter()
+                    .flat_map(|rv| [Some(&rv.begin), rv.end.as_ref()])
+                    .filter_map(|ts_or_id| match ts_or_id {
+                        Some(TxTimestampOrID::TxID(tx_id)) => Some(*tx_id),
+                        _ => None,
+                    });
+                for tx_id in in_flight {
+                    let te = version_tx(&self.txs, tx_id)?;
+                    let te = te.value().read();
+                    if matches!(
+                        te.state.load(),
+                        TransactionState::Active
+                            | TransactionState::Preparing
+                            | TransactionState::Killed
+                    ) {
+                        #[cfg(feature = "metrics")]
+                        metrics::counter!("mvcc_write_conflict_total").increment(1);
+                        return Err(DatabaseError::WriteWriteConflict {
+                            with_tx_id: te.tx_id,
+                        });
+                    }
+                }
+            }
+            self.drop_table(tx_id, table_id)
+        })
+    }
+
+    /// Retrieves a row from the table with the given `id`.
+    ///
+    /// This operation is performed within the scope of the transaction identified
+    /// by `tx_id`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - The ID of the transaction to perform the read operation in.
+    /// * `id` - The ID of the row to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Some(row)` with the row data if the row with the given `id` exists,
+    /// and `None` otherwise.
+    pub fn read(&self, tx_id: TxID, id: RowID) -> Result<Option<Row<T>>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mvcc_read_total").increment(1);
+        if let Some(row_versions) = self.rows.get(&id) {
+            let row_versions = row_versions.value().read();
+            if let Some(row) = self.visible_row(&tx, self.read_ts(&tx), &row_versions)? {
+                tx.insert_to_read_set(id);
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks that the row `child_id` can reference the row `parent_row_id`
+    /// of the table `parent_table_id`, i.e. that the parent row is visible to
+    /// the transaction `tx_id`.
+    ///
+    /// The database has no schema, so applications enforce referential
+    /// integrity by calling this before inserting or updating a child row,
+    /// and failing with `DatabaseError::ForeignKeyError` if it returns
+    /// `false`. The parent row is recorded in the read set, so under repeatable
+    /// read and serializable isolation the commit fails if another transaction
+    /// deletes the parent concurrently. Under snapshot isolation, lock the
+    /// parent with `lock_row` to rule that out.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the parent row exists, and `false` otherwise.
+    pub fn foreign_key_check(
+        &self,
+        tx_id: TxID,
+        child_id: RowID,
+        parent_table_id: u64,
+        parent_row_id: u64,
+    ) -> Result<bool> {
+        let parent_id = RowID::from_u64(parent_table_id, parent_row_id);
+        self.foreign_key_check_row(tx_id, child_id, parent_id)
+    }
+
+    /// Like `foreign_key_check`, but takes the ID of the parent row, for
+    /// parents whose keys are not integers.
+    pub fn foreign_key_check_row(
+        &self,
+        tx_id: TxID,
+        child_id: RowID,
+        parent_id: RowID,
+    ) -> Result<bool> {
+        let exists = self.read(tx_id, parent_id.clone())?.is_some();
+        if !exists {
+            tracing::trace!("NO PARENT {child_id:?} -> {parent_id:?}");
+        }
+        Ok(exists)
+    }
+
+    /// Returns the row of the latest version in `row_versions` that is visible
+    /// to `tx` as of `read_ts`.
+    fn visible_row(
+        &self,
+        tx: &Transaction,
+        read_ts: u64,
+        row_versions: &[RowVersion<T>],
+    ) -> Result<Option<Row<T>>> {
+        Ok(self
+            .visible_version(tx, read_ts, row_versions)?
+            .map(|rv| rv.row.clone()))
+    }
+
+    /// Returns the latest version in `row_versions` that is visible to `tx`
+    /// as of `read_ts`, without copying its row.
+    fn visible_version<'a>(
+        &self,
+        tx: &Transaction,
+        read_ts: u64,
+        row_versions: &'a [RowVersion<T>],
+    ) -> Result<Option<&'a RowVersion<T>>> {
+        for rv in row_versions.iter().rev() {
+            if is_version_visible(&self.txs, tx, read_ts, rv)? {
+                return Ok(Some(rv));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads multiple rows at once.
+    ///
+    /// This is equivalent to calling `read` for each of the row IDs, but
+    /// looks up the transaction only once.
+    ///
+    /// # Returns
+    ///
+    /// Returns the rows in the same order as `ids`, with `None` for rows that
+    /// do not exist or are not visible to the transaction.
+    pub fn multi_get(&self, tx_id: TxID, ids: &[RowID]) -> Result<Vec<Option<Row<T>>>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        let read_ts = self.read_ts(&tx);
+        let mut rows = Vec::with_capacity(ids.len());
+        for id in ids {
+            let row = match self.rows.get(id) {
+                Some(entry) => self.visible_row(&tx, read_ts, &entry.value().read())?,
+                None => None,
+            };
+            if row.is_some() {
+                tx.insert_to_read_set(id.clone());
+            }
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    /// Hints that the rows `ids` are about to be read.
+    ///
+    /// The hint is advisory and not part of any transaction: it does not
+    /// lock the rows or make them visible to anyone. All rows are kept in
+    /// memory, so this only looks up the rows in the index and the returned
+    /// handle is already complete. Storage that spills rows to disk can
+    /// load them in the background instead.
+    pub fn prefetch(&self, ids: &[RowID]) -> PrefetchHandle {
+        for id in ids {
+            self.rows.get(id);
+        }
+        PrefetchHandle { _private: () }
+    }
+
+    /// Returns the visibility horizon for a read by `tx`.
+    ///
+    /// Read-committed transactions see the versions committed before each
+    /// individual read, while all other isolation levels read from the
+    /// snapshot taken when the transaction began.
+    fn read_ts(&self, tx: &Transaction) -> u64 {
+        match tx.isolation_level {
+            IsolationLevel::ReadCommitted => self.get_timestamp(),
+            _ => tx.begin_ts,
+        }
+    }
+
+    /// Gets all rows in the database that are visible to the transaction `tx_id`.
+    pub fn scan_rows(&self, tx_id: TxID) -> Result<Vec<Row<T>>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        self.scan_visible_rows(&tx, self.rows.iter())
+    }
+
+    /// Gets all rows in the database for a given table that are visible to
+    /// the transaction `tx_id`.
+    ///
+    /// Like `scan_row_ids_for_table`, the scan is recorded in the predicate
+    /// set of the transaction.
+    pub fn scan_rows_for_table(&self, tx_id: TxID, table_id: u64) -> Result<Vec<Row<T>>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        tx.insert_to_predicate_set(table_id);
+        self.scan_visible_rows(&tx, self.rows.range(table_range(table_id)))
+    }
+
+    /// Gets all rows in the database for a given table that are visible to
+    /// the transaction `tx_id`, in the given `order`.
+    ///
+    /// Like `scan_rows_for_table`, the scan is recorded in the predicate set
+    /// of the transaction.
+    pub fn scan_rows_ordered(
+        &self,
+        tx_id: TxID,
+        table_id: u64,
+        order: ScanOrder<T>,
+    ) -> Result<Vec<Row<T>>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        tx.insert_to_predicate_set(table_id);
+        let entries = self.rows.range(table_range(table_id));
+        match order {
+            ScanOrder::AscendingByRowId => self.scan_visible_rows(&tx, entries),
+            ScanOrder::DescendingByRowId => self.scan_visible_rows(&tx, entries.rev()),
+            ScanOrder::ByDataField(field) => {
+                let mut rows = self.scan_visible_rows(&tx, entries)?;
+                // The sort is stable, so rows with equal fields stay in row ID order.
+                rows.sort_by(|a, b| field(a).cmp(&field(b)));
+                Ok(rows)
+            }
+        }
+    }
+
+    /// Gets the rows of the table `table_id` with row IDs in the range
+    /// `start_row_id..end_row_id` that are visible to the transaction `tx_id`.
+    ///
+    /// The scan is recorded in the predicate set of the transaction as a scan
+    /// of the whole table.
+    pub fn scan_rows_in_range(
+        &self,
+        tx_id: TxID,
+        table_id: u64,
+        start_row_id: u64,
+        end_row_id: u64,
+    ) -> Result<Vec<Row<T>>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        tx.insert_to_predicate_set(table_id);
+        let range = RowID::from_u64(table_id, start_row_id)..RowID::from_u64(table_id, end_row_id);
+        self.scan_visible_rows(&tx, self.rows.range(range))
+    }
+
+    /// Gets the rows of the tables `table_ids` that are visible to the
+    /// transaction `tx_id`, grouped by table.
+    ///
+    /// Unlike calling `scan_rows_for_table` for each table, all tables are
+    /// read as of the same timestamp, even for read-committed transactions,
+    /// so the result is a consistent view across the tables. The scans are
+    /// recorded in the predicate set of the transaction.
+    pub fn multi_table_scan(
+        &self,
+        tx_id: TxID,
+        table_ids: &[u64],
+    ) -> Result<HashMap<u64, Vec<Row<T>>>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        let read_ts = self.read_ts(&tx);
+        let mut tables = HashMap::with_capacity(table_ids.len());
+        for &table_id in table_ids {
+            if tables.contains_key(&table_id) {
+                continue;
+            }
+            tx.insert_to_predicate_set(table_id);
+            let mut rows = Vec::new();
+            for entry in self.rows.range(table_range(table_id)) {
+                let row_versions = entry.value().read();
+                if let Some(row) = self.visible_row(&tx, read_ts, &row_versions)? {
+                    tx.insert_to_read_set(entry.key().clone());
+                    rows.push(row);
+                }
+            }
+            tables.insert(table_id, rows);
+        }
+        Ok(tables)
+    }
+
+    /// Gets the rows of the table `table_id` that are visible to the
+    /// transaction `tx_id` and match `predicate`.
+    ///
+    /// The predicate is applied while the rows are scanned, so rows that do
+    /// not match are never copied. Like `scan_rows_for_table`, the scan is
+    /// recorded in the predicate set of the transaction.
+    pub fn find_rows<F>(&self, tx_id: TxID, table_id: u64, mut predicate: F) -> Result<Vec<Row<T>>>
+    where
+        F: FnMut(&Row<T>) -> bool,
+    {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        tx.insert_to_predicate_set(table_id);
+        let read_ts = self.read_ts(&tx);
+        let mut rows = Vec::new();
+        for entry in self.rows.range(table_range(table_id)) {
+            let row_versions = entry.value().read();
+            if let Some(rv) = self.visible_version(&tx, read_ts, &row_versions)? {
+                if predicate(&rv.row) {
+                    tx.insert_to_read_set(entry.key().clone());
+                    rows.push(rv.row.clone());
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Gets the first row, in row ID order, of the table `table_id` that is
+    /// visible to the transaction `tx_id` and matches `predicate`.
+    ///
+    /// Like `find_rows`, but the scan stops at the first match.
+    pub fn find_first_row<F>(
+        &self,
+        tx_id: TxID,
+        table_id: u64,
+        mut predicate: F,
+    ) -> Result<Option<Row<T>>>
+    where
+        F: FnMut(&Row<T>) -> bool,
+    {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        tx.insert_to_predicate_set(table_id);
+        let read_ts = self.read_ts(&tx);
+        for entry in self.rows.range(table_range(table_id)) {
+            let row_versions = entry.value().read();
+            if let Some(rv) = self.visible_version(&tx, read_ts, &row_versions)? {
+                if predicate(&rv.row) {
+                    tx.insert_to_read_set(entry.key().clone());
+                    return Ok(Some(rv.row.clone()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the approximate number of rows in the table `table_id`.
+    ///
+    /// Unlike `row_count`, this needs no transaction and does not scan the
+    /// table. It counts the rows inserted minus the rows deleted by committed
+    /// transactions, so it does not reflect uncommitted changes and may be
+    /// briefly out of date while transactions are committing.
+    pub fn estimate_row_count(&self, table_id: u64) -> u64 {
+        self.row_counts.get(&table_id).map_or(0, |count| {
+            count.value().load(Ordering::Relaxed).max(0) as u64
+        })
+    }
+
+    fn adjust_row_count(&self, table_id: u64, delta: i64) {
+        self.row_counts
+            .get_or_insert_with(table_id, || AtomicI64::new(0))
+            .value()
+            .fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// Counts the rows of a given table that are visible to the transaction `tx_id`.
+    ///
+    /// Like `scan_rows_for_table`, the scan is recorded in the predicate set
+    /// of the transaction and the counted rows in its read set, but no rows
+    /// are copied.
+    pub fn row_count(&self, tx_id: TxID, table_id: u64) -> Result<u64> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        tx.insert_to_predicate_set(table_id);
+        let read_ts = self.read_ts(&tx);
+        let mut count = 0;
+        for entry in self.rows.range(table_range(table_id)) {
+            let row_versions = entry.value().read();
+            for rv in row_versions.iter().rev() {
+                if is_version_visible(&self.txs, &tx, read_ts, rv)? {
+                    tx.insert_to_read_set(entry.key().clone());
+                    count += 1;
+                    break;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Returns an iterator that lazily scans the rows of a given table that
+    /// are visible to the transaction `tx_id`.
+    ///
+    /// Unlike `scan_rows_for_table`, the rows are not collected up front and
+    /// each row is only locked while the iterator is advanced past it.
+    pub fn scan_iter(&self, tx_id: TxID, table_id: u64) -> Result<LazyScanCursor<'_, Clock, T>> {
+        LazyScanCursor::new(self, tx_id, table_id)
+    }
+
+    /// Returns a handle to the table `table_id`, which builds row IDs for its rows.
+    pub fn table(&self, table_id: u64) -> Table<'_, Clock, T> {
+        Table::new(self, table_id)
+    }
+
+    /// Records a scan of the table `table_id` in the predicate set of the transaction `tx_id`.
+    pub(crate) fn record_table_scan(&self, tx_id: TxID, table_id: u64) -> Result<()> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        tx.insert_to_predicate_set(table_id);
+        Ok(())
+    }
+
+    /// Returns the row versions of the table `table_id`.
+    pub(crate) fn table_entries(&self, table_id: u64) -> TableEntries<'_, T> {
+        self.rows.range(table_range(table_id))
+    }
+
+    /// Reads the latest version of the row in `entry` that is visible to
+    /// the transaction `tx_id`, adding it to the read set of the transaction.
+    pub(crate) fn read_entry(
+        &self,
+        tx_id: TxID,
+        entry: &Entry<'_, RowID, RwLock<RowManager<T>>>,
+    ) -> Result<Option<Row<T>>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        tx.check_active()?;
+        let row_versions = entry.value().read();
+        let row = self.visible_row(&tx, self.read_ts(&tx), &row_versions)?;
+        if row.is_some() {
+            tx.insert_to_read_set(entry.key().clone());
+        }
+        Ok(row)
+    }
+
+    /// Collects the latest visible version of every row in `entries`, adding
+    /// the found rows to the read set of `tx`.
+    fn scan_visible_rows<'a>(
+        &self,
+        tx: &Transaction,
+        entries: impl Iterator<Item = Entry<'a, RowID, RwLock<RowManager<T>>>>,
+    ) -> Result<Vec<Row<T>>> {
+        let read_ts = self.read_ts(tx);
+        let mut rows = Vec::new();
+        for entry in entries {
+            let row_versions = entry.value().read();
+            if let Some(row) = self.visible_row(tx, read_ts, &row_versions)? {
+                tx.insert_to_read_set(entry.key().clone());
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Retrieves a row with the given `id` as it was at timestamp `ts`.
+    ///
+    /// Unlike `read`, this does not require a transaction and only considers
+    /// versions of committed transactions. Versions that ended before the
+    /// oldest active transaction began may already have been garbage
+    /// collected, see `set_gc_batch_size`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Some(row)` with the row data if the row with the given `id`
+    /// existed at timestamp `ts`, and `None` otherwise.
+    pub fn read_at_timestamp(&self, id: RowID, ts: u64) -> Result<Option<Row<T>>> {
+        Ok(self.rows.get(&id).and_then(|row_versions| {
+            let row_versions = row_versions.value().read();
+            row_versions
+                .iter()
+                .rev()
+                .find(|rv| is_version_visible_at(rv, ts))
+                .map(|rv| rv.row.clone())
+        }))
+    }
+
+    /// Gets all rows of a given table as they were at timestamp `ts`.
+    ///
+    /// Like `read_at_timestamp`, only versions of committed transactions are considered.
+    pub fn scan_rows_at_timestamp(&self, table_id: u64, ts: u64) -> Result<Vec<Row<T>>> {
+        Ok(self
+            .rows
+            .range(table_range(table_id))
+            .filter_map(|entry| {
+                let row_versions = entry.value().read();
+                row_versions
+                    .iter()
+                    .rev()
+                    .find(|rv| is_version_visible_at(rv, ts))
+                    .map(|rv| rv.row.clone())
+            })
+            .collect())
+    }
+
+    /// Exports a snapshot of all rows committed in the database at the time of the call.
+    pub fn export_snapshot(&self) -> Result<Snapshot<T>> {
+        Ok(self.export_snapshot_at(self.get_timestamp()))
+    }
+
+    /// Returns a snapshot of the rows committed at `timestamp`.
+    fn export_snapshot_at(&self, timestamp: u64) -> Snapshot<T> {
+        let rows = self
+            .rows
+            .iter()
+            .filter_map(|entry| {
+                let row_versions = entry.value().read();
+                row_versions
+                    .iter()
+                    .rev()
+                    .find(|rv| is_version_visible_at(rv, timestamp))
+                    .map(|rv| rv.row.clone())
+            })
+            .collect();
+        Snapshot { timestamp, rows }
+    }
+
+    /// Populates the database with the rows of `snapshot`, without going
+    /// through transactions or the transaction log.
+    ///
+    /// The database is expected to be empty. The clock is advanced past the
+    /// snapshot timestamp, so that new transactions see the imported rows.
+    pub fn import_snapshot(&self, snapshot: Snapshot<T>) -> Result<()> {
+        for row in snapshot.rows {
+            self.adjust_row_count(row.id.table_id, 1);
+            let row_version = RowVersion {
+                begin: TxTimestampOrID::Timestamp(snapshot.timestamp),
+                end: None,
+                row,
+            };
+            self.insert_version(row_version.row.id.clone(), row_version);
+        }
+        self.clock.reset(snapshot.timestamp + 1);
+        Ok(())
+    }
+
+    /// Replaces the rows of the database with the rows of `snapshot`.
+    ///
+    /// Fails with `ActiveTransactionsExist` if any transaction is in
+    /// progress, and the caller must not begin transactions until it returns.
+    /// The transaction log is left alone, so the previous state comes back on
+    /// recovery unless the database is checkpointed after the revert.
+    pub fn revert_to_snapshot(&self, snapshot: Snapshot<T>) -> Result<()> {
+        if !self.txs.is_empty() {
+            return Err(DatabaseError::ActiveTransactionsExist);
+        }
+        self.rows.clear();
+        self.row_counts.clear();
+        *self.recent_commits.lock() = RecentCommits::default();
+        *self.gc_cursor.lock() = None;
+        self.import_snapshot(snapshot)
+    }
+
+    /// Creates a new database populated with the rows of `snapshot`.
+    pub fn from_snapshot(
+        clock: Clock,
+        storage: impl Storage<T> + 'static,
+        snapshot: Snapshot<T>,
+    ) -> Result<Self> {
+        let db = Self::new(clock, storage);
+        db.import_snapshot(snapshot)?;
+        Ok(db)
+    }
+
+    /// Gets all row ids in the database.
+    pub fn scan_row_ids(&self) -> Result<Vec<RowID>> {
+        let keys = self.rows.iter().map(|entry| entry.key().clone());
+        Ok(keys.collect())
+    }
+
+    /// Gets all row ids in the database for a given table.
+    ///
+    /// The scan is recorded in the predicate set of the transaction `tx_id`
+    /// so that rows inserted into the table by concurrent transactions are
+    /// detected as phantoms when the transaction commits.
+    pub fn scan_row_ids_for_table(&self, tx_id: TxID, table_id: u64) -> Result<Vec<RowID>> {
+        self.record_table_scan(tx_id, table_id)?;
+        Ok(self
+            .rows
+            .range(table_range(table_id))
+            .map(|entry| entry.key().clone())
+            .collect())
+    }
+
+    /// Begins a new transaction in the database.
+    ///
+    /// This function starts a new transaction in the database and returns a `TxID` value
+    /// that you can use to perform operations within the transaction. All changes made within the
+    /// transaction are isolated from other transactions until you commit the transaction.
     pub fn begin_tx(&self) -> TxID {
+        self.start_tx(self.config.isolation_level, false)
+    }
+
+    /// Begins a new transaction in the database with the given isolation level.
+    ///
+    /// `begin_tx` begins transactions with the isolation level of the
+    /// database configuration, which is `IsolationLevel::SnapshotIsolation`
+    /// by default.
+    pub fn begin_tx_with_isolation(&self, isolation_level: IsolationLevel) -> TxID {
+        self.start_tx(isolation_level, false)
+    }
+
+    /// Begins a new read-only transaction in the database.
+    ///
+    /// Read-only transactions see a consistent snapshot of the database like
+    /// any other transaction, but attempts to modify rows within them fail
+    /// with `DatabaseError::ReadOnlyTransaction`. Committing a read-only
+    /// transaction never fails validation and never writes to the log.
+    pub fn begin_read_only_tx(&self) -> TxID {
+        self.start_tx(self.config.isolation_level, true)
+    }
+
+    fn start_tx(&self, isolation_level: IsolationLevel, read_only: bool) -> TxID {
+        if self.config.max_tx_age.is_some() {
+            self.kill_stale_transactions();
+        }
         let tx_id = self.get_tx_id();
+        // A serializable transaction begins under the recent commits lock, so
+        // that a transaction that commits after its begin timestamp sees it
+        // in progress and registers the commit, see `commit_tx`.
+        let recent_commits = (isolation_level == IsolationLevel::Serializable && !read_only)
+            .then(|| self.recent_commits.lock());
         let begin_ts = self.get_timestamp();
-        let tx = Transaction::new(tx_id, begin_ts);
+        let tx = Transaction::new(tx_id, begin_ts, isolation_level, read_only);
         tracing::trace!("BEGIN     {tx}");
         self.txs.insert(tx_id, RwLock::new(tx));
+        drop(recent_commits);
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("mvcc_tx_begin_total").increment(1);
+            metrics::gauge!("mvcc_active_transactions").increment(1);
+        }
         tx_id
     }
 
+    /// Returns how long ago the transaction `tx_id` began, or `None` if it
+    /// does not exist or has already finished.
+    pub fn transaction_age(&self, tx_id: TxID) -> Option<Duration> {
+        let tx = self.txs.get(&tx_id)?;
+        let tx = tx.value().read();
+        Some(tx.begin_wall_time.elapsed())
+    }
+
+    /// Kills the active transaction `tx_id` on behalf of its owner.
+    ///
+    /// The transaction is only marked as killed, because its owner may be
+    /// in the middle of an operation on it. Later operations on the
+    /// transaction fail with `TransactionKilled`, and the owner has to roll
+    /// it back, which `commit_tx` does as well. Until then, its writes stay
+    /// invisible to other transactions and conflict with theirs.
+    /// Transactions that are already committing cannot be killed and fail
+    /// with `TxTerminated`.
+    pub fn kill_transaction(&self, tx_id: TxID) -> Result<()> {
+        let tx_unlocked = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx_unlocked.value().write();
+        match tx.state.load() {
+            TransactionState::Active => {}
+            TransactionState::Killed => return Err(DatabaseError::TransactionKilled),
+            _ => return Err(DatabaseError::TxTerminated),
+        }
+        tracing::debug!("KILL      {tx_id}");
+        self.killed_txs.insert(tx_id);
+        tx.state.store(TransactionState::Killed);
+        Ok(())
+    }
+
+    /// Kills the active transactions that began longer than `max_tx_age`
+    /// ago, see `DatabaseBuilder::max_tx_age`, and returns their IDs.
+    ///
+    /// This runs whenever a transaction begins, but can also be called
+    /// periodically, for example from a background thread.
+    pub fn kill_stale_transactions(&self) -> Vec<TxID> {
+        let Some(max_tx_age) = self.config.max_tx_age else {
+            return Vec::new();
+        };
+        let stale: Vec<TxID> = self
+            .txs
+            .iter()
+            .filter(|entry| {
+                let tx = entry.value().read();
+                tx.state.load() == TransactionState::Active
+                    && tx.begin_wall_time.elapsed() > max_tx_age
+            })
+            .map(|entry| *entry.key())
+            .collect();
+        stale
+            .into_iter()
+            .filter(|&tx_id| self.kill_transaction(tx_id).is_ok())
+            .collect()
+    }
+
+    /// Returns the error for an operation on the transaction `tx_id` that is
+    /// not tracked by the database.
+    fn untracked_tx_error(&self, tx_id: TxID) -> DatabaseError {
+        if self.killed_txs.contains(&tx_id) {
+            DatabaseError::TransactionKilled
+        } else {
+            DatabaseError::NoSuchTransactionID { tx_id }
+        }
+    }
+
+    /// Returns information about all transactions that have not finished yet.
+    pub fn active_transactions(&self) -> Vec<TransactionInfo> {
+        self.txs
+            .iter()
+            .map(|entry| TransactionInfo::from(&*entry.value().read()))
+            .collect()
+    }
+
+    /// Returns information about the transaction `tx_id`, or `None` if it
+    /// does not exist or has already finished.
+    pub fn transaction_info(&self, tx_id: TxID) -> Option<TransactionInfo> {
+        let tx = self.txs.get(&tx_id)?;
+        let tx = tx.value().read();
+        Some(TransactionInfo::from(&*tx))
+    }
+
+    /// Returns the IDs of the rows read by the transaction `tx_id` so far,
+    /// which are checked for write skew when it commits.
+    pub fn get_transaction_read_set(&self, tx_id: TxID) -> Result<HashSet<RowID>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        Ok(tx.read_set.iter().map(|id| id.value().clone()).collect())
+    }
+
+    /// Returns the IDs of the rows inserted, updated or deleted by the
+    /// transaction `tx_id` so far.
+    pub fn get_transaction_write_set(&self, tx_id: TxID) -> Result<HashSet<RowID>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        Ok(tx.write_set.iter().map(|id| id.value().clone()).collect())
+    }
+
     /// Commits a transaction with the specified transaction ID.
     ///
     /// This function commits the changes made within the specified transaction and finalizes the
@@ -519,17 +3052,121 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
     ///
     /// * `tx_id` - The ID of the transaction to commit.
     pub fn commit_tx(&self, tx_id: TxID) -> Result<()> {
+        // The hooks run before the commit timestamp is taken, so that the
+        // transactions that begin while they run are ordered before the commit.
+        if let Some(tx_unlocked) = self.txs.get(&tx_id) {
+            let write_set = self.hooked_write_set(&tx_unlocked, &[HookEvent::BeforeCommit]);
+            drop(tx_unlocked);
+            if let Err(e) = self
+                .hooks
+                .run(HookEvent::BeforeCommit, tx_id, &write_set, None)
+            {
+                if self.txs.contains_key(&tx_id) {
+                    self.rollback_tx(tx_id);
+                }
+                return Err(e);
+            }
+        }
         let end_ts = self.get_timestamp();
         // NOTICE: the first shadowed tx keeps the entry alive in the map
         // for the duration of this whole function, which is important for correctness!
-        let tx = self.txs.get(&tx_id).ok_or(DatabaseError::TxTerminated)?;
-        let tx = tx.value().write().unwrap();
+        let tx_unlocked =
+            self.txs
+                .get(&tx_id)
+                .ok_or_else(|| match self.untracked_tx_error(tx_id) {
+                    DatabaseError::TransactionKilled => DatabaseError::TransactionKilled,
+                    _ => DatabaseError::TxTerminated,
+                })?;
+        let tx = tx_unlocked.value().write();
         match tx.state.load() {
             TransactionState::Terminated => return Err(DatabaseError::TxTerminated),
+            TransactionState::Killed => {
+                drop(tx);
+                self.rollback_tx(tx_id);
+                return Err(DatabaseError::TransactionKilled);
+            }
             _ => {
                 assert_eq!(tx.state, TransactionState::Active);
             }
         }
+        if tx.read_only {
+            // Read-only transactions have nothing to validate or log.
+            tx.state.store(TransactionState::Committed(end_ts));
+            tracing::trace!("COMMIT    {tx}");
+            drop(tx);
+            self.txs.remove(&tx_id);
+            #[cfg(feature = "metrics")]
+            {
+                metrics::counter!("mvcc_tx_commit_total").increment(1);
+                metrics::gauge!("mvcc_active_transactions").decrement(1);
+            }
+            self.hooks.notify(HookEvent::AfterCommit, tx_id, &[], None);
+            self.maybe_collect_garbage();
+            return Ok(());
+        }
+        let tx_begin_ts = tx.begin_ts;
+        let lock_set: Vec<RowID> = tx.lock_set.iter().map(|v| v.value().clone()).collect();
+        let serializable = tx.isolation_level == IsolationLevel::Serializable;
+        let write_set: HashSet<RowID> = tx.write_set.iter().map(|v| v.value().clone()).collect();
+        let predicate_set: Vec<u64> = match tx.isolation_level {
+            IsolationLevel::Serializable => tx.predicate_set.iter().map(|v| *v.value()).collect(),
+            _ => Vec::new(),
+        };
+        // Rows that the transaction also wrote are already protected by the
+        // write-write conflict detection, so only validate the rest of the read set.
+        let read_set: Vec<RowID> = match tx.isolation_level {
+            IsolationLevel::RepeatableRead | IsolationLevel::Serializable => tx
+                .read_set
+                .iter()
+                .map(|v| v.value().clone())
+                .filter(|id| !tx.write_set.contains(id))
+                .collect(),
+            _ => Vec::new(),
+        };
+        // NOTICE: the transaction lock must not be held while walking the
+        // row versions, because writers lock the rows first and transactions second.
+        drop(tx);
+        if self.has_phantoms(tx_id, tx_begin_ts, &predicate_set) {
+            tracing::trace!("PHANTOM   {tx_id}");
+            self.rollback_tx(tx_id);
+            return Err(DatabaseError::PhantomRead);
+        }
+        if self.has_write_skew(tx_id, tx_begin_ts, &read_set) {
+            tracing::trace!("SKEW      {tx_id}");
+            self.rollback_tx(tx_id);
+            return Err(DatabaseError::WriteSkewConflict);
+        }
+        // Validating against the recent commits and registering the commit
+        // happen under the same lock, so two serializable transactions that
+        // commit concurrently cannot both miss each other.
+        let mut recent_commits = self.recent_commits.lock();
+        if serializable && recent_commits.conflicts_with(tx_begin_ts, &read_set, &write_set) {
+            drop(recent_commits);
+            tracing::trace!("SERIALIZE {tx_id}");
+            self.rollback_tx(tx_id);
+            return Err(DatabaseError::SerializationFailure);
+        }
+        // Only the serializable transactions still in progress validate
+        // against the commits, and only against those after they began.
+        match self.oldest_serializable_begin_ts(tx_id) {
+            Some(oldest_begin_ts) => {
+                recent_commits.retain_after(oldest_begin_ts);
+                let read_set: HashSet<RowID> = if serializable {
+                    read_set.into_iter().collect()
+                } else {
+                    HashSet::new()
+                };
+                recent_commits.insert(end_ts, write_set, read_set);
+                recent_commits.truncate(self.config.recent_commits_window);
+            }
+            None => recent_commits.commits.clear(),
+        }
+        drop(recent_commits);
+        // The lock markers are dropped before the transaction is preparing,
+        // because other transactions treat versions of preparing transactions
+        // as committed when they validate.
+        self.release_row_locks(tx_id, &lock_set);
+        let tx = tx_unlocked.value().write();
         tx.state.store(TransactionState::Preparing);
         tracing::trace!("PREPARE   {tx}");
 
@@ -537,9 +3174,11 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
         ** In order to implement serializability, we need the following steps:
         **
         ** 1. Validate if all read versions are still visible by inspecting the read_set
-        ** 2. Validate if there are no phantoms by walking the scans from scan_set (which we don't even have yet)
+        **    - done for rows that were read but not written, see has_write_skew()
+        ** 2. Validate if there are no phantoms by walking the scans from scan_set
         **    - a phantom is a version that became visible in the middle of our transaction,
         **      but wasn't taken into account during one of the scans from the scan_set
+        **    - we currently only track whole-table scans in the predicate set, see has_phantoms()
         ** 3. Wait for commit dependencies, which we don't even track yet...
         **    Excerpt from what's a commit dependency and how it's tracked in the original paper:
         **    """
@@ -608,19 +3247,37 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
         */
         tx.state.store(TransactionState::Committed(end_ts));
         tracing::trace!("COMMIT    {tx}");
-        let tx_begin_ts = tx.begin_ts;
-        let write_set: Vec<RowID> = tx.write_set.iter().map(|v| *v.value()).collect();
+        let write_set: Vec<RowID> = tx.write_set.iter().map(|v| v.value().clone()).collect();
+        let duration_us = tx.begin_wall_time.elapsed().as_micros() as u64;
+        let mut log_record: LogRecord<T> =
+            LogRecord::new(end_ts, tx.begin_ts, duration_us, tx.causality_token);
         drop(tx);
+        let notify = !self.subscribers.lock().is_empty();
+        let mut changes = Vec::new();
         // Postprocessing: inserting row versions and logging the transaction to persistent storage.
         // TODO: we should probably save to persistent storage first, and only then update the in-memory structures.
-        let mut log_record: LogRecord<T> = LogRecord::new(end_ts);
-        for ref id in write_set {
+        for id in &write_set {
             if let Some(row_versions) = self.rows.get(id) {
-                let mut row_versions = row_versions.value().write().unwrap();
+                let mut row_versions = row_versions.value().write();
+                if let Some((kind, new_row)) = Self::change_of(tx_id, &row_versions) {
+                    match kind {
+                        ChangeKind::Insert => self.adjust_row_count(id.table_id, 1),
+                        ChangeKind::Delete => self.adjust_row_count(id.table_id, -1),
+                        ChangeKind::Update => {}
+                    }
+                    if notify {
+                        changes.push(ChangeEvent::Change {
+                            table_id: id.table_id,
+                            row_id: id.clone(),
+                            kind,
+                            new_row: new_row.cloned(),
+                        });
+                    }
+                }
                 for row_version in row_versions.iter_mut() {
                     if let TxTimestampOrID::TxID(id) = row_version.begin {
                         if id == tx_id {
-                            row_version.begin = TxTimestampOrID::Timestamp(tx_begin_ts);
+                            row_version.begin = TxTimestampOrID::Timestamp(end_ts);
                             self.insert_version_raw(
                                 &mut log_record.row_versions,
                                 row_version.clone(),
@@ -639,61 +3296,469 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
                 }
             }
         }
-        tracing::trace!("UPDATED TX{tx_id}");
-        // We have now updated all the versions with a reference to the
-        // transaction ID to a timestamp and can, therefore, remove the
-        // transaction. Please note that when we move to lockless, the
-        // invariant doesn't necessarily hold anymore because another thread
-        // might have speculatively read a version that we want to remove.
-        // But that's a problem for another day.
-        // FIXME: it actually just become a problem for today!!!
-        // TODO: test that reproduces this failure, and then a fix
-        self.txs.remove(&tx_id);
-        if !log_record.row_versions.is_empty() {
-            self.storage.log_tx(log_record)?;
+        tracing::trace!("UPDATED   {tx_id}");
+        // We have now updated all the versions with a reference to the
+        // transaction ID to a timestamp and can, therefore, remove the
+        // transaction. Please note that when we move to lockless, the
+        // invariant doesn't necessarily hold anymore because another thread
+        // might have speculatively read a version that we want to remove.
+        // But that's a problem for another day.
+        // FIXME: it actually just become a problem for today!!!
+        // TODO: test that reproduces this failure, and then a fix
+        //
+        // NOTICE: the transaction is logged before it is removed, so that
+        // `checkpoint` never misses a transaction that is committed but not yet logged.
+        let hooked_record = self
+            .hooks
+            .has(HookEvent::AfterCommit)
+            .then(|| log_record.clone());
+        let logged = if log_record.row_versions.is_empty() {
+            Ok(())
+        } else {
+            self.storage.log_tx(log_record)
+        };
+        self.txs.remove(&tx_id);
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("mvcc_tx_commit_total").increment(1);
+            metrics::gauge!("mvcc_active_transactions").decrement(1);
+        }
+        logged?;
+        tracing::trace!("LOGGED    {tx_id}");
+        self.hooks.notify(
+            HookEvent::AfterCommit,
+            tx_id,
+            &write_set,
+            hooked_record.as_ref(),
+        );
+        self.notify_subscribers(changes);
+        self.maybe_collect_garbage();
+        Ok(())
+    }
+
+    /// Returns the kind of change that the committing transaction `tx_id`
+    /// made to a row, if any, and the row after the change.
+    fn change_of(
+        tx_id: TxID,
+        row_versions: &[RowVersion<T>],
+    ) -> Option<(ChangeKind, Option<&Row<T>>)> {
+        let own = Some(TxTimestampOrID::TxID(tx_id));
+        let created = TxTimestampOrID::TxID(tx_id);
+        let old = row_versions
+            .iter()
+            .any(|rv| rv.end == own && rv.begin != created);
+        let new = row_versions
+            .iter()
+            .find(|rv| rv.begin == created && rv.end.is_none())
+            .map(|rv| &rv.row);
+        let kind = match (old, new) {
+            (true, Some(_)) => ChangeKind::Update,
+            (false, Some(_)) => ChangeKind::Insert,
+            (true, None) => ChangeKind::Delete,
+            (false, None) => return None,
+        };
+        Some((kind, new))
+    }
+
+    /// Delivers `changes` to the subscribers of their tables, dropping
+    /// subscribers that have gone away.
+    fn notify_subscribers(&self, changes: Vec<ChangeEvent<T>>) {
+        if changes.is_empty() {
+            return;
+        }
+        let mut subscribers = self.subscribers.lock();
+        for change in changes {
+            let ChangeEvent::Change { table_id, .. } = change else {
+                continue;
+            };
+            if let Some(table_subscribers) = subscribers.get_mut(&table_id) {
+                table_subscribers.retain_mut(|subscriber| subscriber.send(change.clone()));
+            }
+        }
+        subscribers.retain(|_, table_subscribers| !table_subscribers.is_empty());
+    }
+
+    /// Subscribes to the changes that committed transactions make to the
+    /// table `table_id`.
+    ///
+    /// Events are delivered once the transaction has been logged, so events
+    /// of transactions that commit concurrently may arrive in any order.
+    /// A subscriber that falls more than `SUBSCRIBER_BUFFER_SIZE`
+    /// events behind misses events, and receives `ChangeEvent::Lagged` with
+    /// the number of missed events before the next one it gets. Commits
+    /// never wait for subscribers.
+    pub fn subscribe(&self, table_id: u64) -> impl Stream<Item = ChangeEvent<T>> {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER_SIZE);
+        self.subscribers
+            .lock()
+            .entry(table_id)
+            .or_default()
+            .push(Subscriber { sender, lagged: 0 });
+        receiver
+    }
+
+    /// Installs `hook` to run at `event` for every transaction.
+    ///
+    /// Hooks run synchronously on the thread that commits or rolls back the
+    /// transaction, in the order they were installed, and must not install
+    /// other hooks. `BeforeCommit` hooks run before the transaction gets its
+    /// commit timestamp, and the first one that fails rolls the transaction
+    /// back, with `commit_tx` returning its error. The errors of the other
+    /// hooks are only logged, as the transaction is already finished or
+    /// being rolled back. Transactions killed with `kill_transaction` are
+    /// rolled back without running hooks.
+    pub fn install_hook(&self, event: HookEvent, hook: Hook<T>) {
+        self.hooks.install(event, hook);
+    }
+
+    /// Rolls back a transaction with the specified ID.
+    ///
+    /// This function rolls back a transaction with the specified `tx_id` by
+    /// discarding any changes made by the transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - The ID of the transaction to abort.
+    pub fn rollback_tx(&self, tx_id: TxID) {
+        // The owner of a killed transaction may still try to roll it back.
+        if self.killed_txs.contains(&tx_id) && !self.txs.contains_key(&tx_id) {
+            return;
+        }
+        let tx_unlocked = self.txs.get(&tx_id).unwrap();
+        let write_set = self.hooked_write_set(
+            &tx_unlocked,
+            &[HookEvent::BeforeRollback, HookEvent::AfterRollback],
+        );
+        self.hooks
+            .notify(HookEvent::BeforeRollback, tx_id, &write_set, None);
+        let tx = tx_unlocked.value().write();
+        assert!(matches!(
+            tx.state.load(),
+            TransactionState::Active | TransactionState::Killed
+        ));
+        self.abort_tx(&tx_unlocked, tx);
+        self.hooks
+            .notify(HookEvent::AfterRollback, tx_id, &write_set, None);
+    }
+
+    /// Returns the write set of the transaction for the hooks of `events`,
+    /// or an empty one if no such hooks are installed.
+    fn hooked_write_set(
+        &self,
+        tx_unlocked: &Entry<'_, TxID, RwLock<Transaction>>,
+        events: &[HookEvent],
+    ) -> Vec<RowID> {
+        if !events.iter().any(|&event| self.hooks.has(event)) {
+            return Vec::new();
+        }
+        let tx = tx_unlocked.value().read();
+        tx.write_set.iter().map(|v| v.value().clone()).collect()
+    }
+
+    /// Rolls back the active transaction `tx`, which is locked by the caller.
+    fn abort_tx(
+        &self,
+        tx_unlocked: &Entry<'_, TxID, RwLock<Transaction>>,
+        tx: RwLockWriteGuard<'_, Transaction>,
+    ) {
+        let tx_id = tx.tx_id;
+        tx.state.store(TransactionState::Aborted);
+        tracing::trace!("ABORT     {tx}");
+        let write_set: Vec<RowID> = tx.write_set.iter().map(|v| v.value().clone()).collect();
+        let lock_set: Vec<RowID> = tx.lock_set.iter().map(|v| v.value().clone()).collect();
+        drop(tx);
+        self.release_row_locks(tx_id, &lock_set);
+
+        for ref id in write_set {
+            if let Some(entry) = self.rows.get(id) {
+                let mut row_versions = entry.value().write();
+                row_versions.retain(|rv| rv.begin != TxTimestampOrID::TxID(tx_id));
+                // Versions deleted by the transaction become visible again.
+                for rv in row_versions.iter_mut() {
+                    if rv.end == Some(TxTimestampOrID::TxID(tx_id)) {
+                        rv.end = None;
+                    }
+                }
+                if row_versions.is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+
+        let tx = tx_unlocked.value().read();
+        tx.state.store(TransactionState::Terminated);
+        tracing::trace!("TERMINATE {tx}");
+        // FIXME: verify that we can already remove the transaction here!
+        // Maybe it's fine for snapshot isolation, but too early for serializable?
+        self.txs.remove(&tx_id);
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("mvcc_tx_rollback_total").increment(1);
+            metrics::gauge!("mvcc_active_transactions").decrement(1);
+        }
+    }
+
+    /// Runs `f` within a new transaction.
+    ///
+    /// The transaction is committed if `f` returns `Ok`, and rolled back if it
+    /// returns `Err`, in which case the error is returned to the caller.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function to run, which receives the ID of the transaction.
+    pub fn transaction<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(TxID) -> Result<R>,
+    {
+        let tx_id = self.begin_tx();
+        match f(tx_id) {
+            Ok(result) => {
+                self.commit_tx(tx_id)?;
+                Ok(result)
+            }
+            Err(e) => {
+                // Some errors, such as write-write conflicts, already rolled back the transaction.
+                if self.txs.contains_key(&tx_id) {
+                    self.rollback_tx(tx_id);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs `f` within a new read-only transaction.
+    ///
+    /// This is the read-only counterpart of `transaction`: `f` reads from a
+    /// consistent snapshot of the database, and the transaction is committed
+    /// if `f` returns `Ok` and rolled back otherwise. If `f` panics, the
+    /// transaction is rolled back while the panic unwinds.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function to run, which receives the ID of the transaction.
+    pub fn with_snapshot<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(TxID) -> Result<R>,
+    {
+        let mut guard = RollbackGuard {
+            db: self,
+            tx_id: Some(self.begin_read_only_tx()),
+        };
+        let tx_id = guard.tx_id.unwrap();
+        let result = f(tx_id)?;
+        guard.tx_id = None;
+        self.commit_tx(tx_id)?;
+        Ok(result)
+    }
+
+    /// Runs the future returned by `f` within a new transaction.
+    ///
+    /// This is the asynchronous counterpart of `transaction`: the transaction
+    /// is committed if the future resolves to `Ok`, and rolled back if it
+    /// resolves to `Err`. No lock is held while the future runs. If the future
+    /// is dropped before it completes, the transaction is rolled back.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function to run, which receives the ID of the transaction.
+    pub async fn with_transaction<F, Fut, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(TxID) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let mut guard = RollbackGuard {
+            db: self,
+            tx_id: Some(self.begin_tx()),
+        };
+        let tx_id = guard.tx_id.unwrap();
+        let result = f(tx_id).await;
+        guard.tx_id = None;
+        match result {
+            Ok(result) => {
+                self.commit_tx(tx_id)?;
+                Ok(result)
+            }
+            Err(e) => {
+                if self.txs.contains_key(&tx_id) {
+                    self.rollback_tx(tx_id);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs `f` within a new transaction like `transaction`, retrying with
+    /// a new transaction up to `retries` times on write-write conflicts.
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - The maximum number of times to retry.
+    /// * `f` - The function to run, which receives the ID of the transaction.
+    pub fn transaction_with_retry<F, R>(&self, retries: usize, mut f: F) -> Result<R>
+    where
+        F: FnMut(TxID) -> Result<R>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.transaction(&mut f) {
+                Err(DatabaseError::WriteWriteConflict { .. }) if attempt < retries => {
+                    attempt += 1;
+                    tracing::trace!("RETRY     {attempt}/{retries}");
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sets the causality token of the transaction `tx_id`.
+    ///
+    /// The token is opaque to the database and is recorded in the log record
+    /// of the transaction when it commits.
+    pub fn set_causality_token(&self, tx_id: TxID, token: [u8; 16]) -> Result<()> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let mut tx = tx.value().write();
+        tx.check_active()?;
+        tx.causality_token = Some(token);
+        Ok(())
+    }
+
+    /// Creates a savepoint in the transaction `tx_id`.
+    ///
+    /// The savepoint captures the write set of the transaction and the row
+    /// versions it has inserted and deleted so far, so that later changes
+    /// can be undone with `rollback_to_savepoint` without aborting the
+    /// whole transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - The ID of the transaction to create the savepoint in.
+    ///
+    /// # Returns
+    ///
+    /// Returns the ID of the new savepoint.
+    pub fn savepoint(&self, tx_id: TxID) -> Result<SavepointID> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let write_set: Vec<RowID> = {
+            let tx = tx.value().read();
+            tx.check_active()?;
+            tx.write_set.iter().map(|v| v.value().clone()).collect()
+        };
+        let rows = write_set
+            .iter()
+            .map(|id| (id.clone(), self.savepoint_row_state(tx_id, id)))
+            .collect();
+        let mut tx = tx.value().write();
+        let savepoint_id = tx.savepoints.len() as SavepointID;
+        tx.savepoints.push(SavepointState { write_set, rows });
+        tracing::trace!("SAVEPOINT {tx_id}:{savepoint_id}");
+        Ok(savepoint_id)
+    }
+
+    /// Rolls back the transaction `tx_id` to the savepoint `savepoint_id`.
+    ///
+    /// All row versions inserted and deleted by the transaction after the
+    /// savepoint was created are undone. The savepoint itself remains valid,
+    /// but savepoints created after it are released.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_id` - The ID of the transaction to roll back.
+    /// * `savepoint_id` - The ID of the savepoint to roll back to.
+    pub fn rollback_to_savepoint(&self, tx_id: TxID, savepoint_id: SavepointID) -> Result<()> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let (savepoint, write_set) = {
+            let mut tx = tx.value().write();
+            tx.check_active()?;
+            let savepoint = tx
+                .savepoints
+                .get(savepoint_id as usize)
+                .cloned()
+                .ok_or(DatabaseError::NoSuchSavepoint(savepoint_id))?;
+            tx.savepoints.truncate(savepoint_id as usize + 1);
+            let write_set: Vec<RowID> = tx.write_set.iter().map(|v| v.value().clone()).collect();
+            (savepoint, write_set)
+        };
+        tracing::trace!("ROLLBACK  {tx_id}:{savepoint_id}");
+        let own = Some(TxTimestampOrID::TxID(tx_id));
+        for id in &write_set {
+            let saved = savepoint
+                .rows
+                .iter()
+                .find(|(saved_id, _)| saved_id == id)
+                .map(|(_, state)| state.clone())
+                .unwrap_or_default();
+            if let Some(entry) = self.rows.get(id) {
+                let mut row_versions = entry.value().write();
+                let mut own_versions = saved.own_versions_deleted.iter();
+                row_versions.retain_mut(|rv| {
+                    if rv.begin != TxTimestampOrID::TxID(tx_id) {
+                        if rv.end == own && !saved.foreign_version_deleted {
+                            rv.end = None;
+                        }
+                        return true;
+                    }
+                    match own_versions.next() {
+                        Some(deleted) => {
+                            if !deleted {
+                                rv.end = None;
+                            }
+                            true
+                        }
+                        None => false,
+                    }
+                });
+                if row_versions.is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+        let tx = tx.value().read();
+        for id in write_set {
+            if !savepoint.write_set.contains(&id) {
+                tx.write_set.remove(&id);
+            }
         }
-        tracing::trace!("LOGGED    {tx_id}");
         Ok(())
     }
 
-    /// Rolls back a transaction with the specified ID.
-    ///
-    /// This function rolls back a transaction with the specified `tx_id` by
-    /// discarding any changes made by the transaction.
-    ///
-    /// # Arguments
-    ///
-    /// * `tx_id` - The ID of the transaction to abort.
-    pub fn rollback_tx(&self, tx_id: TxID) {
-        let tx_unlocked = self.txs.get(&tx_id).unwrap();
-        let tx = tx_unlocked.value().write().unwrap();
-        assert_eq!(tx.state, TransactionState::Active);
-        tx.state.store(TransactionState::Aborted);
-        tracing::trace!("ABORT     {tx}");
-        let write_set: Vec<RowID> = tx.write_set.iter().map(|v| *v.value()).collect();
-        drop(tx);
-        
-        for ref id in write_set {
-            if let Some(row_versions) = self.rows.get(id) {
-                let mut row_versions = row_versions.value().write().unwrap();
-                row_versions.retain(|rv| rv.begin != TxTimestampOrID::TxID(tx_id));
-                if row_versions.is_empty() {
-                    self.rows.remove(id);
+    /// Captures the versions of row `id` owned by the transaction `tx_id`.
+    fn savepoint_row_state(&self, tx_id: TxID, id: &RowID) -> SavepointRowState {
+        let mut state = SavepointRowState::default();
+        if let Some(row_versions) = self.rows.get(id) {
+            let own = Some(TxTimestampOrID::TxID(tx_id));
+            for rv in row_versions.value().read().iter() {
+                if rv.begin == TxTimestampOrID::TxID(tx_id) {
+                    state.own_versions_deleted.push(rv.end == own);
+                } else if rv.end == own {
+                    state.foreign_version_deleted = true;
                 }
             }
         }
-
-        let tx = tx_unlocked.value().write().unwrap();
-        tx.state.store(TransactionState::Terminated);
-        tracing::trace!("TERMINATE {tx}");
-        // FIXME: verify that we can already remove the transaction here!
-        // Maybe it's fine for snapshot isolation, but too early for serializable?
-        self.txs.remove(&tx_id);
+        state
     }
 
     /// Generates next unique transaction id
-    pub fn get_tx_id(&self) -> u64 {
-        self.tx_ids.fetch_add(1, Ordering::SeqCst)
+    ///
+    /// The ID counter wraps around after `u64::MAX`. Transaction 0 is
+    /// reserved, and IDs of transactions that are still tracked are skipped,
+    /// so that the IDs of retired transactions are recycled.
+    pub fn get_tx_id(&self) -> TxID {
+        loop {
+            let tx_id = TxID(self.tx_ids.fetch_add(1, Ordering::SeqCst));
+            if *tx_id != 0 && !self.txs.contains_key(&tx_id) {
+                self.killed_txs.remove(&tx_id);
+                return tx_id;
+            }
+        }
     }
 
     /// Gets current timestamp
@@ -701,45 +3766,307 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
         self.clock.get_timestamp()
     }
 
+    /// Returns a timestamp greater than every timestamp handed out so far,
+    /// without advancing the clock if it can, see `LogicalClock::peek`.
+    ///
+    /// Every transaction committed before the call has a lower commit
+    /// timestamp, so a reader elsewhere that waits for a snapshot at or
+    /// after the returned value sees its writes.
+    pub fn get_clock_value(&self) -> u64 {
+        self.clock.peek()
+    }
+
     /// Removes unused row  versions with very loose heuristics,
     /// which sometimes leaves versions intact for too long.
     /// Returns the number of removed versions.
     pub fn drop_unused_row_versions(&self) -> usize {
+        self.run_gc().versions_dropped as usize
+    }
+
+    /// Runs a full garbage collection pass over all rows and returns what it did.
+    pub fn run_gc(&self) -> GcStats {
         tracing::trace!(
             "Dropping unused row versions. Database stats: transactions: {}; rows: {}",
             self.txs.len(),
             self.rows.len()
         );
-        let mut dropped = 0;
-        let mut to_remove = Vec::new();
+        let started = Instant::now();
+        let watermark = self.update_gc_watermark();
+        let mut stats = GcStats {
+            oldest_active_ts: watermark,
+            ..Default::default()
+        };
+        for entry in self.rows.iter() {
+            self.drop_unused_versions_of(&entry, watermark, &mut stats);
+        }
+        stats.duration_micros = started.elapsed().as_micros() as u64;
+        self.record_gc_stats(&stats);
+        stats
+    }
+
+    /// Returns the garbage collection statistics accumulated over all
+    /// passes, including the incremental ones that run on commit.
+    pub fn gc_stats(&self) -> GcStats {
+        self.gc_stats.lock().clone()
+    }
+
+    fn record_gc_stats(&self, stats: &GcStats) {
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("mvcc_gc_versions_dropped_total").increment(stats.versions_dropped);
+            metrics::histogram!("mvcc_gc_duration_micros").record(stats.duration_micros as f64);
+        }
+        let mut totals = self.gc_stats.lock();
+        totals.versions_dropped += stats.versions_dropped;
+        totals.rows_removed += stats.rows_removed;
+        totals.duration_micros += stats.duration_micros;
+        totals.oldest_active_ts = totals.oldest_active_ts.max(stats.oldest_active_ts);
+    }
+
+    /// Returns the number of versions of the row `id`.
+    pub fn version_chain_length(&self, id: RowID) -> Result<usize> {
+        Ok(self
+            .rows
+            .get(&id)
+            .map(|entry| entry.value().read().len())
+            .unwrap_or(0))
+    }
+
+    /// Returns all versions of the row `id`, oldest first, regardless of
+    /// whether they are visible to any transaction.
+    pub fn iter_versions(&self, id: RowID) -> Result<Vec<VersionView<T>>> {
+        Ok(self
+            .rows
+            .get(&id)
+            .map(|entry| {
+                entry
+                    .value()
+                    .read()
+                    .iter()
+                    .map(|rv| VersionView {
+                        begin: rv.begin.clone(),
+                        end: rv.end.clone(),
+                        data: rv.row.data.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Explains for every version of the row `id`, oldest first, why it is
+    /// or isn't visible to the transaction `tx_id`.
+    pub fn explain_visibility(&self, tx_id: TxID, id: RowID) -> Result<Vec<VisibilityExplanation>> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or_else(|| self.untracked_tx_error(tx_id))?;
+        let tx = tx.value().read();
+        let read_ts = self.read_ts(&tx);
+        let Some(entry) = self.rows.get(&id) else {
+            return Ok(Vec::new());
+        };
+        let row_versions = entry.value().read();
+        row_versions
+            .iter()
+            .map(|rv| {
+                let (begin_visible, begin_reason) = rv.explain_begin(&tx, &self.txs, read_ts)?;
+                let (end_visible, end_reason) = rv.explain_end(&tx, &self.txs, read_ts)?;
+                Ok(VisibilityExplanation {
+                    begin: rv.begin.clone(),
+                    end: rv.end.clone(),
+                    is_visible: begin_visible && end_visible,
+                    reason: format!("{begin_reason}, {end_reason}"),
+                })
+            })
+            .collect()
+    }
+
+    /// Formats the versions of the row `id` for debugging, one per line.
+    pub fn print_version_chain(&self, id: RowID) -> Result<String> {
+        let format = |ts_or_id: &TxTimestampOrID| match ts_or_id {
+            TxTimestampOrID::Timestamp(ts) => format!("ts {ts}"),
+            TxTimestampOrID::TxID(tx_id) => tx_id.to_string(),
+        };
+        let mut chain = format!("{id:?}\n");
+        for version in self.iter_versions(id)? {
+            let end = version.end.as_ref().map_or("-".to_string(), format);
+            chain += &format!(
+                "  [{}, {}) {:?}\n",
+                format(&version.begin),
+                end,
+                version.data
+            );
+        }
+        Ok(chain)
+    }
+
+    /// Returns the number of versions of the row with the most versions.
+    pub fn max_version_chain_length(&self) -> Result<usize> {
+        Ok(self.version_chain_stats().max)
+    }
+
+    /// Returns statistics about the number of versions of every row.
+    pub fn version_chain_stats(&self) -> VersionChainStats {
+        let mut stats = VersionChainStats {
+            min: usize::MAX,
+            ..Default::default()
+        };
         for entry in self.rows.iter() {
-            let mut row_versions = entry.value().write().unwrap();
+            let len = entry.value().read().len();
+            stats.min = stats.min.min(len);
+            stats.max = stats.max.max(len);
+            stats.total_versions += len;
+            stats.total_rows += 1;
+        }
+        if stats.total_rows == 0 {
+            return VersionChainStats::default();
+        }
+        stats.mean = stats.total_versions as f64 / stats.total_rows as f64;
+        stats
+    }
+
+    /// Counts the versions of the rows in the table `table_id` by the state
+    /// of their end, regardless of whether they are visible to any
+    /// transaction.
+    pub fn count_versions(&self, table_id: u64) -> Result<VersionCountStats> {
+        let mut stats = VersionCountStats::default();
+        for entry in self.rows.range(table_range(table_id)) {
+            let row_versions = entry.value().read();
+            if row_versions.is_empty() {
+                continue;
+            }
+            for rv in row_versions.iter() {
+                match rv.end {
+                    None => stats.current_versions += 1,
+                    Some(TxTimestampOrID::Timestamp(_)) => stats.deleted_versions += 1,
+                    Some(TxTimestampOrID::TxID(_)) => stats.in_flight_versions += 1,
+                }
+            }
+            stats.total_rows += 1;
+        }
+        Ok(stats)
+    }
+
+    /// Runs an incremental garbage collection pass if the oldest active
+    /// transaction has advanced since the last pass.
+    ///
+    /// A pass visits at most `gc_batch_size` rows, continuing from where the
+    /// previous pass left off, so that commits pay a bounded cost.
+    fn maybe_collect_garbage(&self) {
+        if self.config.gc_batch_size == 0 {
+            return;
+        }
+        let watermark = self.update_gc_watermark();
+        if self.gc_epoch.fetch_max(watermark, Ordering::SeqCst) >= watermark {
+            return;
+        }
+        // Another thread is already collecting garbage, let it do the work.
+        let Some(mut cursor) = self.gc_cursor.try_lock() else {
+            return;
+        };
+        // Continue after the last row visited, wrapping around to the first row.
+        let last = cursor.clone();
+        let after = match last.clone() {
+            Some(last) => (Bound::Excluded(last), Bound::Unbounded),
+            None => (Bound::Unbounded, Bound::Unbounded),
+        };
+        let before = last.map(|last| self.rows.range((Bound::Unbounded, Bound::Included(last))));
+        let started = Instant::now();
+        let mut stats = GcStats {
+            oldest_active_ts: watermark,
+            ..Default::default()
+        };
+        let mut visited = 0;
+        for entry in self
+            .rows
+            .range(after)
+            .chain(before.into_iter().flatten())
+            .take(self.config.gc_batch_size)
+        {
+            self.drop_unused_versions_of(&entry, watermark, &mut stats);
+            *cursor = Some(entry.key().clone());
+            visited += 1;
+        }
+        stats.duration_micros = started.elapsed().as_micros() as u64;
+        tracing::trace!(
+            "GC        epoch {watermark}: dropped {} versions in {visited} rows",
+            stats.versions_dropped
+        );
+        self.record_gc_stats(&stats);
+    }
+
+    /// Recomputes the GC watermark, the minimum begin timestamp of all
+    /// active transactions capped by the watermark set with
+    /// `set_gc_watermark`, and returns it.
+    fn update_gc_watermark(&self) -> u64 {
+        let watermark = self
+            .txs
+            .iter()
+            .filter_map(|tx| {
+                let tx = tx.value().read();
+                match tx.state.load() {
+                    TransactionState::Active | TransactionState::Preparing => Some(tx.begin_ts),
+                    _ => None,
+                }
+            })
+            .min()
+            .unwrap_or_else(|| self.clock.peek())
+            .min(self.external_gc_watermark.load(Ordering::SeqCst));
+        self.gc_watermark.store(watermark, Ordering::SeqCst);
+        // No transaction that is still to commit began before the watermark.
+        self.recent_commits.lock().retain_after(watermark);
+        watermark
+    }
+
+    /// Returns the begin timestamp of the oldest serializable transaction in
+    /// progress other than `tx_id` that is validated on commit.
+    fn oldest_serializable_begin_ts(&self, tx_id: TxID) -> Option<u64> {
+        self.txs
+            .iter()
+            .filter(|tx| *tx.key() != tx_id)
+            .filter_map(|tx| {
+                let tx = tx.value().read();
+                let validated = tx.isolation_level == IsolationLevel::Serializable
+                    && !tx.read_only
+                    && matches!(
+                        tx.state.load(),
+                        TransactionState::Active | TransactionState::Preparing
+                    );
+                validated.then_some(tx.begin_ts)
+            })
+            .min()
+    }
+
+    /// Drops the versions of a row that are no longer visible to any
+    /// transaction that began at or after `watermark`, and removes the row
+    /// if no versions are left. The work done is added to `stats`.
+    fn drop_unused_versions_of(
+        &self,
+        entry: &Entry<'_, RowID, RwLock<RowManager<T>>>,
+        watermark: u64,
+        stats: &mut GcStats,
+    ) {
+        let should_stay = |rv: &RowVersion<T>| {
+            // FIXME: should take rv.begin into account as well
+            match rv.end_timestamp() {
+                // a transaction started before this row version ended, ergo row version is needed
+                Some(version_end_ts) => version_end_ts > watermark,
+                // Either this row version is current, ergo visible, or the
+                // transaction ending it is still active/tracked. Let's skip
+                // potentially complex logic in the latter case. We will drop
+                // the row version when the transaction gets garbage-collected
+                // itself, it will always happen eventually.
+                None => true,
+            }
+        };
+        let mut row_versions = entry.value().write();
+        // Only take a mutable reference if there is something to drop, so
+        // that versions shared with a branch are not copied needlessly.
+        if !row_versions.iter().all(should_stay) {
             row_versions.retain(|rv| {
-                // FIXME: should take rv.begin into account as well
-                let should_stay = match rv.end {
-                    Some(TxTimestampOrID::Timestamp(version_end_ts)) => {
-                        // a transaction started before this row version ended, ergo row version is needed
-                        // NOTICE: O(row_versions x transactions), but also lock-free, so sounds acceptable
-                        self.txs.iter().any(|tx| {
-                            let tx = tx.value().read().unwrap();
-                            // FIXME: verify!
-                            match tx.state.load() {
-                                TransactionState::Active | TransactionState::Preparing => {
-                                    version_end_ts > tx.begin_ts
-                                }
-                                _ => false,
-                            }
-                        })
-                    }
-                    // Let's skip potentially complex logic if the transafction is still
-                    // active/tracked. We will drop the row version when the transaction
-                    // gets garbage-collected itself, it will always happen eventually.
-                    Some(TxTimestampOrID::TxID(tx_id)) => !self.txs.contains_key(&tx_id),
-                    // this row version is current, ergo visible
-                    None => true,
-                };
+                let should_stay = should_stay(rv);
                 if !should_stay {
-                    dropped += 1;
+                    stats.versions_dropped += 1;
                     tracing::trace!(
                         "Dropping row version {:?} {:?}-{:?}",
                         entry.key(),
@@ -749,115 +4076,558 @@ impl<Clock: LogicalClock, T: Sync + Send + Clone + Serialize + Debug + Deseriali
                 }
                 should_stay
             });
-            if row_versions.is_empty() {
-                to_remove.push(*entry.key());
-            }
         }
-        for id in to_remove {
-            self.rows.remove(&id);
+        if row_versions.is_empty() {
+            entry.remove();
+            stats.rows_removed += 1;
+        }
+    }
+
+    /// Checks if a transaction other than `tx_id` that committed after
+    /// `begin_ts` added a version to any row of the tables in `predicate_set`.
+    ///
+    /// This flags rows that were only updated as well as inserted rows, so it
+    /// is stricter than phantom detection needs to be. Updates of rows the
+    /// scan saw would fail `has_write_skew` anyway, because scans add the rows
+    /// to the read set. Deletes add no version and are not flagged here.
+    fn has_phantoms(&self, tx_id: TxID, begin_ts: u64, predicate_set: &[u64]) -> bool {
+        predicate_set.iter().any(|&table_id| {
+            self.rows.range(table_range(table_id)).any(|entry| {
+                let row_versions = entry.value().read();
+                row_versions
+                    .iter()
+                    .any(|rv| self.is_committed_after(&rv.begin, tx_id, begin_ts))
+            })
+        })
+    }
+
+    /// Checks if a transaction other than `tx_id` that committed after
+    /// `begin_ts` updated or deleted any of the rows in `read_set`.
+    fn has_write_skew(&self, tx_id: TxID, begin_ts: u64, read_set: &[RowID]) -> bool {
+        read_set.iter().any(|id| {
+            self.rows.get(id).is_some_and(|row_versions| {
+                let row_versions = row_versions.value().read();
+                row_versions.iter().any(|rv| {
+                    self.is_committed_after(&rv.begin, tx_id, begin_ts)
+                        || rv
+                            .end
+                            .as_ref()
+                            .is_some_and(|end| self.is_committed_after(end, tx_id, begin_ts))
+                })
+            })
+        })
+    }
+
+    /// Checks if the timestamp or transaction `ts_or_id` belongs to a
+    /// transaction other than `tx_id` that committed after `begin_ts`.
+    /// Transactions that are still preparing are conservatively treated as committed.
+    fn is_committed_after(&self, ts_or_id: &TxTimestampOrID, tx_id: TxID, begin_ts: u64) -> bool {
+        match ts_or_id {
+            TxTimestampOrID::Timestamp(ts) => *ts > begin_ts,
+            TxTimestampOrID::TxID(id) if *id == tx_id => false,
+            TxTimestampOrID::TxID(id) => match self.txs.get(id) {
+                Some(other) => match other.value().read().state.load() {
+                    TransactionState::Preparing => true,
+                    TransactionState::Committed(committed_ts) => committed_ts > begin_ts,
+                    _ => false,
+                },
+                None => false,
+            },
+        }
+    }
+
+    /// Writes a checkpoint of the database to persistent storage and drops
+    /// the log records it covers.
+    ///
+    /// The checkpoint contains the rows committed at the time of the call.
+    /// It fails with `CheckpointBusy` if a read-write transaction that began
+    /// before the checkpoint is still in progress, because its log record
+    /// could end up neither in the checkpoint nor in the truncated log.
+    pub fn checkpoint(&self) -> Result<()> {
+        let timestamp = self.get_timestamp();
+        // A transaction leaves the transaction table only after its versions
+        // are stamped with its commit timestamp and its record is logged. So
+        // once no transaction that began before the checkpoint timestamp is
+        // left, including ones that are still committing, every commit below
+        // the timestamp is visible to the export.
+        let busy = self.txs.iter().any(|entry| {
+            let tx = entry.value().read();
+            !tx.read_only && tx.begin_ts < timestamp
+        });
+        if busy {
+            return Err(DatabaseError::CheckpointBusy);
         }
-        dropped
+        let snapshot = self.export_snapshot_at(timestamp);
+        self.storage.checkpoint(&snapshot)?;
+        self.storage.truncate_before(snapshot.timestamp)?;
+        tracing::debug!("CHECKPOINT {}", snapshot.timestamp);
+        Ok(())
+    }
+
+    /// Drops unused row versions from memory and replaces the transaction
+    /// log with a checkpoint of the current rows.
+    ///
+    /// This writes a checkpoint like `checkpoint`, and fails with
+    /// `CheckpointBusy` under the same conditions, followed by a full
+    /// garbage collection pass.
+    pub fn vacuum(&self) -> Result<VacuumStats> {
+        let started = Instant::now();
+        let versions_before = self.version_count();
+        let log_size_before = self.log_size()?;
+        self.checkpoint()?;
+        self.run_gc();
+        let stats = VacuumStats {
+            versions_before,
+            versions_after: self.version_count(),
+            bytes_freed: log_size_before.saturating_sub(self.log_size()?),
+            duration_micros: started.elapsed().as_micros() as u64,
+        };
+        tracing::debug!("VACUUM {stats:?}");
+        Ok(stats)
+    }
+
+    fn version_count(&self) -> u64 {
+        self.rows
+            .iter()
+            .map(|entry| entry.value().read().len() as u64)
+            .sum()
+    }
+
+    /// Returns the bincode-encoded size of the transaction log.
+    fn log_size(&self) -> Result<u64> {
+        self.storage
+            .read_tx_log()?
+            .iter()
+            .map(|record| {
+                bincode::serialized_size(record).map_err(|e| DatabaseError::Io(e.to_string()))
+            })
+            .sum()
+    }
+
+    /// Compacts the transaction log of the persistent storage, see
+    /// `Storage::compact_log`.
+    pub fn compact_log(&self) -> Result<CompactionStats> {
+        let stats = self.storage.compact_log()?;
+        tracing::debug!("COMPACT {stats:?}");
+        Ok(stats)
     }
 
+    /// Recovers the database from the transaction log of its persistent storage.
+    ///
+    /// If the storage has a checkpoint, it is loaded first and only the log
+    /// records of transactions that committed after it are replayed.
+    /// The clock is advanced past the highest replayed commit timestamp, so
+    /// new transactions get timestamps greater than those of all recovered ones.
     pub fn recover(&self) -> Result<()> {
+        let mut checkpoint_ts = 0;
+        if let Some(snapshot) = self.storage.read_checkpoint()? {
+            tracing::debug!("RECOVERING CHECKPOINT {}", snapshot.timestamp);
+            checkpoint_ts = snapshot.timestamp;
+            self.import_snapshot(snapshot)?;
+        }
         let tx_log = self.storage.read_tx_log()?;
         for record in tx_log {
+            // The log can still contain records covered by the checkpoint
+            // if we crashed before it was truncated.
+            if record.tx_timestamp < checkpoint_ts {
+                continue;
+            }
             tracing::debug!("RECOVERING {:?}", record);
             for version in record.row_versions {
-                self.insert_version(version.row.id, version);
+                self.replay_version(version);
             }
-            self.clock.reset(record.tx_timestamp);
+            self.clock.reset(record.tx_timestamp + 1);
+        }
+        let report = self.repair()?;
+        if report != RepairReport::default() {
+            tracing::warn!("Repaired database after recovery: {:?}", report);
         }
         Ok(())
     }
+
+    /// Removes the changes of transactions that never committed.
+    ///
+    /// Versions that still reference an unknown transaction ID, for example
+    /// because the transaction was in flight when the database crashed, are
+    /// treated as aborted: versions it inserted are removed, and versions it
+    /// deleted become live again. Versions of transactions that are still in
+    /// progress are left alone.
+    pub fn repair(&self) -> Result<RepairReport> {
+        let mut report = RepairReport::default();
+        for entry in self.rows.iter() {
+            let mut row_versions = entry.value().write();
+            let versions = row_versions.len();
+            row_versions.retain(
+                |rv| !matches!(rv.begin, TxTimestampOrID::TxID(id) if !self.txs.contains_key(&id)),
+            );
+            report.versions_removed += versions - row_versions.len();
+            for rv in row_versions.iter_mut() {
+                if let Some(TxTimestampOrID::TxID(id)) = rv.end {
+                    if !self.txs.contains_key(&id) {
+                        rv.end = None;
+                        report.deletions_undone += 1;
+                    }
+                }
+            }
+            if row_versions.is_empty() {
+                entry.remove();
+                report.rows_removed += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Panics if any row version references a transaction that no longer
+    /// exists, which means that a rollback or commit left it behind.
+    ///
+    /// Meant for tests, the same versions are cleaned up by `repair` after
+    /// recovery.
+    pub fn assert_no_version_leaks(&self) {
+        let mut leaks = Vec::new();
+        for entry in self.rows.iter() {
+            for rv in entry.value().read().iter() {
+                if let TxTimestampOrID::TxID(id) = rv.begin {
+                    if !self.txs.contains_key(&id) {
+                        leaks.push(format!(
+                            "{:?} begins with unknown transaction {id}",
+                            entry.key()
+                        ));
+                    }
+                }
+                if let Some(TxTimestampOrID::TxID(id)) = rv.end {
+                    if !self.txs.contains_key(&id) {
+                        leaks.push(format!(
+                            "{:?} ends with unknown transaction {id}",
+                            entry.key()
+                        ));
+                    }
+                }
+            }
+        }
+        assert!(
+            leaks.is_empty(),
+            "leaked row versions:\n{}",
+            leaks.join("\n")
+        );
+    }
+
+    /// Replays a row version from the transaction log.
+    ///
+    /// A version with an end timestamp was deleted by the transaction that
+    /// logged it. It ends the live version of the row, which was either
+    /// replayed from an earlier record or loaded from the checkpoint.
+    fn replay_version(&self, version: RowVersion<T>) {
+        if let Some(end_ts) = version.end_timestamp() {
+            if let Some(entry) = self.rows.get(&version.row.id) {
+                let mut row_versions = entry.value().write();
+                let live = row_versions.iter_mut().rev().find(|rv| {
+                    !rv.is_deleted() && rv.begin_timestamp().is_some_and(|ts| ts <= end_ts)
+                });
+                if let Some(live) = live {
+                    live.end = version.end;
+                    self.adjust_row_count(version.row.id.table_id, -1);
+                    return;
+                }
+            }
+        }
+        if !version.is_deleted() {
+            self.adjust_row_count(version.row.id.table_id, 1);
+        }
+        self.insert_version(version.row.id.clone(), version);
+    }
+}
+
+/// Rolls back the transaction `tx_id` when dropped, unless it was taken.
+struct RollbackGuard<'a, Clock, T>
+where
+    Clock: LogicalClock,
+    T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + 'static,
+{
+    db: &'a Database<Clock, T>,
+    tx_id: Option<TxID>,
+}
+
+impl<Clock, T> Drop for RollbackGuard<'_, Clock, T>
+where
+    Clock: LogicalClock,
+    T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + 'static,
+{
+    fn drop(&mut self) {
+        if let Some(tx_id) = self.tx_id.take() {
+            if self.db.txs.contains_key(&tx_id) {
+                self.db.rollback_tx(tx_id);
+            }
+        }
+    }
+}
+
+/// Returns the range of row IDs that belong to the table `table_id`.
+fn table_range(table_id: u64) -> TableRange {
+    let end = match table_id.checked_add(1) {
+        Some(next) => Bound::Excluded(RowID::new(next, Vec::new())),
+        None => Bound::Unbounded,
+    };
+    (Bound::Included(RowID::new(table_id, Vec::new())), end)
+}
+
+/// Checks if a row version was committed and not yet deleted at timestamp `ts`.
+/// Versions that are still being deleted by an uncommitted transaction are
+/// considered live.
+fn is_version_visible_at<T>(rv: &RowVersion<T>, ts: u64) -> bool {
+    rv.begin_timestamp()
+        .is_some_and(|rv_begin_ts| rv_begin_ts <= ts)
+        && rv.end_timestamp().is_none_or(|rv_end_ts| ts < rv_end_ts)
 }
 
 /// A write-write conflict happens when transaction T_m attempts to update a
 /// row version that is currently being updated by an active transaction T_n.
+///
+/// Returns the ID of the conflicting transaction T_n, if any.
 pub(crate) fn is_write_write_conflict<T>(
     txs: &SkipMap<TxID, RwLock<Transaction>>,
     tx: &Transaction,
     rv: &RowVersion<T>,
-) -> bool {
+) -> Result<Option<TxID>> {
     match rv.end {
         Some(TxTimestampOrID::TxID(rv_end)) => {
-            let te = txs.get(&rv_end).unwrap();
-            let te = te.value().read().unwrap();
+            let te = version_tx(txs, rv_end)?;
+            let te = te.value().read();
             match te.state.load() {
-                TransactionState::Active | TransactionState::Preparing => tx.tx_id != te.tx_id,
-                _ => false,
+                TransactionState::Active
+                | TransactionState::Preparing
+                | TransactionState::Killed
+                    if tx.tx_id != te.tx_id =>
+                {
+                    Ok(Some(te.tx_id))
+                }
+                _ => Ok(None),
             }
         }
-        Some(TxTimestampOrID::Timestamp(_)) => false,
-        None => false,
+        Some(TxTimestampOrID::Timestamp(_)) => Ok(None),
+        None => Ok(None),
     }
 }
 
-pub(crate) fn is_version_visible<T>(
+/// Looks up the transaction `tx_id` that a row version refers to.
+fn version_tx(
     txs: &SkipMap<TxID, RwLock<Transaction>>,
-    tx: &Transaction,
-    rv: &RowVersion<T>,
-) -> bool {
-    is_begin_visible(txs, tx, rv) && is_end_visible(txs, tx, rv)
+    tx_id: TxID,
+) -> Result<Entry<'_, TxID, RwLock<Transaction>>> {
+    txs.get(&tx_id).ok_or_else(|| {
+        DatabaseError::InternalStateError(format!(
+            "row version refers to unknown transaction {tx_id}"
+        ))
+    })
 }
 
-fn is_begin_visible<T>(
+/// Checks if the row version `rv` is visible to the transaction `tx` reading
+/// as of `read_ts`, which is the begin timestamp of the transaction unless it
+/// runs at the read-committed isolation level.
+pub(crate) fn is_version_visible<T>(
     txs: &SkipMap<TxID, RwLock<Transaction>>,
     tx: &Transaction,
+    read_ts: u64,
     rv: &RowVersion<T>,
-) -> bool {
-    match rv.begin {
-        TxTimestampOrID::Timestamp(rv_begin_ts) => tx.begin_ts >= rv_begin_ts,
-        TxTimestampOrID::TxID(rv_begin) => {
-            let tb = txs.get(&rv_begin).unwrap();
-            let tb = tb.value().read().unwrap();
-            let visible = match tb.state.load() {
-                TransactionState::Active => tx.tx_id == tb.tx_id && rv.end.is_none(),
-                TransactionState::Preparing => false, // NOTICE: makes sense for snapshot isolation, not so much for serializable!
-                TransactionState::Committed(committed_ts) => tx.begin_ts >= committed_ts,
-                TransactionState::Aborted => false,
-                TransactionState::Terminated => {
-                    tracing::debug!("TODO: should reread rv's end field - it should have updated the timestamp in the row version by now");
-                    false
-                }
-            };
-            tracing::trace!(
-                "is_begin_visible: tx={tx}, tb={tb} rv = {:?}-{:?} visible = {visible}",
-                rv.begin,
-                rv.end
-            );
-            visible
+) -> Result<bool> {
+    rv.is_visible_to(tx, txs, read_ts)
+}
+
+impl<T> RowVersion<T> {
+    /// Returns true if the transaction that created the version has
+    /// committed and its ID was replaced with the commit timestamp.
+    pub fn is_committed(&self) -> bool {
+        matches!(self.begin, TxTimestampOrID::Timestamp(_))
+    }
+
+    /// Returns true if the version was ended by a delete or an update,
+    /// whether or not the transaction that ended it has committed.
+    pub fn is_deleted(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// Returns the commit timestamp of the transaction that created the version.
+    pub fn begin_timestamp(&self) -> Option<u64> {
+        match self.begin {
+            TxTimestampOrID::Timestamp(ts) => Some(ts),
+            TxTimestampOrID::TxID(_) => None,
         }
     }
-}
 
-fn is_end_visible<T>(
-    txs: &SkipMap<TxID, RwLock<Transaction>>,
-    tx: &Transaction,
-    rv: &RowVersion<T>,
-) -> bool {
-    match rv.end {
-        Some(TxTimestampOrID::Timestamp(rv_end_ts)) => tx.begin_ts < rv_end_ts,
-        Some(TxTimestampOrID::TxID(rv_end)) => {
-            let te = txs.get(&rv_end).unwrap();
-            let te = te.value().read().unwrap();
-            let visible = match te.state.load() {
-                TransactionState::Active => tx.tx_id != te.tx_id,
-                TransactionState::Preparing => false, // NOTICE: makes sense for snapshot isolation, not so much for serializable!
-                TransactionState::Committed(committed_ts) => tx.begin_ts < committed_ts,
-                TransactionState::Aborted => false,
-                TransactionState::Terminated => {
-                    tracing::debug!("TODO: should reread rv's end field - it should have updated the timestamp in the row version by now");
-                    false
+    /// Returns the commit timestamp of the transaction that ended the version.
+    pub fn end_timestamp(&self) -> Option<u64> {
+        match self.end {
+            Some(TxTimestampOrID::Timestamp(ts)) => Some(ts),
+            Some(TxTimestampOrID::TxID(_)) | None => None,
+        }
+    }
+
+    /// Checks if the version is visible to the transaction `tx` reading as
+    /// of `read_ts`, which is the begin timestamp of the transaction unless
+    /// it runs at the read-committed isolation level.
+    pub(crate) fn is_visible_to(
+        &self,
+        tx: &Transaction,
+        txs: &SkipMap<TxID, RwLock<Transaction>>,
+        read_ts: u64,
+    ) -> Result<bool> {
+        Ok(self.is_begin_visible(tx, txs, read_ts)? && self.is_end_visible(tx, txs, read_ts)?)
+    }
+
+    fn is_begin_visible(
+        &self,
+        tx: &Transaction,
+        txs: &SkipMap<TxID, RwLock<Transaction>>,
+        read_ts: u64,
+    ) -> Result<bool> {
+        match self.begin {
+            TxTimestampOrID::Timestamp(rv_begin_ts) => Ok(read_ts >= rv_begin_ts),
+            TxTimestampOrID::TxID(rv_begin) => {
+                let tb = version_tx(txs, rv_begin)?;
+                let tb = tb.value().read();
+                let visible = match tb.state.load() {
+                    // A transaction sees its own versions until it finishes committing.
+                    TransactionState::Active
+                    | TransactionState::Preparing
+                    | TransactionState::Killed => tx.tx_id == tb.tx_id && self.end.is_none(),
+                    TransactionState::Committed(committed_ts) => read_ts >= committed_ts,
+                    TransactionState::Aborted => false,
+                    TransactionState::Terminated => {
+                        tracing::debug!("TODO: should reread rv's end field - it should have updated the timestamp in the row version by now");
+                        false
+                    }
+                };
+                tracing::trace!(
+                    "is_begin_visible: tx={tx}, tb={tb} rv = {:?}-{:?} visible = {visible}",
+                    self.begin,
+                    self.end
+                );
+                Ok(visible)
+            }
+        }
+    }
+
+    /// Explains the outcome of `is_begin_visible`.
+    fn explain_begin(
+        &self,
+        tx: &Transaction,
+        txs: &SkipMap<TxID, RwLock<Transaction>>,
+        read_ts: u64,
+    ) -> Result<(bool, String)> {
+        let rv_begin = match self.begin {
+            TxTimestampOrID::Timestamp(rv_begin_ts) => {
+                let visible = read_ts >= rv_begin_ts;
+                let order = if visible { "at or before" } else { "after" };
+                let reason =
+                    format!("begin timestamp {rv_begin_ts} is {order} read timestamp {read_ts}");
+                return Ok((visible, reason));
+            }
+            TxTimestampOrID::TxID(rv_begin) => rv_begin,
+        };
+        let tb = version_tx(txs, rv_begin)?;
+        let tb = tb.value().read();
+        let explanation = match tb.state.load() {
+            state @ (TransactionState::Active
+            | TransactionState::Preparing
+            | TransactionState::Killed) => {
+                if tx.tx_id != tb.tx_id {
+                    let reason =
+                        format!("begin {rv_begin} is {state:?} and is not this transaction");
+                    (false, reason)
+                } else if self.end.is_some() {
+                    let reason =
+                        format!("begin {rv_begin} is this transaction, which deleted the version");
+                    (false, reason)
+                } else {
+                    (true, format!("begin {rv_begin} is this transaction"))
                 }
-            };
-            tracing::trace!(
-                "is_end_visible: tx={tx}, te={te} rv = {:?}-{:?}  visible = {visible}",
-                rv.begin,
-                rv.end
-            );
-            visible
+            }
+            TransactionState::Committed(committed_ts) => {
+                let visible = read_ts >= committed_ts;
+                let order = if visible { "at or before" } else { "after" };
+                let reason = format!("begin {rv_begin} committed at {committed_ts}, {order} read timestamp {read_ts}");
+                (visible, reason)
+            }
+            state @ (TransactionState::Aborted | TransactionState::Terminated) => {
+                (false, format!("begin {rv_begin} is {state:?}"))
+            }
+        };
+        Ok(explanation)
+    }
+
+    /// Explains the outcome of `is_end_visible`.
+    fn explain_end(
+        &self,
+        tx: &Transaction,
+        txs: &SkipMap<TxID, RwLock<Transaction>>,
+        read_ts: u64,
+    ) -> Result<(bool, String)> {
+        let rv_end = match self.end {
+            Some(TxTimestampOrID::Timestamp(rv_end_ts)) => {
+                let visible = read_ts < rv_end_ts;
+                let order = if visible { "after" } else { "at or before" };
+                let reason =
+                    format!("end timestamp {rv_end_ts} is {order} read timestamp {read_ts}");
+                return Ok((visible, reason));
+            }
+            Some(TxTimestampOrID::TxID(rv_end)) => rv_end,
+            None => return Ok((true, "the version has not been deleted".to_string())),
+        };
+        let te = version_tx(txs, rv_end)?;
+        let te = te.value().read();
+        let explanation = match te.state.load() {
+            state @ (TransactionState::Active | TransactionState::Killed)
+                if tx.tx_id != te.tx_id =>
+            {
+                let reason = format!("end {rv_end} is {state:?} and is not this transaction");
+                (true, reason)
+            }
+            TransactionState::Active | TransactionState::Killed => {
+                (false, format!("end {rv_end} is this transaction"))
+            }
+            TransactionState::Committed(committed_ts) => {
+                let visible = read_ts < committed_ts;
+                let order = if visible { "after" } else { "at or before" };
+                let reason = format!(
+                    "end {rv_end} committed at {committed_ts}, {order} read timestamp {read_ts}"
+                );
+                (visible, reason)
+            }
+            state @ (TransactionState::Preparing
+            | TransactionState::Aborted
+            | TransactionState::Terminated) => (false, format!("end {rv_end} is {state:?}")),
+        };
+        Ok(explanation)
+    }
+
+    fn is_end_visible(
+        &self,
+        tx: &Transaction,
+        txs: &SkipMap<TxID, RwLock<Transaction>>,
+        read_ts: u64,
+    ) -> Result<bool> {
+        match self.end {
+            Some(TxTimestampOrID::Timestamp(rv_end_ts)) => Ok(read_ts < rv_end_ts),
+            Some(TxTimestampOrID::TxID(rv_end)) => {
+                let te = version_tx(txs, rv_end)?;
+                let te = te.value().read();
+                let visible = match te.state.load() {
+                    TransactionState::Active | TransactionState::Killed => tx.tx_id != te.tx_id,
+                    TransactionState::Preparing => false, // NOTICE: makes sense for snapshot isolation, not so much for serializable!
+                    TransactionState::Committed(committed_ts) => read_ts < committed_ts,
+                    TransactionState::Aborted => false,
+                    TransactionState::Terminated => {
+                        tracing::debug!("TODO: should reread rv's end field - it should have updated the timestamp in the row version by now");
+                        false
+                    }
+                };
+                tracing::trace!(
+                    "is_end_visible: tx={tx}, te={te} rv = {:?}-{:?}  visible = {visible}",
+                    self.begin,
+                    self.end
+                );
+                Ok(visible)
+            }
+            None => Ok(true),
         }
-        None => true,
     }
 }
@@ -0,0 +1,80 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::clock::LogicalClock;
+use crate::database::{Database, Result, TxID};
+use crate::errors::DatabaseError;
+
+/// How `Database::transaction_with_policy` retries transactions that fail
+/// with a write-write conflict.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of times the transaction is run, including the
+    /// first attempt.
+    pub max_attempts: usize,
+    /// The delay before the first retry.
+    pub base_delay: Duration,
+    /// The factor by which the delay grows with every retry.
+    pub backoff_factor: f64,
+    /// Whether to pick a random delay between zero and the computed delay,
+    /// so that conflicting transactions do not keep retrying in lockstep.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(1),
+            backoff_factor: 2.0,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before retrying a transaction that failed `attempt`
+    /// times.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as usize) as i32;
+        let mut secs = self.base_delay.as_secs_f64() * self.backoff_factor.powi(exponent);
+        if self.jitter {
+            let random = RandomState::new().build_hasher().finish();
+            secs *= random as f64 / u64::MAX as f64;
+        }
+        Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+    }
+}
+
+impl<
+        Clock: LogicalClock,
+        T: Sync + Send + Clone + Serialize + Debug + DeserializeOwned + 'static,
+    > Database<Clock, T>
+{
+    /// Runs `f` within a new transaction like `transaction`, retrying with
+    /// a new transaction on write-write conflicts as directed by `policy`.
+    ///
+    /// The thread sleeps between attempts. Other errors, and the conflict of
+    /// the last attempt, are returned to the caller.
+    pub fn transaction_with_policy<F, R>(&self, policy: &RetryPolicy, mut f: F) -> Result<R>
+    where
+        F: FnMut(TxID) -> Result<R>,
+    {
+        let mut attempt = 1;
+        loop {
+            match self.transaction(&mut f) {
+                Err(DatabaseError::WriteWriteConflict { .. }) if attempt < policy.max_attempts => {
+                    let delay = policy.delay(attempt);
+                    tracing::trace!("RETRY     {attempt}/{} in {delay:?}", policy.max_attempts);
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
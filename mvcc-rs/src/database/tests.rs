@@ -4,7 +4,7 @@ use tracing_test::traced_test;
 
 fn test_db() -> Database<LocalClock, String> {
     let clock = LocalClock::new();
-    let storage = crate::persistent_storage::Storage::new_noop();
+    let storage = crate::persistent_storage::MemoryStorage::new();
     Database::new(clock, storage)
 }
 
@@ -15,52 +15,146 @@ fn test_insert_read() {
 
     let tx1 = db.begin_tx();
     let tx1_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
+        id: RowID::from_u64(1, 1),
         data: "Hello".to_string(),
     };
     db.insert(tx1, tx1_row.clone()).unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
     assert_eq!(tx1_row, row);
     db.commit_tx(tx1).unwrap();
 
     let tx2 = db.begin_tx();
-    let row = db
-        .read(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
+    let row = db.read(tx2, RowID::from_u64(1, 1)).unwrap().unwrap();
     assert_eq!(tx1_row, row);
 }
 
+#[traced_test]
+#[test]
+fn test_insert_batch() {
+    let db = test_db();
+    let row = |row_id| Row {
+        id: RowID::from_u64(1, row_id),
+        data: format!("Hello {row_id}"),
+    };
+    let tx1 = db.begin_tx();
+    db.insert_batch(tx1, (1..=3).map(row).collect()).unwrap();
+    assert_eq!(db.row_count(tx1, 1).unwrap(), 3);
+    db.commit_tx(tx1).unwrap();
+
+    // A batch in a read-only transaction fails without inserting anything.
+    let tx2 = db.begin_read_only_tx();
+    assert_eq!(
+        db.insert_batch(tx2, (4..=5).map(row).collect()),
+        Err(DatabaseError::ReadOnlyTransaction)
+    );
+    assert_eq!(db.version_chain_length(row(4).id).unwrap(), 0);
+    assert_eq!(db.row_count(tx2, 1).unwrap(), 3);
+    assert_eq!(db.read(tx2, row(2).id).unwrap(), Some(row(2)));
+}
+
+#[traced_test]
+#[test]
+fn test_delete_batch() {
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id| Row {
+        id: id(row_id),
+        data: "Hello".to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert_batch(tx1, (1..=4).map(row).collect()).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    assert_eq!(
+        db.delete_batch(tx2, vec![id(1), id(5), id(2), id(1)])
+            .unwrap(),
+        vec![true, false, true, false]
+    );
+    assert_eq!(db.row_count(tx2, 1).unwrap(), 2);
+
+    // A conflict on any of the rows rolls back the whole transaction.
+    let tx3 = db.begin_tx();
+    assert!(db.delete(tx3, id(4)).unwrap());
+    assert_eq!(
+        db.delete_batch(tx2, vec![id(3), id(4)]),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx3 })
+    );
+    db.commit_tx(tx3).unwrap();
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, id(1)).unwrap(), Some(row(1)));
+    assert_eq!(db.read(tx4, id(3)).unwrap(), Some(row(3)));
+    assert_eq!(db.row_count(tx4, 1).unwrap(), 3);
+}
+
+#[traced_test]
+#[test]
+fn test_table() {
+    let db = test_db();
+    let users = db.table(1);
+    let orders = db.table(2);
+    assert_eq!(users.row_id(7), RowID::from_u64(1, 7));
+
+    let tx1 = db.begin_tx();
+    users.insert(tx1, 1, "alice".to_string()).unwrap();
+    users.insert(tx1, 2, "bob".to_string()).unwrap();
+    orders.insert(tx1, 1, "order".to_string()).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    assert_eq!(users.read(tx2, 1).unwrap().unwrap().data, "alice");
+    assert_eq!(orders.read(tx2, 1).unwrap().unwrap().data, "order");
+    assert!(users.update(tx2, 1, "carol".to_string()).unwrap());
+    assert!(users.delete(tx2, 2).unwrap());
+    assert!(!orders.delete(tx2, 2).unwrap());
+    let rows = users.scan_rows(tx2).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].id, users.row_id(1));
+    assert_eq!(rows[0].data, "carol");
+    assert_eq!(orders.scan_rows(tx2).unwrap().len(), 1);
+}
+
+#[traced_test]
+#[test]
+fn test_get_or_insert() {
+    let db = test_db();
+    let row = |data: &str| Row {
+        id: RowID::from_u64(1, 1),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    assert_eq!(
+        db.get_or_insert(tx1, row("Hello")).unwrap(),
+        (row("Hello"), true)
+    );
+    assert_eq!(
+        db.get_or_insert(tx1, row("World")).unwrap(),
+        (row("Hello"), false)
+    );
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    assert_eq!(
+        db.get_or_insert(tx2, row("World")).unwrap(),
+        (row("Hello"), false)
+    );
+    assert!(db.delete(tx2, row("Hello").id).unwrap());
+    assert_eq!(
+        db.get_or_insert(tx2, row("World")).unwrap(),
+        (row("World"), true)
+    );
+    db.commit_tx(tx2).unwrap();
+
+    let tx3 = db.begin_tx();
+    assert_eq!(db.read(tx3, row("World").id).unwrap(), Some(row("World")));
+}
+
 #[traced_test]
 #[test]
 fn test_read_nonexistent() {
     let db = test_db();
     let tx = db.begin_tx();
-    let row = db.read(
-        tx,
-        RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-    );
+    let row = db.read(tx, RowID::from_u64(1, 1));
     assert!(row.unwrap().is_none());
 }
 
@@ -71,54 +165,19 @@ fn test_delete() {
 
     let tx1 = db.begin_tx();
     let tx1_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
+        id: RowID::from_u64(1, 1),
         data: "Hello".to_string(),
     };
     db.insert(tx1, tx1_row.clone()).unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
     assert_eq!(tx1_row, row);
-    db.delete(
-        tx1,
-        RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-    )
-    .unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap();
+    db.delete(tx1, RowID::from_u64(1, 1)).unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap();
     assert!(row.is_none());
     db.commit_tx(tx1).unwrap();
 
     let tx2 = db.begin_tx();
-    let row = db
-        .read(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap();
+    let row = db.read(tx2, RowID::from_u64(1, 1)).unwrap();
     assert!(row.is_none());
 }
 
@@ -127,15 +186,7 @@ fn test_delete() {
 fn test_delete_nonexistent() {
     let db = test_db();
     let tx = db.begin_tx();
-    assert!(!db
-        .delete(
-            tx,
-            RowID {
-                table_id: 1,
-                row_id: 1
-            }
-        )
-        .unwrap());
+    assert!(!db.delete(tx, RowID::from_u64(1, 1)).unwrap());
 }
 
 #[traced_test]
@@ -144,56 +195,23 @@ fn test_commit() {
     let db = test_db();
     let tx1 = db.begin_tx();
     let tx1_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
+        id: RowID::from_u64(1, 1),
         data: "Hello".to_string(),
     };
     db.insert(tx1, tx1_row.clone()).unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
     assert_eq!(tx1_row, row);
     let tx1_updated_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
+        id: RowID::from_u64(1, 1),
         data: "World".to_string(),
     };
     db.update(tx1, tx1_updated_row.clone()).unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
     assert_eq!(tx1_updated_row, row);
     db.commit_tx(tx1).unwrap();
 
     let tx2 = db.begin_tx();
-    let row = db
-        .read(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
+    let row = db.read(tx2, RowID::from_u64(1, 1)).unwrap().unwrap();
     db.commit_tx(tx2).unwrap();
     assert_eq!(tx1_updated_row, row);
     db.drop_unused_row_versions();
@@ -201,580 +219,2968 @@ fn test_commit() {
 
 #[traced_test]
 #[test]
-fn test_rollback() {
-    let db = test_db();
-    let tx1 = db.begin_tx();
-    let row1 = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "Hello".to_string(),
+fn test_gc_on_commit() {
+    let mut db = test_db();
+    db.set_gc_batch_size(1);
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
     };
-    db.insert(tx1, row1.clone()).unwrap();
-    let row2 = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(row1, row2);
-    let row3 = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "World".to_string(),
+    let versions = |db: &Database<LocalClock, String>, row_id| {
+        db.rows
+            .get(&id(row_id))
+            .map(|e| e.value().read().len())
+            .unwrap_or(0)
     };
-    db.update(tx1, row3.clone()).unwrap();
-    let row4 = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(row3, row4);
-    db.rollback_tx(tx1);
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // An active reader keeps the old versions alive.
+    let reader = db.begin_read_only_tx();
     let tx2 = db.begin_tx();
-    let row5 = db
-        .read(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap();
-    assert_eq!(row5, None);
+    db.update(tx2, row(1, "World")).unwrap();
+    db.update(tx2, row(2, "World")).unwrap();
+    db.commit_tx(tx2).unwrap();
+    assert_eq!(versions(&db, 1), 2);
+    assert_eq!(versions(&db, 2), 2);
+    assert_eq!(db.read(reader, id(1)).unwrap(), Some(row(1, "Hello")));
+
+    // Once the reader finishes, each commit collects one row at a time,
+    // continuing after the row visited by the first commit.
+    db.commit_tx(reader).unwrap();
+    assert_eq!(versions(&db, 1), 2);
+    assert_eq!(versions(&db, 2), 1);
+    let tx3 = db.begin_tx();
+    db.commit_tx(tx3).unwrap();
+    assert_eq!(versions(&db, 1), 1);
+    assert_eq!(versions(&db, 2), 1);
+    db.assert_no_version_leaks();
 }
 
 #[traced_test]
 #[test]
-fn test_dirty_write() {
-    let db = test_db();
+fn test_database_builder() {
+    let storage = crate::persistent_storage::MemoryStorage::new();
+    let db: Database<LocalClock, String> = DatabaseBuilder::new()
+        .storage(storage.clone())
+        .gc_batch_size(0)
+        .isolation_level(IsolationLevel::Serializable)
+        .max_version_chain_length(2)
+        .build();
+    let id = RowID::from_u64(1, 1);
+    let row = |data: &str| Row {
+        id: id.clone(),
+        data: data.to_string(),
+    };
 
-    // T1 inserts a row with ID 1, but does not commit.
     let tx1 = db.begin_tx();
-    let tx1_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "Hello".to_string(),
+    assert_eq!(
+        db.txs.get(&tx1).unwrap().value().read().isolation_level,
+        IsolationLevel::Serializable
+    );
+    db.insert(tx1, row("Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    assert_eq!(storage.len(), 1);
+
+    // The version chain is collected as soon as it grows past two versions,
+    // even though automatic garbage collection is disabled.
+    for data in ["World", "Again", "And again"] {
+        let tx = db.begin_tx();
+        assert!(db.update(tx, row(data)).unwrap());
+        assert!(db.version_chain_length(id.clone()).unwrap() <= 2);
+        db.commit_tx(tx).unwrap();
+    }
+    assert_eq!(db.gc_stats().versions_dropped, 2);
+
+    // So it is when the row is inserted again with `get_or_insert` or
+    // `insert_batch`.
+    for data in ["Hello", "World"] {
+        let tx = db.begin_tx();
+        assert!(db.delete(tx, id.clone()).unwrap());
+        db.commit_tx(tx).unwrap();
+        let tx = db.begin_tx();
+        if data == "Hello" {
+            assert!(db.get_or_insert(tx, row(data)).unwrap().1);
+        } else {
+            db.insert_batch(tx, vec![row(data)]).unwrap();
+        }
+        assert!(db.version_chain_length(id.clone()).unwrap() <= 2);
+        db.commit_tx(tx).unwrap();
+    }
+
+    // And when the row is written with `read_modify_write`.
+    for data in ["Hello", "World"] {
+        let tx = db.begin_tx();
+        db.read_modify_write(tx, id.clone(), |_| Some(row(data)))
+            .unwrap();
+        assert!(db.version_chain_length(id.clone()).unwrap() <= 2);
+        db.commit_tx(tx).unwrap();
+    }
+
+    // And with `compare_and_swap`.
+    for (old, new) in [("World", "Hello"), ("Hello", "World")] {
+        let tx = db.begin_tx();
+        assert_eq!(
+            db.compare_and_swap(tx, id.clone(), &old.to_string(), row(new))
+                .unwrap(),
+            CasResult::Swapped
+        );
+        assert!(db.version_chain_length(id.clone()).unwrap() <= 2);
+        db.commit_tx(tx).unwrap();
+    }
+
+    // And with `update_if`.
+    for data in ["Hello", "World"] {
+        let tx = db.begin_tx();
+        assert_eq!(
+            db.update_if(tx, id.clone(), |_| true, row(data)).unwrap(),
+            ConditionalUpdateResult::Updated
+        );
+        assert!(db.version_chain_length(id.clone()).unwrap() <= 2);
+        db.commit_tx(tx).unwrap();
+    }
+}
+
+#[traced_test]
+#[test]
+fn test_swap_rows_version_chain_length() {
+    let db: Database<LocalClock, String> = DatabaseBuilder::new()
+        .gc_batch_size(0)
+        .max_version_chain_length(2)
+        .build();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let tx1 = db.begin_tx();
+    for (row_id, data) in [(1, "Hello"), (2, "World")] {
+        let row = Row {
+            id: id(row_id),
+            data: data.to_string(),
+        };
+        db.insert(tx1, row).unwrap();
+    }
+    db.commit_tx(tx1).unwrap();
+
+    // Both version chains are collected as soon as they grow past two
+    // versions, even though automatic garbage collection is disabled.
+    for _ in 0..3 {
+        let tx = db.begin_tx();
+        assert!(db.swap_rows(tx, id(1), id(2)).unwrap());
+        assert!(db.version_chain_length(id(1)).unwrap() <= 2);
+        assert!(db.version_chain_length(id(2)).unwrap() <= 2);
+        db.commit_tx(tx).unwrap();
+    }
+    assert_eq!(db.gc_stats().versions_dropped, 4);
+}
+
+#[traced_test]
+#[test]
+fn test_gc_stats() {
+    let mut db = test_db();
+    db.set_gc_batch_size(0);
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
     };
-    db.insert(tx1, tx1_row.clone()).unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx1_row, row);
 
-    // T2 attempts to delete row with ID 1, but fails because T1 has not committed.
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
     let tx2 = db.begin_tx();
-    let tx2_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "World".to_string(),
-    };
-    assert!(!db.update(tx2, tx2_row).unwrap());
+    db.update(tx2, row(1, "World")).unwrap();
+    db.delete(tx2, id(2)).unwrap();
+    db.commit_tx(tx2).unwrap();
 
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx1_row, row);
+    // An active transaction holds back the watermark.
+    let tx3 = db.begin_tx();
+    let stats = db.run_gc();
+    assert_eq!(stats.versions_dropped, 2);
+    assert_eq!(stats.rows_removed, 1);
+    assert_eq!(
+        stats.oldest_active_ts,
+        db.transaction_info(tx3).unwrap().begin_ts
+    );
+    assert_eq!(db.run_gc().versions_dropped, 0);
+
+    let totals = db.gc_stats();
+    assert_eq!(totals.versions_dropped, 2);
+    assert_eq!(totals.rows_removed, 1);
 }
 
 #[traced_test]
 #[test]
-fn test_dirty_read() {
+fn test_version_chain_stats() {
+    let mut db = test_db();
+    db.set_gc_batch_size(0);
+    assert_eq!(db.version_chain_stats(), VersionChainStats::default());
+
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    for data in ["World", "Goodbye"] {
+        let tx = db.begin_tx();
+        db.update(tx, row(1, data)).unwrap();
+        db.commit_tx(tx).unwrap();
+    }
+
+    assert_eq!(db.version_chain_length(id(1)).unwrap(), 3);
+    assert_eq!(db.version_chain_length(id(2)).unwrap(), 1);
+    assert_eq!(db.version_chain_length(id(3)).unwrap(), 0);
+    assert_eq!(db.max_version_chain_length().unwrap(), 3);
+    assert_eq!(
+        db.version_chain_stats(),
+        VersionChainStats {
+            min: 1,
+            max: 3,
+            mean: 2.0,
+            total_versions: 4,
+            total_rows: 2,
+        }
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_upsert() {
     let db = test_db();
+    let row = |data: &str| Row {
+        id: RowID::from_u64(1, 1),
+        data: data.to_string(),
+    };
 
-    // T1 inserts a row with ID 1, but does not commit.
     let tx1 = db.begin_tx();
-    let row1 = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "Hello".to_string(),
-    };
-    db.insert(tx1, row1).unwrap();
+    assert_eq!(
+        db.upsert(tx1, row("Hello")).unwrap(),
+        UpsertResult::Inserted
+    );
+    assert_eq!(db.upsert(tx1, row("World")).unwrap(), UpsertResult::Updated);
+    db.commit_tx(tx1).unwrap();
 
-    // T2 attempts to read row with ID 1, but doesn't see one because T1 has not committed.
     let tx2 = db.begin_tx();
-    let row2 = db
-        .read(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap();
-    assert_eq!(row2, None);
+    assert_eq!(db.read(tx2, row("").id).unwrap(), Some(row("World")));
 }
 
 #[traced_test]
 #[test]
-fn test_dirty_read_deleted() {
+fn test_read_only_tx() {
     let db = test_db();
-
-    // T1 inserts a row with ID 1 and commits.
-    let tx1 = db.begin_tx();
-    let tx1_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
+    let id = RowID::from_u64(1, 1);
+    let row = Row {
+        id: id.clone(),
         data: "Hello".to_string(),
     };
-    db.insert(tx1, tx1_row.clone()).unwrap();
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row.clone()).unwrap();
     db.commit_tx(tx1).unwrap();
 
-    // T2 deletes row with ID 1, but does not commit.
-    let tx2 = db.begin_tx();
-    assert!(db
-        .delete(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1
-            }
-        )
-        .unwrap());
+    let tx2 = db.begin_read_only_tx();
+    assert_eq!(db.read(tx2, id.clone()).unwrap(), Some(row.clone()));
+    assert_eq!(
+        Err(DatabaseError::ReadOnlyTransaction),
+        db.insert(tx2, row.clone())
+    );
+    assert_eq!(
+        Err(DatabaseError::ReadOnlyTransaction),
+        db.update(tx2, row.clone())
+    );
+    assert_eq!(
+        Err(DatabaseError::ReadOnlyTransaction),
+        db.delete(tx2, id.clone())
+    );
+    db.commit_tx(tx2).unwrap();
 
-    // T3 reads row with ID 1, but doesn't see the delete because T2 hasn't committed.
     let tx3 = db.begin_tx();
-    let row = db
-        .read(
-            tx3,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx1_row, row);
+    assert_eq!(db.read(tx3, id).unwrap(), Some(row));
 }
 
 #[traced_test]
 #[test]
-fn test_fuzzy_read() {
+fn test_savepoint() {
     let db = test_db();
-
-    // T1 inserts a row with ID 1 and commits.
-    let tx1 = db.begin_tx();
-    let tx1_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "Hello".to_string(),
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
     };
-    db.insert(tx1, tx1_row.clone()).unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx1_row, row);
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "World")).unwrap();
     db.commit_tx(tx1).unwrap();
 
-    // T2 reads the row with ID 1 within an active transaction.
     let tx2 = db.begin_tx();
-    let row = db
-        .read(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx1_row, row);
+    db.update(tx2, row(1, "Hello, world")).unwrap();
+    let sp1 = db.savepoint(tx2).unwrap();
+    db.update(tx2, row(1, "Goodbye")).unwrap();
+    db.delete(tx2, id(2)).unwrap();
+    db.insert(tx2, row(3, "New")).unwrap();
+    let sp2 = db.savepoint(tx2).unwrap();
+    db.delete(tx2, id(3)).unwrap();
+    assert_eq!(db.read(tx2, id(3)).unwrap(), None);
+
+    db.rollback_to_savepoint(tx2, sp2).unwrap();
+    assert_eq!(db.read(tx2, id(3)).unwrap(), Some(row(3, "New")));
+
+    db.rollback_to_savepoint(tx2, sp1).unwrap();
+    assert_eq!(db.read(tx2, id(1)).unwrap(), Some(row(1, "Hello, world")));
+    assert_eq!(db.read(tx2, id(2)).unwrap(), Some(row(2, "World")));
+    assert_eq!(db.read(tx2, id(3)).unwrap(), None);
+    assert_eq!(
+        Err(DatabaseError::NoSuchSavepoint(sp2)),
+        db.rollback_to_savepoint(tx2, sp2)
+    );
+    db.commit_tx(tx2).unwrap();
 
-    // T3 updates the row and commits.
     let tx3 = db.begin_tx();
-    let tx3_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "World".to_string(),
-    };
-    db.update(tx3, tx3_row).unwrap();
-    db.commit_tx(tx3).unwrap();
-
-    // T2 still reads the same version of the row as before.
-    let row = db
-        .read(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx1_row, row);
+    assert_eq!(db.read(tx3, id(1)).unwrap(), Some(row(1, "Hello, world")));
+    assert_eq!(db.read(tx3, id(2)).unwrap(), Some(row(2, "World")));
+    assert_eq!(db.read(tx3, id(3)).unwrap(), None);
 }
 
 #[traced_test]
 #[test]
-fn test_lost_update() {
+fn test_transaction() {
     let db = test_db();
+    let id = RowID::from_u64(1, 1);
+    let row = |data: &str| Row {
+        id: id.clone(),
+        data: data.to_string(),
+    };
 
-    // T1 inserts a row with ID 1 and commits.
+    db.transaction(|tx| db.insert(tx, row("Hello"))).unwrap();
+    assert_eq!(
+        Err(DatabaseError::Io("oops".to_string())),
+        db.transaction(|tx| {
+            db.update(tx, row("World"))?;
+            Err::<(), _>(DatabaseError::Io("oops".to_string()))
+        })
+    );
+    assert_eq!(
+        db.transaction(|tx| db.read(tx, id.clone())).unwrap(),
+        Some(row("Hello"))
+    );
+
+    // The first attempt conflicts with T1, which rolls back before the retry.
     let tx1 = db.begin_tx();
-    let tx1_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "Hello".to_string(),
+    db.update(tx1, row("Conflict")).unwrap();
+    let mut attempts = 0;
+    let result = db.transaction_with_retry(1, |tx| {
+        attempts += 1;
+        let result = db.update(tx, row("World"));
+        if attempts == 1 {
+            db.rollback_tx(tx1);
+        }
+        result
+    });
+    assert_eq!(result, Ok(true));
+    assert_eq!(attempts, 2);
+    assert_eq!(
+        db.transaction(|tx| db.read(tx, id.clone())).unwrap(),
+        Some(row("World"))
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_branch() {
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
     };
-    db.insert(tx1, tx1_row.clone()).unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx1_row, row);
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "Hello")).unwrap();
     db.commit_tx(tx1).unwrap();
 
-    // T2 attempts to update row ID 1 within an active transaction.
+    // Uncommitted changes are not part of the branch.
     let tx2 = db.begin_tx();
-    let tx2_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "World".to_string(),
+    db.insert(tx2, row(3, "Hello")).unwrap();
+    assert!(db.delete(tx2, id(2)).unwrap());
+
+    let branch = db.branch();
+
+    // Rows with only committed versions are shared until either database
+    // writes them.
+    let shared = |row_id| {
+        let a = db.rows.get(&id(row_id)).unwrap();
+        let b = branch.rows.get(&id(row_id)).unwrap();
+        let shared = Arc::ptr_eq(&a.value().read().versions, &b.value().read().versions);
+        shared
     };
-    assert!(db.update(tx2, tx2_row.clone()).unwrap());
+    assert!(!shared(2));
+    db.commit_tx(tx2).unwrap();
+    assert!(shared(1));
 
-    // T3 also attempts to update row ID 1 within an active transaction.
-    let tx3 = db.begin_tx();
-    let tx3_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "Hello, world!".to_string(),
+    let tx3 = branch.begin_tx();
+    assert_eq!(branch.read(tx3, id(1)).unwrap(), Some(row(1, "Hello")));
+    assert_eq!(branch.read(tx3, id(2)).unwrap(), Some(row(2, "Hello")));
+    assert_eq!(branch.read(tx3, id(3)).unwrap(), None);
+    assert!(branch.update(tx3, row(1, "Branch")).unwrap());
+    branch.commit_tx(tx3).unwrap();
+    assert!(!shared(1));
+
+    // Changes in one database are not visible in the other one.
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, id(1)).unwrap(), Some(row(1, "Hello")));
+    assert_eq!(db.read(tx4, id(2)).unwrap(), None);
+    assert_eq!(db.read(tx4, id(3)).unwrap(), Some(row(3, "Hello")));
+    let tx5 = branch.begin_tx();
+    assert_eq!(branch.read(tx5, id(1)).unwrap(), Some(row(1, "Branch")));
+}
+
+#[traced_test]
+#[test]
+fn test_merge() {
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
     };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // The branch changes row 1 and adds row 3, row 2 stays the same.
+    let branch = db.branch();
+    let tx2 = branch.begin_tx();
+    assert!(branch.update(tx2, row(1, "Branch")).unwrap());
+    branch.insert(tx2, row(3, "Branch")).unwrap();
+    branch.commit_tx(tx2).unwrap();
+
+    let stats = db.merge(branch).unwrap();
     assert_eq!(
-        Err(DatabaseError::WriteWriteConflict),
-        db.update(tx3, tx3_row)
+        stats,
+        MergeStats {
+            rows_inserted: 1,
+            rows_unchanged: 1,
+            conflicts: vec![id(1)],
+        }
     );
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.scan_rows(tx3).unwrap(),
+        vec![row(1, "Hello"), row(2, "Hello"), row(3, "Branch")]
+    );
+    db.commit_tx(tx3).unwrap();
 
-    db.commit_tx(tx2).unwrap();
-    assert_eq!(Err(DatabaseError::TxTerminated), db.commit_tx(tx3));
-
-    let tx4 = db.begin_tx();
-    let row = db
-        .read(
-            tx4,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx2_row, row);
+    // A row written by a transaction in progress makes the whole merge fail.
+    let branch = db.branch();
+    let tx4 = branch.begin_tx();
+    branch.insert(tx4, row(0, "Branch")).unwrap();
+    branch.commit_tx(tx4).unwrap();
+    let tx5 = db.begin_tx();
+    assert!(db.update(tx5, row(2, "World")).unwrap());
+    assert_eq!(
+        db.merge(branch),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx5 })
+    );
+    db.commit_tx(tx5).unwrap();
+    let tx6 = db.begin_tx();
+    assert_eq!(db.read(tx6, id(0)).unwrap(), None);
+    assert_eq!(db.read(tx6, id(2)).unwrap(), Some(row(2, "World")));
 }
 
-// Test for the visibility to check if a new transaction can see old committed values.
-// This test checks for the typo present in the paper, explained in https://github.com/penberg/mvcc-rs/issues/15
 #[traced_test]
 #[test]
-fn test_committed_visibility() {
+fn test_get_clock_value() {
     let db = test_db();
-
-    // let's add $10 to my account since I like money
     let tx1 = db.begin_tx();
-    let tx1_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
+    db.insert(
+        tx1,
+        Row {
+            id: RowID::from_u64(1, 1),
+            data: "Hello".to_string(),
         },
-        data: "10".to_string(),
-    };
-    db.insert(tx1, tx1_row.clone()).unwrap();
+    )
+    .unwrap();
     db.commit_tx(tx1).unwrap();
 
-    // but I like more money, so let me try adding $10 more
+    // Reading the clock does not advance it.
+    let ts = db.get_clock_value();
+    assert_eq!(db.get_clock_value(), ts);
+    // A snapshot at the returned timestamp sees the committed writes.
+    assert_eq!(
+        db.read_at_timestamp(RowID::from_u64(1, 1), ts)
+            .unwrap()
+            .unwrap()
+            .data,
+        "Hello"
+    );
     let tx2 = db.begin_tx();
-    let tx2_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
+    assert_eq!(db.transaction_info(tx2).unwrap().begin_ts, ts);
+}
+
+#[traced_test]
+#[test]
+fn test_transaction_info() {
+    let db = test_db();
+    let id = RowID::from_u64(1, 1);
+    let tx1 = db.begin_tx();
+    db.insert(
+        tx1,
+        Row {
+            id: id.clone(),
+            data: "Hello".to_string(),
         },
-        data: "20".to_string(),
-    };
-    assert!(db.update(tx2, tx2_row.clone()).unwrap());
-    let row = db
-        .read(
-            tx2,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(row, tx2_row);
+    )
+    .unwrap();
+    let tx2 = db.begin_tx();
+    db.read(tx2, id).unwrap();
 
-    // can I check how much money I have?
-    let tx3 = db.begin_tx();
-    let row = db
-        .read(
-            tx3,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap()
-        .unwrap();
-    assert_eq!(tx1_row, row);
+    let info = db.transaction_info(tx1).unwrap();
+    assert_eq!(info.tx_id, tx1);
+    assert_eq!(info.write_set_size, 1);
+    assert_eq!(info.read_set_size, 0);
+    assert_eq!(info.state, TransactionState::Active);
+    assert_eq!(
+        db.active_transactions()
+            .iter()
+            .map(|info| info.tx_id)
+            .collect::<Vec<_>>(),
+        vec![tx1, tx2]
+    );
+
+    db.commit_tx(tx1).unwrap();
+    assert_eq!(db.transaction_info(tx1), None);
+    assert_eq!(db.active_transactions().len(), 1);
 }
 
-// Test to check if a older transaction can see (un)committed future rows
 #[traced_test]
 #[test]
-fn test_future_row() {
+fn test_drop_table() {
     let db = test_db();
-
+    let row = |table_id, row_id| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: "Hello".to_string(),
+    };
     let tx1 = db.begin_tx();
+    for table_id in 1..=2 {
+        for row_id in 1..=3 {
+            db.insert(tx1, row(table_id, row_id)).unwrap();
+        }
+    }
+    db.commit_tx(tx1).unwrap();
 
+    // T2 drops table 1, while T3 keeps seeing its rows until T2 commits.
     let tx2 = db.begin_tx();
-    let tx2_row = Row {
-        id: RowID {
-            table_id: 1,
-            row_id: 1,
-        },
-        data: "10".to_string(),
-    };
-    db.insert(tx2, tx2_row).unwrap();
+    let tx3 = db.begin_tx();
+    db.drop_table(tx2, 1).unwrap();
+    assert!(db.scan_rows_for_table(tx2, 1).unwrap().is_empty());
+    assert_eq!(db.scan_rows_for_table(tx3, 1).unwrap().len(), 3);
+    db.commit_tx(tx2).unwrap();
+    assert_eq!(db.scan_rows_for_table(tx3, 1).unwrap().len(), 3);
 
-    // transaction in progress, so tx1 shouldn't be able to see the value
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap();
-    assert_eq!(row, None);
+    let tx4 = db.begin_tx();
+    assert!(db.scan_rows_for_table(tx4, 1).unwrap().is_empty());
+    assert_eq!(db.scan_rows_for_table(tx4, 2).unwrap().len(), 3);
 
-    // lets commit the transaction and check if tx1 can see it
-    db.commit_tx(tx2).unwrap();
-    let row = db
-        .read(
-            tx1,
-            RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-        )
-        .unwrap();
-    assert_eq!(row, None);
+    // Rolling back a dropped table restores its rows.
+    let tx5 = db.begin_tx();
+    db.drop_table(tx5, 2).unwrap();
+    db.rollback_tx(tx5);
+    let tx6 = db.begin_tx();
+    assert_eq!(db.scan_rows_for_table(tx6, 2).unwrap().len(), 3);
 }
 
 #[traced_test]
 #[test]
-fn test_storage1() {
-    let clock = LocalClock::new();
-    let mut path = std::env::temp_dir();
-    path.push(format!(
-        "mvcc-rs-storage-test-{}",
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos(),
-    ));
-    let storage = crate::persistent_storage::Storage::new_json_on_disk(path.clone());
-    let db = Database::new(clock, storage);
-
+fn test_multi_get() {
+    let db = test_db();
+    let row = |row_id| Row {
+        id: RowID::from_u64(1, row_id),
+        data: format!("Hello {row_id}"),
+    };
     let tx1 = db.begin_tx();
+    db.insert(tx1, row(1)).unwrap();
+    db.insert(tx1, row(2)).unwrap();
+    db.commit_tx(tx1).unwrap();
     let tx2 = db.begin_tx();
+    db.insert(tx2, row(3)).unwrap();
+
     let tx3 = db.begin_tx();
+    let ids: Vec<RowID> = [2, 4, 3, 1].into_iter().map(|id| row(id).id).collect();
+    assert_eq!(
+        db.multi_get(tx3, &ids).unwrap(),
+        vec![Some(row(2)), None, None, Some(row(1))]
+    );
+    assert_eq!(
+        db.multi_get(tx2, &ids).unwrap(),
+        vec![Some(row(2)), None, Some(row(3)), Some(row(1))]
+    );
+    let tx3 = db.txs.get(&tx3).unwrap();
+    assert_eq!(tx3.value().read().read_set.len(), 2);
+}
 
+#[traced_test]
+#[test]
+fn test_composite_row_keys() {
+    let db = test_db();
+    let order = |user_id: u64, order_id: u64| {
+        let mut key = user_id.to_be_bytes().to_vec();
+        key.extend_from_slice(&order_id.to_be_bytes());
+        RowID::new(1, key)
+    };
+    let tx1 = db.begin_tx();
+    for (user_id, order_id) in [(2, 1), (1, 2), (1, 1)] {
+        let row = Row {
+            id: order(user_id, order_id),
+            data: format!("{user_id}:{order_id}"),
+        };
+        db.insert(tx1, row).unwrap();
+    }
+    // The integer keys of other tables sort by value, up to the largest one.
     db.insert(
-        tx3,
+        tx1,
         Row {
-            id: RowID {
-                table_id: 1,
-                row_id: 1,
-            },
-            data: "testme".to_string(),
+            id: RowID::from_u64(2, u64::MAX),
+            data: "max".to_string(),
         },
     )
     .unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    let rows = db.scan_rows_for_table(tx2, 1).unwrap();
+    let data: Vec<&str> = rows.iter().map(|row| row.data.as_str()).collect();
+    assert_eq!(data, vec!["1:1", "1:2", "2:1"]);
+    assert_eq!(rows[0].id.as_u64(), None);
+    let rows = db.scan_rows_for_table(tx2, 2).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].id.as_u64(), Some(u64::MAX));
+    assert!(RowID::from_u64(1, 255) < RowID::from_u64(1, 256));
+}
 
+#[traced_test]
+#[test]
+fn test_scan_rows_in_range() {
+    let db = test_db();
+    let row = |table_id, row_id| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: "Hello".to_string(),
+    };
+    let tx1 = db.begin_tx();
+    for row_id in 1..=5 {
+        db.insert(tx1, row(1, row_id)).unwrap();
+    }
+    db.insert(tx1, row(2, 3)).unwrap();
     db.commit_tx(tx1).unwrap();
-    db.rollback_tx(tx2);
-    db.commit_tx(tx3).unwrap();
 
-    let tx4 = db.begin_tx();
-    db.insert(
-        tx4,
-        Row {
-            id: RowID {
-                table_id: 1,
-                row_id: 2,
-            },
-            data: "testme2".to_string(),
-        },
-    )
-    .unwrap();
-    db.insert(
-        tx4,
-        Row {
-            id: RowID {
-                table_id: 1,
-                row_id: 3,
-            },
-            data: "testme3".to_string(),
-        },
-    )
-    .unwrap();
+    // T2 inserts a row in the range, which T3 cannot see.
+    let tx2 = db.begin_tx();
+    db.insert(tx2, row(1, 6)).unwrap();
+    let tx3 = db.begin_tx();
 
+    // The start of the range is included and the end is excluded.
     assert_eq!(
-        db.read(
-            tx4,
-            RowID {
-                table_id: 1,
-                row_id: 1
-            }
-        )
-        .unwrap()
-        .unwrap()
-        .data,
-        "testme"
+        db.scan_rows_in_range(tx3, 1, 2, 4).unwrap(),
+        vec![row(1, 2), row(1, 3)]
     );
     assert_eq!(
-        db.read(
-            tx4,
-            RowID {
-                table_id: 1,
-                row_id: 2
-            }
-        )
-        .unwrap()
-        .unwrap()
-        .data,
-        "testme2"
+        db.scan_rows_in_range(tx3, 1, 5, 10).unwrap(),
+        vec![row(1, 5)]
     );
     assert_eq!(
-        db.read(
-            tx4,
-            RowID {
-                table_id: 1,
-                row_id: 3
-            }
-        )
-        .unwrap()
-        .unwrap()
-        .data,
+        db.scan_rows_in_range(tx2, 1, 5, 10).unwrap(),
+        vec![row(1, 5), row(1, 6)]
+    );
+    assert!(db.scan_rows_in_range(tx3, 1, 3, 3).unwrap().is_empty());
+}
+
+#[traced_test]
+#[test]
+fn test_iter_versions() {
+    let db = test_db();
+    let row = |data: &str| Row {
+        id: RowID::from_u64(1, 1),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row("Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    assert!(db.update(tx2, row("World")).unwrap());
+
+    let versions = db.iter_versions(row("").id).unwrap();
+    assert_eq!(versions.len(), 2);
+    assert!(matches!(versions[0].begin, TxTimestampOrID::Timestamp(_)));
+    assert_eq!(versions[0].end, Some(TxTimestampOrID::TxID(tx2)));
+    assert_eq!(versions[0].data, "Hello");
+    assert_eq!(versions[1].begin, TxTimestampOrID::TxID(tx2));
+    assert_eq!(versions[1].end, None);
+    assert_eq!(versions[1].data, "World");
+
+    let chain = db.print_version_chain(row("").id).unwrap();
+    assert!(chain.ends_with(&format!("[{tx2}, -) \"World\"\n")));
+    assert!(db.iter_versions(RowID::from_u64(1, 2)).unwrap().is_empty());
+}
+
+#[traced_test]
+#[test]
+fn test_lock_row() {
+    use futures::{FutureExt, StreamExt};
+
+    let storage = crate::persistent_storage::MemoryStorage::new();
+    let mut db = Database::new(LocalClock::new(), storage.clone());
+    db.set_gc_batch_size(0);
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    let mut events = Box::pin(db.subscribe(1));
+
+    let tx2 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    let tx3 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    db.lock_row(tx2, id(2)).unwrap();
+    db.lock_row(tx2, id(1)).unwrap();
+    // Locking a row twice is fine.
+    db.lock_row(tx2, id(1)).unwrap();
+    assert_eq!(db.version_chain_length(id(1)).unwrap(), 2);
+    assert_eq!(db.read(tx2, id(1)).unwrap(), Some(row(1, "Hello")));
+    assert_eq!(db.read(tx2, id(2)).unwrap(), None);
+    // Other transactions can still read the row, but not lock it.
+    assert_eq!(db.read(tx3, id(1)).unwrap(), Some(row(1, "Hello")));
+    assert!(!db.get_transaction_write_set(tx2).unwrap().contains(&id(1)));
+
+    // A lock-only commit leaves the version chain, the log, and the
+    // subscribers alone, and does not fail serializable readers of the row.
+    db.commit_tx(tx2).unwrap();
+    assert_eq!(db.version_chain_length(id(1)).unwrap(), 1);
+    assert!(db.rows.get(&id(2)).is_none());
+    assert_eq!(storage.len(), 1);
+    assert_eq!(events.next().now_or_never(), None);
+    db.commit_tx(tx3).unwrap();
+
+    let tx4 = db.begin_tx();
+    let tx5 = db.begin_tx();
+    db.lock_row(tx4, id(1)).unwrap();
+    assert_eq!(
+        db.lock_row(tx5, id(1)),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx4 })
+    );
+    let tx5 = db.begin_tx();
+    assert_eq!(
+        db.update(tx5, row(1, "Oops")),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx4 })
+    );
+
+    // The lock holder can update the row, and the update replaces the marker.
+    assert!(db.update(tx4, row(1, "World")).unwrap());
+    db.commit_tx(tx4).unwrap();
+    assert_eq!(db.version_chain_length(id(1)).unwrap(), 2);
+    assert_eq!(storage.len(), 2);
+    let tx6 = db.begin_tx();
+    assert_eq!(db.read(tx6, id(1)).unwrap(), Some(row(1, "World")));
+
+    // Rolling back removes the marker and releases the lock.
+    db.lock_row(tx6, id(1)).unwrap();
+    db.rollback_tx(tx6);
+    assert_eq!(db.version_chain_length(id(1)).unwrap(), 2);
+    let tx7 = db.begin_tx();
+    db.lock_row(tx7, id(1)).unwrap();
+    db.commit_tx(tx7).unwrap();
+}
+
+#[traced_test]
+#[test]
+fn test_compare_and_swap() {
+    let db = test_db();
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    assert_eq!(
+        db.compare_and_swap(tx2, row(2, "").id, &"Hello".to_string(), row(2, "World"))
+            .unwrap(),
+        CasResult::NotFound
+    );
+    assert_eq!(
+        db.compare_and_swap(tx2, row(1, "").id, &"World".to_string(), row(1, "World"))
+            .unwrap(),
+        CasResult::Mismatch(row(1, "Hello"))
+    );
+    assert_eq!(
+        db.compare_and_swap(tx2, row(1, "").id, &"Hello".to_string(), row(1, "World"))
+            .unwrap(),
+        CasResult::Swapped
+    );
+    assert_eq!(db.read(tx2, row(1, "").id).unwrap(), Some(row(1, "World")));
+
+    // Another transaction cannot swap the row until T2 finishes.
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.compare_and_swap(tx3, row(1, "").id, &"Hello".to_string(), row(1, "Again")),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx2 })
+    );
+    db.commit_tx(tx2).unwrap();
+
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, row(1, "").id).unwrap(), Some(row(1, "World")));
+    assert_eq!(
+        db.compare_and_swap(tx4, row(1, "").id, &"World".to_string(), row(2, "Again")),
+        Err(DatabaseError::RowIdMismatch {
+            expected: row(1, "").id,
+            actual: row(2, "").id,
+        })
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_update_if() {
+    let db = test_db();
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    let is_hello = |row: &Row<String>| row.data == "Hello";
+    assert_eq!(
+        db.update_if(tx2, row(2, "").id, is_hello, row(2, "World"))
+            .unwrap(),
+        ConditionalUpdateResult::NotFound
+    );
+    assert_eq!(
+        db.update_if(
+            tx2,
+            row(1, "").id,
+            |row| row.data.is_empty(),
+            row(1, "World")
+        )
+        .unwrap(),
+        ConditionalUpdateResult::PredicateFailed(row(1, "Hello"))
+    );
+    assert_eq!(
+        db.update_if(tx2, row(1, "").id, is_hello, row(1, "World"))
+            .unwrap(),
+        ConditionalUpdateResult::Updated
+    );
+    assert_eq!(db.read(tx2, row(1, "").id).unwrap(), Some(row(1, "World")));
+
+    // Another transaction cannot update the row until T2 finishes.
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.update_if(tx3, row(1, "").id, is_hello, row(1, "Again")),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx2 })
+    );
+    db.commit_tx(tx2).unwrap();
+
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, row(1, "").id).unwrap(), Some(row(1, "World")));
+    assert_eq!(
+        db.update_if(tx4, row(1, "").id, |_| true, row(2, "Again")),
+        Err(DatabaseError::RowIdMismatch {
+            expected: row(1, "").id,
+            actual: row(2, "").id,
+        })
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_read_modify_write() {
+    let db = test_db();
+    let row = |data: &str| Row {
+        id: RowID::from_u64(1, 1),
+        data: data.to_string(),
+    };
+    let append = |old: Option<Row<String>>| {
+        let data = old.map_or("a".to_string(), |old| format!("{}a", old.data));
+        Some(row(&data))
+    };
+    let tx1 = db.begin_tx();
+    assert_eq!(
+        db.read_modify_write(tx1, row("").id, |_| None).unwrap(),
+        None
+    );
+    assert_eq!(
+        db.read_modify_write(tx1, row("").id, append).unwrap(),
+        Some(row("a"))
+    );
+    assert_eq!(
+        db.read_modify_write(tx1, row("").id, append).unwrap(),
+        Some(row("aa"))
+    );
+    db.commit_tx(tx1).unwrap();
+
+    // Another transaction cannot modify the row until T2 finishes.
+    let tx2 = db.begin_tx();
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.read_modify_write(tx2, row("").id, append).unwrap(),
+        Some(row("aaa"))
+    );
+    assert_eq!(
+        db.read_modify_write(tx3, row("").id, append),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx2 })
+    );
+    db.commit_tx(tx2).unwrap();
+
+    let tx4 = db.begin_tx();
+    assert_eq!(
+        db.read_modify_write(tx4, row("").id, |_| None).unwrap(),
+        None
+    );
+    assert_eq!(db.read(tx4, row("").id).unwrap(), None);
+    db.commit_tx(tx4).unwrap();
+
+    let tx5 = db.begin_tx();
+    assert_eq!(db.read(tx5, row("").id).unwrap(), None);
+    // A function that changes the row ID fails without writing anything.
+    let other_id = RowID::from_u64(1, 2);
+    assert_eq!(
+        db.read_modify_write(tx5, other_id.clone(), |_| Some(row("a"))),
+        Err(DatabaseError::RowIdMismatch {
+            expected: other_id.clone(),
+            actual: row("").id,
+        })
+    );
+    assert_eq!(db.version_chain_length(other_id).unwrap(), 0);
+    assert!(db.get_transaction_write_set(tx5).unwrap().is_empty());
+    db.commit_tx(tx5).unwrap();
+}
+
+#[traced_test]
+#[test]
+fn test_multi_table_scan() {
+    let db = test_db();
+    let row = |table_id, row_id| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: format!("Hello {row_id}"),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, 1)).unwrap();
+    db.insert(tx1, row(1, 2)).unwrap();
+    db.insert(tx1, row(2, 1)).unwrap();
+    db.insert(tx1, row(3, 1)).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    let tx3 = db.begin_tx();
+    db.delete(tx3, row(1, 1).id).unwrap();
+    db.insert(tx3, row(2, 2)).unwrap();
+    db.commit_tx(tx3).unwrap();
+
+    let tables = db.multi_table_scan(tx2, &[1, 2, 4, 1]).unwrap();
+    assert_eq!(tables.len(), 3);
+    assert_eq!(tables[&1], vec![row(1, 1), row(1, 2)]);
+    assert_eq!(tables[&2], vec![row(2, 1)]);
+    assert!(tables[&4].is_empty());
+
+    let tx4 = db.begin_tx();
+    let tables = db.multi_table_scan(tx4, &[1, 2]).unwrap();
+    assert_eq!(tables[&1], vec![row(1, 2)]);
+    assert_eq!(tables[&2], vec![row(2, 1), row(2, 2)]);
+}
+
+#[traced_test]
+#[test]
+fn test_scan_rows_ordered() {
+    let db = test_db();
+    let row = |table_id, row_id, data: &str| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, 1, "b:1")).unwrap();
+    db.insert(tx1, row(1, 2, "a:2")).unwrap();
+    db.insert(tx1, row(1, 3, "b:3")).unwrap();
+    db.insert(tx1, row(1, 4, "a:4")).unwrap();
+    db.insert(tx1, row(1, 5, "a:5")).unwrap();
+    db.insert(tx1, row(2, 1, "a:1")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    db.delete(tx2, row(1, 5, "").id).unwrap();
+    assert_eq!(
+        db.scan_rows_ordered(tx2, 1, ScanOrder::AscendingByRowId)
+            .unwrap(),
+        vec![
+            row(1, 1, "b:1"),
+            row(1, 2, "a:2"),
+            row(1, 3, "b:3"),
+            row(1, 4, "a:4"),
+        ]
+    );
+    assert_eq!(
+        db.scan_rows_ordered(tx2, 1, ScanOrder::DescendingByRowId)
+            .unwrap(),
+        vec![
+            row(1, 4, "a:4"),
+            row(1, 3, "b:3"),
+            row(1, 2, "a:2"),
+            row(1, 1, "b:1"),
+        ]
+    );
+    // Rows with the same prefix stay in row ID order.
+    let by_prefix = ScanOrder::ByDataField(Box::new(|row: &Row<String>| {
+        Cow::Borrowed(row.data.split(':').next().unwrap())
+    }));
+    assert_eq!(
+        db.scan_rows_ordered(tx2, 1, by_prefix).unwrap(),
+        vec![
+            row(1, 2, "a:2"),
+            row(1, 4, "a:4"),
+            row(1, 1, "b:1"),
+            row(1, 3, "b:3"),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_find_rows() {
+    let db = test_db();
+    let row = |table_id, row_id, data: &str| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, 1, "foo")).unwrap();
+    db.insert(tx1, row(1, 2, "bar")).unwrap();
+    db.insert(tx1, row(1, 3, "foobar")).unwrap();
+    db.insert(tx1, row(2, 1, "foo")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    db.delete(tx2, row(1, 1, "").id).unwrap();
+    let contains_foo = |row: &Row<String>| row.data.contains("foo");
+    assert_eq!(
+        db.find_rows(tx2, 1, contains_foo).unwrap(),
+        vec![row(1, 3, "foobar")]
+    );
+    assert_eq!(
+        db.find_first_row(tx2, 1, |row| row.data.starts_with("ba"))
+            .unwrap(),
+        Some(row(1, 2, "bar"))
+    );
+    assert_eq!(db.find_first_row(tx2, 1, |_| false).unwrap(), None);
+
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.find_rows(tx3, 1, contains_foo).unwrap(),
+        vec![row(1, 1, "foo"), row(1, 3, "foobar")]
+    );
+    assert_eq!(
+        db.find_first_row(tx3, 1, contains_foo).unwrap(),
+        Some(row(1, 1, "foo"))
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_swap_rows() {
+    let db = test_db();
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "World")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    assert!(!db.swap_rows(tx2, row(1, "").id, row(3, "").id).unwrap());
+    assert!(db.swap_rows(tx2, row(2, "").id, row(1, "").id).unwrap());
+    assert_eq!(db.read(tx2, row(1, "").id).unwrap(), Some(row(1, "World")));
+    assert_eq!(db.read(tx2, row(2, "").id).unwrap(), Some(row(2, "Hello")));
+
+    // Another transaction cannot swap the rows until T2 finishes.
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.swap_rows(tx3, row(1, "").id, row(2, "").id),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx2 })
+    );
+    db.commit_tx(tx2).unwrap();
+
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, row(1, "").id).unwrap(), Some(row(1, "World")));
+    assert_eq!(db.read(tx4, row(2, "").id).unwrap(), Some(row(2, "Hello")));
+}
+
+#[traced_test]
+#[test]
+fn test_count_versions() {
+    let db = test_db();
+    let row = |table_id, row_id, data: &str| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: data.to_string(),
+    };
+    assert_eq!(db.count_versions(1).unwrap(), VersionCountStats::default());
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, 1, "Hello")).unwrap();
+    db.insert(tx1, row(1, 2, "Hello")).unwrap();
+    db.insert(tx1, row(2, 1, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    // Keep the old versions around.
+    let _tx2 = db.begin_tx();
+    let tx3 = db.begin_tx();
+    db.update(tx3, row(1, 1, "World")).unwrap();
+    db.commit_tx(tx3).unwrap();
+    let tx4 = db.begin_tx();
+    db.delete(tx4, row(1, 2, "").id).unwrap();
+
+    assert_eq!(
+        db.count_versions(1).unwrap(),
+        VersionCountStats {
+            current_versions: 1,
+            deleted_versions: 1,
+            in_flight_versions: 1,
+            total_rows: 2,
+        }
+    );
+    assert_eq!(db.count_versions(2).unwrap().current_versions, 1);
+}
+
+#[traced_test]
+#[test]
+fn test_with_transaction() {
+    use futures::FutureExt;
+
+    let db = &test_db();
+    let row = |data: &str| Row {
+        id: RowID::from_u64(1, 1),
+        data: data.to_string(),
+    };
+    let result = futures::executor::block_on(db.with_transaction(|tx_id| async move {
+        db.insert(tx_id, row("Hello"))?;
+        Ok(42)
+    }));
+    assert_eq!(result, Ok(42));
+
+    let result: Result<()> = futures::executor::block_on(db.with_transaction(|tx_id| async move {
+        db.update(tx_id, row("World"))?;
+        Err(DatabaseError::TxTerminated)
+    }));
+    assert_eq!(result, Err(DatabaseError::TxTerminated));
+
+    // A transaction whose future is dropped before completing is rolled back.
+    let pending = db.with_transaction(|tx_id| async move {
+        db.update(tx_id, row("World"))?;
+        futures::future::pending::<Result<()>>().await
+    });
+    assert_eq!(pending.now_or_never(), None);
+    assert!(db.txs.is_empty());
+
+    let tx = db.begin_tx();
+    assert_eq!(db.read(tx, row("").id).unwrap(), Some(row("Hello")));
+}
+
+#[traced_test]
+#[test]
+fn test_with_snapshot() {
+    let db = test_db();
+    let row = |data: &str| Row {
+        id: RowID::from_u64(1, 1),
+        data: data.to_string(),
+    };
+    db.transaction(|tx| db.insert(tx, row("Hello"))).unwrap();
+
+    let result = db.with_snapshot(|tx| db.read(tx, row("").id));
+    assert_eq!(result, Ok(Some(row("Hello"))));
+    assert!(db.txs.is_empty());
+
+    let result = db.with_snapshot(|tx| db.update(tx, row("World")));
+    assert_eq!(result, Err(DatabaseError::ReadOnlyTransaction));
+    assert!(db.txs.is_empty());
+
+    // A transaction whose closure panics is rolled back.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        db.with_snapshot(|_| -> Result<()> { panic!("oops") })
+    }));
+    assert!(result.is_err());
+    assert!(db.txs.is_empty());
+}
+
+#[traced_test]
+#[test]
+fn test_transaction_read_and_write_sets() {
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "World")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    db.read(tx2, id(1)).unwrap();
+    db.read(tx2, id(3)).unwrap();
+    db.update(tx2, row(2, "Again")).unwrap();
+    db.insert(tx2, row(3, "New")).unwrap();
+    assert_eq!(
+        db.get_transaction_read_set(tx2).unwrap(),
+        HashSet::from([id(1)])
+    );
+    assert_eq!(
+        db.get_transaction_write_set(tx2).unwrap(),
+        HashSet::from([id(2), id(3)])
+    );
+    db.commit_tx(tx2).unwrap();
+
+    assert_eq!(
+        db.get_transaction_read_set(tx2),
+        Err(DatabaseError::NoSuchTransactionID { tx_id: tx2 })
+    );
+    assert_eq!(
+        db.get_transaction_write_set(tx2),
+        Err(DatabaseError::NoSuchTransactionID { tx_id: tx2 })
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_prefetch() {
+    let db = test_db();
+    let tx1 = db.begin_tx();
+    db.insert(
+        tx1,
+        Row {
+            id: RowID::from_u64(1, 1),
+            data: "Hello".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let handle = db.prefetch(&[RowID::from_u64(1, 1), RowID::from_u64(1, 2)]);
+    assert!(handle.is_complete());
+    futures::executor::block_on(handle);
+}
+
+#[traced_test]
+#[test]
+fn test_hooks() {
+    let db = test_db();
+    let row = |row_id| Row {
+        id: RowID::from_u64(1, row_id),
+        data: "Hello".to_string(),
+    };
+    let events = Arc::new(Mutex::new(Vec::new()));
+    for event in [
+        HookEvent::BeforeCommit,
+        HookEvent::AfterCommit,
+        HookEvent::BeforeRollback,
+        HookEvent::AfterRollback,
+    ] {
+        let events = events.clone();
+        db.install_hook(
+            event,
+            Box::new(move |ctx| {
+                let record_len = ctx.log_record.map(|record| record.row_count());
+                events
+                    .lock()
+                    .push((event, ctx.tx_id, ctx.write_set.len(), record_len));
+                Ok(())
+            }),
+        );
+    }
+    let rejected = row(2).id;
+    db.install_hook(
+        HookEvent::BeforeCommit,
+        Box::new(move |ctx| {
+            if ctx.write_set.contains(&rejected) {
+                return Err(DatabaseError::CommitRejected("row 2".to_string()));
+            }
+            Ok(())
+        }),
+    );
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1)).unwrap();
+    db.commit_tx(tx1).unwrap();
+    assert_eq!(
+        std::mem::take(&mut *events.lock()),
+        vec![
+            (HookEvent::BeforeCommit, tx1, 1, None),
+            (HookEvent::AfterCommit, tx1, 1, Some(1)),
+        ]
+    );
+
+    let tx2 = db.begin_tx();
+    db.insert(tx2, row(2)).unwrap();
+    assert_eq!(
+        db.commit_tx(tx2),
+        Err(DatabaseError::CommitRejected("row 2".to_string()))
+    );
+    assert_eq!(
+        std::mem::take(&mut *events.lock()),
+        vec![
+            (HookEvent::BeforeCommit, tx2, 1, None),
+            (HookEvent::BeforeRollback, tx2, 1, None),
+            (HookEvent::AfterRollback, tx2, 1, None),
+        ]
+    );
+
+    let tx3 = db.begin_tx();
+    assert_eq!(db.read(tx3, row(2).id).unwrap(), None);
+}
+
+#[traced_test]
+#[test]
+fn test_foreign_key_check() {
+    let db = test_db();
+    let row = |table_id, row_id| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: "Hello".to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, 1)).unwrap();
+    db.insert(tx1, row(1, 2)).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    let tx3 = db.begin_tx();
+    db.delete(tx3, row(1, 2).id).unwrap();
+    db.commit_tx(tx3).unwrap();
+    let child_id = row(2, 1).id;
+    assert!(db.foreign_key_check(tx2, child_id.clone(), 1, 1).unwrap());
+    assert!(!db.foreign_key_check(tx2, child_id.clone(), 1, 3).unwrap());
+    // The parent is deleted, but not in the snapshot of T2.
+    assert!(db.foreign_key_check(tx2, child_id.clone(), 1, 2).unwrap());
+
+    let tx4 = db.begin_tx();
+    assert!(!db.foreign_key_check(tx4, child_id.clone(), 1, 2).unwrap());
+    assert!(db
+        .foreign_key_check_row(tx4, child_id.clone(), row(1, 1).id)
+        .unwrap());
+    assert!(!db
+        .foreign_key_check_row(tx4, child_id, row(1, 2).id)
+        .unwrap());
+}
+
+#[traced_test]
+#[test]
+fn test_copy_row() {
+    let db = test_db();
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "World")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    assert!(!db.copy_row(tx2, row(3, "").id, row(4, "").id).unwrap());
+    assert_eq!(
+        db.copy_row(tx2, row(1, "").id, row(2, "").id),
+        Err(DatabaseError::RowAlreadyExists(row(2, "").id))
+    );
+    assert!(db.copy_row(tx2, row(1, "").id, row(3, "").id).unwrap());
+    assert_eq!(db.read(tx2, row(3, "").id).unwrap(), Some(row(3, "Hello")));
+    db.commit_tx(tx2).unwrap();
+
+    let tx3 = db.begin_tx();
+    assert_eq!(db.read(tx3, row(1, "").id).unwrap(), Some(row(1, "Hello")));
+    assert_eq!(db.read(tx3, row(3, "").id).unwrap(), Some(row(3, "Hello")));
+}
+
+#[traced_test]
+#[test]
+fn test_rename_table() {
+    let db = test_db();
+    let row = |table_id, row_id| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: format!("Hello {row_id}"),
+    };
+    let tx1 = db.begin_tx();
+    for row_id in 1..=3 {
+        db.insert(tx1, row(1, row_id)).unwrap();
+    }
+    db.insert(tx1, row(3, 1)).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.rename_table(tx2, 1, 3),
+        Err(DatabaseError::TableNotEmpty(3))
+    );
+    db.rename_table(tx2, 1, 2).unwrap();
+    assert!(db.scan_rows_for_table(tx2, 1).unwrap().is_empty());
+    assert_eq!(db.scan_rows_for_table(tx3, 1).unwrap().len(), 3);
+    db.commit_tx(tx3).unwrap();
+    db.commit_tx(tx2).unwrap();
+
+    let tx4 = db.begin_tx();
+    assert!(db.scan_row_ids_for_table(tx4, 1).unwrap().is_empty());
+    assert_eq!(
+        db.scan_rows_for_table(tx4, 2).unwrap(),
+        vec![row(2, 1), row(2, 2), row(2, 3)]
+    );
+
+    // Renaming a table with rows that are being updated is a conflict.
+    assert!(db.delete(tx4, row(2, 1).id).unwrap());
+    let tx5 = db.begin_tx();
+    assert_eq!(
+        db.rename_table(tx5, 2, 1),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx4 })
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_truncate_table() {
+    let db = test_db();
+    let row = |table_id, row_id| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: "Hello".to_string(),
+    };
+    let tx1 = db.begin_tx();
+    for table_id in 1..=2 {
+        for row_id in 1..=3 {
+            db.insert(tx1, row(table_id, row_id)).unwrap();
+        }
+    }
+    db.commit_tx(tx1).unwrap();
+
+    db.truncate_table(1).unwrap();
+    let tx2 = db.begin_tx();
+    assert!(db.scan_rows_for_table(tx2, 1).unwrap().is_empty());
+    assert_eq!(db.scan_rows_for_table(tx2, 2).unwrap().len(), 3);
+
+    // Uncommitted inserts and deletes are not trashed.
+    db.insert(tx2, row(1, 4)).unwrap();
+    assert_eq!(
+        db.truncate_table(1),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx2 })
+    );
+    db.commit_tx(tx2).unwrap();
+    let tx3 = db.begin_tx();
+    assert!(db.delete(tx3, row(2, 1).id).unwrap());
+    assert_eq!(
+        db.truncate_table(2),
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx3 })
+    );
+    db.commit_tx(tx3).unwrap();
+
+    let tx4 = db.begin_tx();
+    assert_eq!(db.scan_rows_for_table(tx4, 1).unwrap(), vec![row(1, 4)]);
+    assert_eq!(db.scan_rows_for_table(tx4, 2).unwrap().len(), 2);
+}
+
+#[traced_test]
+#[test]
+fn test_rollback() {
+    let db = test_db();
+    let tx1 = db.begin_tx();
+    let row1 = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello".to_string(),
+    };
+    db.insert(tx1, row1.clone()).unwrap();
+    let row2 = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(row1, row2);
+    let row3 = Row {
+        id: RowID::from_u64(1, 1),
+        data: "World".to_string(),
+    };
+    db.update(tx1, row3.clone()).unwrap();
+    let row4 = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(row3, row4);
+    db.rollback_tx(tx1);
+    db.assert_no_version_leaks();
+    let tx2 = db.begin_tx();
+    let row5 = db.read(tx2, RowID::from_u64(1, 1)).unwrap();
+    assert_eq!(row5, None);
+}
+
+#[traced_test]
+#[test]
+fn test_rollback_delete() {
+    let db = test_db();
+    let tx1 = db.begin_tx();
+    let row1 = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello".to_string(),
+    };
+    db.insert(tx1, row1.clone()).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // T2 deletes the row, but rolls back, so the row is visible again.
+    let tx2 = db.begin_tx();
+    assert!(db.delete(tx2, row1.id.clone()).unwrap());
+    db.rollback_tx(tx2);
+    let tx3 = db.begin_tx();
+    assert_eq!(db.read(tx3, row1.id.clone()).unwrap(), Some(row1.clone()));
+
+    // T3 can delete the row in turn.
+    assert!(db.delete(tx3, row1.id.clone()).unwrap());
+    db.commit_tx(tx3).unwrap();
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, row1.id).unwrap(), None);
+}
+
+#[traced_test]
+#[test]
+fn test_dirty_write() {
+    let db = test_db();
+
+    // T1 inserts a row with ID 1, but does not commit.
+    let tx1 = db.begin_tx();
+    let tx1_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello".to_string(),
+    };
+    db.insert(tx1, tx1_row.clone()).unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx1_row, row);
+
+    // T2 attempts to delete row with ID 1, but fails because T1 has not committed.
+    let tx2 = db.begin_tx();
+    let tx2_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "World".to_string(),
+    };
+    assert!(!db.update(tx2, tx2_row).unwrap());
+
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx1_row, row);
+}
+
+#[traced_test]
+#[test]
+fn test_dirty_read() {
+    let db = test_db();
+
+    // T1 inserts a row with ID 1, but does not commit.
+    let tx1 = db.begin_tx();
+    let row1 = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello".to_string(),
+    };
+    db.insert(tx1, row1).unwrap();
+
+    // T2 attempts to read row with ID 1, but doesn't see one because T1 has not committed.
+    let tx2 = db.begin_tx();
+    let row2 = db.read(tx2, RowID::from_u64(1, 1)).unwrap();
+    assert_eq!(row2, None);
+}
+
+#[traced_test]
+#[test]
+fn test_dirty_read_deleted() {
+    let db = test_db();
+
+    // T1 inserts a row with ID 1 and commits.
+    let tx1 = db.begin_tx();
+    let tx1_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello".to_string(),
+    };
+    db.insert(tx1, tx1_row.clone()).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // T2 deletes row with ID 1, but does not commit.
+    let tx2 = db.begin_tx();
+    assert!(db.delete(tx2, RowID::from_u64(1, 1)).unwrap());
+
+    // T3 reads row with ID 1, but doesn't see the delete because T2 hasn't committed.
+    let tx3 = db.begin_tx();
+    let row = db.read(tx3, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx1_row, row);
+}
+
+#[traced_test]
+#[test]
+fn test_fuzzy_read() {
+    let db = test_db();
+
+    // T1 inserts a row with ID 1 and commits.
+    let tx1 = db.begin_tx();
+    let tx1_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello".to_string(),
+    };
+    db.insert(tx1, tx1_row.clone()).unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx1_row, row);
+    db.commit_tx(tx1).unwrap();
+
+    // T2 reads the row with ID 1 within an active transaction.
+    let tx2 = db.begin_tx();
+    let row = db.read(tx2, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx1_row, row);
+
+    // T3 updates the row and commits.
+    let tx3 = db.begin_tx();
+    let tx3_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "World".to_string(),
+    };
+    db.update(tx3, tx3_row).unwrap();
+    db.commit_tx(tx3).unwrap();
+
+    // T2 still reads the same version of the row as before.
+    let row = db.read(tx2, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx1_row, row);
+}
+
+#[traced_test]
+#[test]
+fn test_lost_update() {
+    let db = test_db();
+
+    // T1 inserts a row with ID 1 and commits.
+    let tx1 = db.begin_tx();
+    let tx1_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello".to_string(),
+    };
+    db.insert(tx1, tx1_row.clone()).unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx1_row, row);
+    db.commit_tx(tx1).unwrap();
+
+    // T2 attempts to update row ID 1 within an active transaction.
+    let tx2 = db.begin_tx();
+    let tx2_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "World".to_string(),
+    };
+    assert!(db.update(tx2, tx2_row.clone()).unwrap());
+
+    // T3 also attempts to update row ID 1 within an active transaction.
+    let tx3 = db.begin_tx();
+    let tx3_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello, world!".to_string(),
+    };
+    assert_eq!(
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx2 }),
+        db.update(tx3, tx3_row)
+    );
+
+    db.commit_tx(tx2).unwrap();
+    assert_eq!(Err(DatabaseError::TxTerminated), db.commit_tx(tx3));
+
+    let tx4 = db.begin_tx();
+    let row = db.read(tx4, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx2_row, row);
+}
+
+// Test for the visibility to check if a new transaction can see old committed values.
+// This test checks for the typo present in the paper, explained in https://github.com/penberg/mvcc-rs/issues/15
+#[traced_test]
+#[test]
+fn test_committed_visibility() {
+    let db = test_db();
+
+    // let's add $10 to my account since I like money
+    let tx1 = db.begin_tx();
+    let tx1_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "10".to_string(),
+    };
+    db.insert(tx1, tx1_row.clone()).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // but I like more money, so let me try adding $10 more
+    let tx2 = db.begin_tx();
+    let tx2_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "20".to_string(),
+    };
+    assert!(db.update(tx2, tx2_row.clone()).unwrap());
+    let row = db.read(tx2, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(row, tx2_row);
+
+    // can I check how much money I have?
+    let tx3 = db.begin_tx();
+    let row = db.read(tx3, RowID::from_u64(1, 1)).unwrap().unwrap();
+    assert_eq!(tx1_row, row);
+}
+
+// Test to check if a older transaction can see (un)committed future rows
+#[traced_test]
+#[test]
+fn test_future_row() {
+    let db = test_db();
+
+    let tx1 = db.begin_tx();
+
+    let tx2 = db.begin_tx();
+    let tx2_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "10".to_string(),
+    };
+    db.insert(tx2, tx2_row).unwrap();
+
+    // transaction in progress, so tx1 shouldn't be able to see the value
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap();
+    assert_eq!(row, None);
+
+    // lets commit the transaction and check if tx1 can see it
+    db.commit_tx(tx2).unwrap();
+    let row = db.read(tx1, RowID::from_u64(1, 1)).unwrap();
+    assert_eq!(row, None);
+}
+
+#[traced_test]
+#[test]
+fn test_scan_rows() {
+    let db = test_db();
+
+    // T1 inserts rows into tables 1 and 2 and commits.
+    let tx1 = db.begin_tx();
+    let tx1_rows: Vec<Row<String>> = [(1, 1), (1, 2), (2, 1)]
+        .into_iter()
+        .map(|(table_id, row_id)| Row {
+            id: RowID::from_u64(table_id, row_id),
+            data: format!("{table_id}:{row_id}"),
+        })
+        .collect();
+    for row in &tx1_rows {
+        db.insert(tx1, row.clone()).unwrap();
+    }
+    db.commit_tx(tx1).unwrap();
+
+    // T2 inserts another row into table 1, but does not commit.
+    let tx2 = db.begin_tx();
+    let tx2_row = Row {
+        id: RowID::from_u64(1, 3),
+        data: "1:3".to_string(),
+    };
+    db.insert(tx2, tx2_row.clone()).unwrap();
+
+    // T3 sees only the committed rows.
+    let tx3 = db.begin_tx();
+    assert_eq!(db.scan_rows(tx3).unwrap(), tx1_rows);
+    assert_eq!(db.scan_rows_for_table(tx3, 1).unwrap(), tx1_rows[..2]);
+    assert_eq!(db.scan_rows_for_table(tx3, 2).unwrap(), tx1_rows[2..]);
+
+    // T2 sees its own row, too.
+    let mut tx2_rows = tx1_rows[..2].to_vec();
+    tx2_rows.push(tx2_row);
+    assert_eq!(db.scan_rows_for_table(tx2, 1).unwrap(), tx2_rows);
+}
+
+#[traced_test]
+#[test]
+fn test_row_count() {
+    let db = test_db();
+    let id = |table_id, row_id| RowID::from_u64(table_id, row_id);
+    let row = |table_id, row_id| Row {
+        id: id(table_id, row_id),
+        data: "Hello".to_string(),
+    };
+    let tx1 = db.begin_tx();
+    for row_id in 1..=3 {
+        db.insert(tx1, row(1, row_id)).unwrap();
+    }
+    db.insert(tx1, row(2, 1)).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // T2 deletes a row and inserts another one, which only T2 sees.
+    let tx2 = db.begin_tx();
+    let tx3 = db.begin_tx();
+    assert!(db.delete(tx2, id(1, 1)).unwrap());
+    db.insert(tx2, row(1, 4)).unwrap();
+    db.insert(tx2, row(1, 5)).unwrap();
+    assert_eq!(db.row_count(tx2, 1).unwrap(), 4);
+    assert_eq!(db.row_count(tx3, 1).unwrap(), 3);
+    assert_eq!(db.row_count(tx3, 2).unwrap(), 1);
+    assert_eq!(db.row_count(tx3, 3).unwrap(), 0);
+}
+
+#[traced_test]
+#[test]
+fn test_scan_iter() {
+    let db = test_db();
+
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(3, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // T2 starts scanning the table.
+    let tx2 = db.begin_tx();
+    let mut iter = db.scan_iter(tx2, 1).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap(), row(1, "Hello"));
+
+    // T3 updates a row that T2 has not reached yet, inserts a new row, and commits.
+    let tx3 = db.begin_tx();
+    assert!(db.update(tx3, row(3, "World")).unwrap());
+    db.insert(tx3, row(2, "World")).unwrap();
+    db.commit_tx(tx3).unwrap();
+
+    // T2 keeps seeing its own snapshot.
+    assert_eq!(iter.next().unwrap().unwrap(), row(3, "Hello"));
+    assert!(iter.next().is_none());
+}
+
+#[traced_test]
+#[test]
+fn test_read_at_timestamp() {
+    let mut db = test_db();
+    // Keep old versions around for reading at past timestamps.
+    db.set_gc_batch_size(0);
+    let id = RowID::from_u64(1, 1);
+    let row = |data: &str| Row {
+        id: id.clone(),
+        data: data.to_string(),
+    };
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row("Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    let ts1 = db.get_timestamp();
+
+    let tx2 = db.begin_tx();
+    assert!(db.update(tx2, row("World")).unwrap());
+    db.commit_tx(tx2).unwrap();
+    let ts2 = db.get_timestamp();
+
+    // T3 deletes the row, but does not commit.
+    let tx3 = db.begin_tx();
+    assert!(db.delete(tx3, id.clone()).unwrap());
+
+    assert_eq!(db.read_at_timestamp(id.clone(), 0).unwrap(), None);
+    assert_eq!(
+        db.read_at_timestamp(id.clone(), ts1).unwrap(),
+        Some(row("Hello"))
+    );
+    assert_eq!(
+        db.read_at_timestamp(id.clone(), ts2).unwrap(),
+        Some(row("World"))
+    );
+    assert_eq!(
+        db.scan_rows_at_timestamp(1, ts1).unwrap(),
+        vec![row("Hello")]
+    );
+    assert_eq!(db.scan_rows_at_timestamp(2, ts1).unwrap(), vec![]);
+}
+
+// Test to check if a transaction that scanned a table is aborted when a
+// concurrent transaction inserts a row into that table and commits first.
+#[traced_test]
+#[test]
+fn test_phantom_read() {
+    let db = test_db();
+
+    // T1 scans table 1, which is empty, and table 2.
+    let tx1 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    assert!(db.scan_row_ids_for_table(tx1, 1).unwrap().is_empty());
+
+    // T2 scans only table 2.
+    let tx2 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    assert!(db.scan_row_ids_for_table(tx2, 2).unwrap().is_empty());
+
+    // T3 inserts a row into table 1 and commits.
+    let tx3 = db.begin_tx();
+    let tx3_row = Row {
+        id: RowID::from_u64(1, 1),
+        data: "Hello".to_string(),
+    };
+    db.insert(tx3, tx3_row).unwrap();
+    db.commit_tx(tx3).unwrap();
+
+    // T1's scan of table 1 now has a phantom, so T1 fails to commit.
+    assert_eq!(Err(DatabaseError::PhantomRead), db.commit_tx(tx1));
+    assert_eq!(Err(DatabaseError::TxTerminated), db.commit_tx(tx1));
+
+    // T2 did not scan table 1, so it commits just fine.
+    db.commit_tx(tx2).unwrap();
+}
+
+// Test for the "two doctors on call" write skew: both transactions read
+// both rows, but each of them updates a different one.
+#[traced_test]
+#[test]
+fn test_write_skew() {
+    let db = test_db();
+
+    let alice = RowID::from_u64(1, 1);
+    let bob = RowID::from_u64(1, 2);
+    let tx1 = db.begin_tx();
+    for id in [alice.clone(), bob.clone()] {
+        db.insert(
+            tx1,
+            Row {
+                id,
+                data: "on call".to_string(),
+            },
+        )
+        .unwrap();
+    }
+    db.commit_tx(tx1).unwrap();
+
+    // T2 and T3 both check that the other doctor is still on call.
+    let tx2 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    let tx3 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    for tx in [tx2, tx3] {
+        assert!(db.read(tx, alice.clone()).unwrap().is_some());
+        assert!(db.read(tx, bob.clone()).unwrap().is_some());
+    }
+
+    // T2 takes Alice off call, T3 takes Bob off call.
+    let off_call = |id| Row {
+        id,
+        data: "off call".to_string(),
+    };
+    assert!(db.update(tx2, off_call(alice.clone())).unwrap());
+    assert!(db.update(tx3, off_call(bob.clone())).unwrap());
+
+    // The first one to commit wins, the other one read a stale version.
+    db.commit_tx(tx2).unwrap();
+    assert_eq!(Err(DatabaseError::WriteSkewConflict), db.commit_tx(tx3));
+
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, alice).unwrap().unwrap().data, "off call");
+    assert_eq!(db.read(tx4, bob).unwrap().unwrap().data, "on call");
+}
+
+#[traced_test]
+#[test]
+fn test_assert_isolation() {
+    let value = |s: &str| s.to_string();
+    for level in [
+        IsolationLevel::ReadCommitted,
+        IsolationLevel::RepeatableRead,
+        IsolationLevel::SnapshotIsolation,
+        IsolationLevel::Serializable,
+    ] {
+        let db: Database<Arc<crate::clock::SimClock>, String> =
+            DatabaseBuilder::new().isolation_level(level).build();
+        let scenarios = [
+            IsolationScenario::DirtyRead {
+                id: RowID::from_u64(1, 1),
+                initial: value("Hello"),
+                update: value("World"),
+            },
+            IsolationScenario::FuzzyRead {
+                id: RowID::from_u64(1, 2),
+                initial: value("Hello"),
+                update: value("World"),
+            },
+            IsolationScenario::LostUpdate {
+                id: RowID::from_u64(1, 3),
+                initial: value("Hello"),
+                first: value("World"),
+                second: value("Hello, world!"),
+            },
+            IsolationScenario::PhantomRead {
+                row: Row {
+                    id: RowID::from_u64(2, 1),
+                    data: value("Hello"),
+                },
+            },
+            IsolationScenario::WriteSkew {
+                a: RowID::from_u64(3, 1),
+                b: RowID::from_u64(3, 2),
+                initial: value("on call"),
+                update: value("off call"),
+            },
+        ];
+        for scenario in scenarios {
+            db.assert_isolation(scenario).unwrap();
+        }
+    }
+}
+
+#[traced_test]
+#[test]
+fn test_serialization_failure() {
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let tx1 = db.begin_tx();
+    db.insert(
+        tx1,
+        Row {
+            id: id(1),
+            data: "Hello".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    assert!(db.read(tx2, id(1)).unwrap().is_some());
+    // A transaction that is committing concurrently, and whose write is not
+    // yet visible in the row versions, wrote the row T2 read.
+    let end_ts = db.get_timestamp();
+    db.recent_commits
+        .lock()
+        .insert(end_ts, HashSet::from([id(1)]), HashSet::new());
+    assert_eq!(Err(DatabaseError::SerializationFailure), db.commit_tx(tx2));
+
+    // Commits are forgotten once no transaction can be concurrent with them.
+    db.run_gc();
+    assert!(db.recent_commits.lock().commits.is_empty());
+}
+
+#[traced_test]
+#[test]
+fn test_serialization_failure_on_read_by_later_commit() {
+    let db = test_db();
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // Commits are only registered while a serializable transaction could
+    // still validate against them.
+    let tx2 = db.begin_tx();
+    db.insert(tx2, row(2, "World")).unwrap();
+    db.commit_tx(tx2).unwrap();
+    assert!(db.recent_commits.lock().commits.is_empty());
+
+    // T4 reads the row T3 writes, and commits first.
+    let tx3 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    let tx4 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    assert_eq!(
+        Some(row(1, "Hello")),
+        db.read(tx4, RowID::from_u64(1, 1)).unwrap()
+    );
+    db.insert(tx4, row(3, "!")).unwrap();
+    db.commit_tx(tx4).unwrap();
+    assert_eq!(1, db.recent_commits.lock().commits.len());
+    db.update(tx3, row(1, "Hi")).unwrap();
+    assert_eq!(Err(DatabaseError::SerializationFailure), db.commit_tx(tx3));
+
+    // The commit is dropped once no serializable transaction is in progress.
+    let tx5 = db.begin_tx();
+    db.update(tx5, row(1, "Hi")).unwrap();
+    db.commit_tx(tx5).unwrap();
+    assert!(db.recent_commits.lock().commits.is_empty());
+}
+
+#[traced_test]
+#[test]
+fn test_recent_commits_window() {
+    let clock = LocalClock::default();
+    let storage = crate::persistent_storage::MemoryStorage::new();
+    let db: Database<LocalClock, String> = DatabaseBuilder::new()
+        .clock(clock)
+        .storage(storage)
+        .recent_commits_window(1)
+        .build();
+    let row = |row_id| Row {
+        id: RowID::from_u64(1, row_id),
+        data: "Hello".to_string(),
+    };
+    let tx1 = db.begin_tx_with_isolation(IsolationLevel::Serializable);
+    db.insert(tx1, row(1)).unwrap();
+    for row_id in 2..4 {
+        let tx_id = db.begin_tx();
+        db.insert(tx_id, row(row_id)).unwrap();
+        db.commit_tx(tx_id).unwrap();
+    }
+    assert_eq!(1, db.recent_commits.lock().commits.len());
+    // T1 cannot be validated against the commit that was dropped.
+    assert_eq!(Err(DatabaseError::SerializationFailure), db.commit_tx(tx1));
+}
+
+// Snapshot isolation allows the "two doctors on call" write skew.
+#[traced_test]
+#[test]
+fn test_snapshot_isolation_write_skew() {
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "on call")).unwrap();
+    db.insert(tx1, row(2, "on call")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx_with_isolation(IsolationLevel::SnapshotIsolation);
+    let tx3 = db.begin_tx_with_isolation(IsolationLevel::SnapshotIsolation);
+    for tx in [tx2, tx3] {
+        assert!(db.read(tx, id(1)).unwrap().is_some());
+        assert!(db.read(tx, id(2)).unwrap().is_some());
+    }
+    assert!(db.update(tx2, row(1, "off call")).unwrap());
+    assert!(db.update(tx3, row(2, "off call")).unwrap());
+    db.commit_tx(tx2).unwrap();
+    db.commit_tx(tx3).unwrap();
+}
+
+#[traced_test]
+#[test]
+fn test_read_committed() {
+    let db = test_db();
+    let id = RowID::from_u64(1, 1);
+    let row = |data: &str| Row {
+        id: id.clone(),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row("Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx_with_isolation(IsolationLevel::ReadCommitted);
+    assert_eq!(db.read(tx2, id.clone()).unwrap(), Some(row("Hello")));
+
+    // T3 updates the row, but does not commit yet.
+    let tx3 = db.begin_tx();
+    assert!(db.update(tx3, row("World")).unwrap());
+    assert_eq!(db.read(tx2, id.clone()).unwrap(), Some(row("Hello")));
+
+    // Once T3 commits, T2 sees the new version.
+    db.commit_tx(tx3).unwrap();
+    assert_eq!(db.read(tx2, id.clone()).unwrap(), Some(row("World")));
+    db.commit_tx(tx2).unwrap();
+}
+
+// A read-committed transaction sees rows committed after it began, while a
+// snapshot isolation transaction does not.
+#[traced_test]
+#[test]
+fn test_read_committed_vs_snapshot_isolation() {
+    let db = test_db();
+    let id = RowID::from_u64(1, 1);
+    let row = Row {
+        id: id.clone(),
+        data: "Hello".to_string(),
+    };
+
+    let rc = db.begin_tx_with_isolation(IsolationLevel::ReadCommitted);
+    let si = db.begin_tx_with_isolation(IsolationLevel::SnapshotIsolation);
+    assert_eq!(db.read(rc, id.clone()).unwrap(), None);
+    assert_eq!(db.read(si, id.clone()).unwrap(), None);
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row.clone()).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    assert_eq!(db.read(rc, id.clone()).unwrap(), Some(row.clone()));
+    assert_eq!(db.scan_rows_for_table(rc, 1).unwrap(), vec![row]);
+    assert_eq!(db.read(si, id).unwrap(), None);
+    assert!(db.scan_rows_for_table(si, 1).unwrap().is_empty());
+
+    // The read set of a read-committed transaction is not validated.
+    db.commit_tx(rc).unwrap();
+}
+
+#[traced_test]
+#[test]
+fn test_snapshot_export_import() {
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    assert!(db.update(tx2, row(1, "World")).unwrap());
+    db.commit_tx(tx2).unwrap();
+
+    // Uncommitted changes are not part of the snapshot.
+    let tx3 = db.begin_tx();
+    db.insert(tx3, row(3, "Hello")).unwrap();
+
+    let snapshot = db.export_snapshot().unwrap();
+    assert_eq!(snapshot.rows, vec![row(1, "World"), row(2, "Hello")]);
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let snapshot: Snapshot<String> = serde_json::from_str(&json).unwrap();
+    let timestamp = snapshot.timestamp;
+
+    let db = Database::from_snapshot(LocalClock::new(), crate::persistent_storage::Noop, snapshot)
+        .unwrap();
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, id(1)).unwrap(), Some(row(1, "World")));
+    assert_eq!(db.read(tx4, id(2)).unwrap(), Some(row(2, "Hello")));
+    assert_eq!(db.read(tx4, id(3)).unwrap(), None);
+    assert!(db.get_timestamp() > timestamp);
+}
+
+#[traced_test]
+#[test]
+fn test_storage1() {
+    let clock = LocalClock::new();
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-storage-test-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+    ));
+    let storage = crate::persistent_storage::JsonOnDisk::new(path.clone());
+    let db = Database::new(clock, storage);
+
+    let tx1 = db.begin_tx();
+    let tx2 = db.begin_tx();
+    let tx3 = db.begin_tx();
+
+    db.insert(
+        tx3,
+        Row {
+            id: RowID::from_u64(1, 1),
+            data: "testme".to_string(),
+        },
+    )
+    .unwrap();
+
+    db.commit_tx(tx1).unwrap();
+    db.rollback_tx(tx2);
+    db.commit_tx(tx3).unwrap();
+
+    let tx4 = db.begin_tx();
+    db.insert(
+        tx4,
+        Row {
+            id: RowID::from_u64(1, 2),
+            data: "testme2".to_string(),
+        },
+    )
+    .unwrap();
+    db.insert(
+        tx4,
+        Row {
+            id: RowID::from_u64(1, 3),
+            data: "testme3".to_string(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        db.read(tx4, RowID::from_u64(1, 1)).unwrap().unwrap().data,
+        "testme"
+    );
+    assert_eq!(
+        db.read(tx4, RowID::from_u64(1, 2)).unwrap().unwrap().data,
+        "testme2"
+    );
+    assert_eq!(
+        db.read(tx4, RowID::from_u64(1, 3)).unwrap().unwrap().data,
+        "testme3"
+    );
+    db.commit_tx(tx4).unwrap();
+
+    let clock = LocalClock::new();
+    let storage = crate::persistent_storage::JsonOnDisk::new(path);
+    let db: Database<LocalClock, String> = Database::new(clock, storage);
+    db.recover().unwrap();
+    println!("{:#?}", db);
+
+    let tx5 = db.begin_tx();
+    println!("{:#?}", db.read(tx5, RowID::from_u64(1, 1)));
+    assert_eq!(
+        db.read(tx5, RowID::from_u64(1, 1)).unwrap().unwrap().data,
+        "testme"
+    );
+    assert_eq!(
+        db.read(tx5, RowID::from_u64(1, 2)).unwrap().unwrap().data,
+        "testme2"
+    );
+    assert_eq!(
+        db.read(tx5, RowID::from_u64(1, 3)).unwrap().unwrap().data,
         "testme3"
     );
+}
+
+#[cfg(feature = "csv_import")]
+#[traced_test]
+#[test]
+fn test_import_csv() {
+    let db = test_db();
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(2, "Existing")).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    let csv = "id,data\n1,Hello\n2,World\nthree,Oops\n3,\"Hello, World\"\n4\n5,a,b\n1,Again\n";
+    let stats = db.import_csv(tx2, 1, csv).unwrap();
+    assert_eq!(
+        stats,
+        ImportStats {
+            rows_inserted: 2,
+            rows_skipped: 6,
+        }
+    );
+    db.commit_tx(tx2).unwrap();
+
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.scan_rows_for_table(tx3, 1).unwrap(),
+        vec![row(1, "Hello"), row(2, "Existing"), row(3, "Hello, World")]
+    );
+}
+
+#[cfg(feature = "csv_import")]
+#[traced_test]
+#[test]
+fn test_export_csv() {
+    let db = test_db();
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(2, "Hello, World")).unwrap();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(3, "Hello\nWorld")).unwrap();
+    db.insert(tx1, row(4, "Deleted")).unwrap();
+    db.insert(
+        tx1,
+        Row {
+            id: RowID::new(1, *b"key"),
+            data: "Keyed".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    let tx2 = db.begin_tx();
+    db.delete(tx2, row(4, "").id).unwrap();
+    assert_eq!(
+        db.export_csv(tx2, 1).unwrap(),
+        "table_id,row_id,data\n\
+         1,1,Hello\n\
+         1,2,\"Hello, World\"\n\
+         1,3,\"Hello\nWorld\"\n\
+         1,0x6b6579,Keyed\n"
+    );
+    assert_eq!(db.export_csv(tx2, 2).unwrap(), "table_id,row_id,data\n");
+
+    // The exported rows can be imported back, skipping the header.
+    let tx3 = db.begin_tx();
+    let csv = db.export_csv(tx2, 1).unwrap();
+    let stats = db.import_csv(tx3, 2, &csv).unwrap();
+    assert_eq!(
+        stats,
+        ImportStats {
+            rows_inserted: 4,
+            rows_skipped: 1,
+        }
+    );
+    db.commit_tx(tx2).unwrap();
+    db.commit_tx(tx3).unwrap();
+    let tx4 = db.begin_tx();
+    let exported = db.export_csv(tx4, 2).unwrap();
+    assert_eq!(exported.replace("\n2,", "\n1,"), csv);
+}
+
+#[traced_test]
+#[test]
+fn test_storage_error_source() {
+    use std::error::Error;
+
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-storage-error-test-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+    ));
+    let storage = crate::persistent_storage::JsonOnDisk::new(path);
+    let err = Storage::<String>::read_tx_log(&storage).unwrap_err();
+    assert!(matches!(err, DatabaseError::StorageError(_)));
+    let source = err.source().unwrap();
+    let source = source.downcast_ref::<std::io::Error>().unwrap();
+    assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[traced_test]
+#[test]
+fn test_storage_wal() {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-storage-wal-test-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+    ));
+    let storage = crate::persistent_storage::WalStorage::new(path.clone());
+    let db = Database::new(LocalClock::new(), storage);
+
+    for row_id in 1..=3 {
+        let tx = db.begin_tx();
+        db.insert(
+            tx,
+            Row {
+                id: RowID::from_u64(1, row_id),
+                data: format!("testme{row_id}"),
+            },
+        )
+        .unwrap();
+        db.commit_tx(tx).unwrap();
+    }
+
+    // Simulate a crash in the middle of appending a frame.
+    {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&[100, 0, 0, 0, b'{']).unwrap();
+    }
+
+    let storage = crate::persistent_storage::WalStorage::new(path.clone());
+    let db: Database<LocalClock, String> = Database::new(LocalClock::new(), storage);
+    db.recover().unwrap();
+
+    let tx = db.begin_tx();
+    for row_id in 1..=3 {
+        let row = db.read(tx, RowID::from_u64(1, row_id)).unwrap().unwrap();
+        assert_eq!(row.data, format!("testme{row_id}"));
+    }
+    db.commit_tx(tx).unwrap();
+
+    // The truncated frame is dropped before the next one is appended.
+    let tx = db.begin_tx();
+    db.insert(
+        tx,
+        Row {
+            id: RowID::from_u64(1, 4),
+            data: "testme4".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx).unwrap();
+
+    let storage = crate::persistent_storage::WalStorage::new(path);
+    let db: Database<LocalClock, String> = Database::new(LocalClock::new(), storage);
+    db.recover().unwrap();
+
+    let tx = db.begin_tx();
+    for row_id in 1..=4 {
+        let row = db.read(tx, RowID::from_u64(1, row_id)).unwrap().unwrap();
+        assert_eq!(row.data, format!("testme{row_id}"));
+    }
+}
+
+#[traced_test]
+#[test]
+fn test_storage_bincode() {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-storage-bincode-test-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+    ));
+    let storage = crate::persistent_storage::BincodeOnDisk::new(path.clone());
+    let db = Database::new(LocalClock::new(), storage);
+
+    let id = RowID::from_u64(1, 1);
+    let tx1 = db.begin_tx();
+    db.insert(
+        tx1,
+        Row {
+            id: id.clone(),
+            data: "Hello".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    db.update(
+        tx2,
+        Row {
+            id: id.clone(),
+            data: "World".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx2).unwrap();
+
+    let storage = crate::persistent_storage::BincodeOnDisk::new(path);
+    let db: Database<LocalClock, String> = Database::new(LocalClock::new(), storage);
+    db.recover().unwrap();
+
+    let tx = db.begin_tx();
+    assert_eq!(db.read(tx, id).unwrap().unwrap().data, "World");
+}
+
+#[cfg(feature = "rocksdb")]
+#[traced_test]
+#[test]
+fn test_storage_rocksdb() {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-storage-rocksdb-test-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+    ));
+    let storage = crate::persistent_storage::RocksDbStorage::open(&path).unwrap();
+    let db = Database::new(LocalClock::new(), storage);
+
+    let id = RowID::from_u64(1, 1);
+    let tx1 = db.begin_tx();
+    db.insert(
+        tx1,
+        Row {
+            id: id.clone(),
+            data: "Hello".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    db.update(
+        tx2,
+        Row {
+            id: id.clone(),
+            data: "World".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx2).unwrap();
+    // RocksDB allows only one open instance per directory.
+    drop(db);
+
+    let storage = crate::persistent_storage::RocksDbStorage::open(&path).unwrap();
+    let db: Database<LocalClock, String> = Database::new(LocalClock::new(), storage);
+    db.recover().unwrap();
+
+    let tx = db.begin_tx();
+    assert_eq!(db.read(tx, id).unwrap().unwrap().data, "World");
+}
+
+#[traced_test]
+#[test]
+fn test_storage_memory() {
+    let log = crate::persistent_storage::MemoryStorage::new();
+    let db = Database::new(LocalClock::new(), log.clone());
+
+    let id = RowID::from_u64(1, 1);
+    let tx1 = db.begin_tx();
+    db.insert(
+        tx1,
+        Row {
+            id: id.clone(),
+            data: "Hello".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx1).unwrap();
+    // Read-only transactions are not logged.
+    let tx2 = db.begin_tx();
+    db.read(tx2, id.clone()).unwrap();
+    db.commit_tx(tx2).unwrap();
+    assert_eq!(log.len(), 1);
+    let deleted_id = RowID::from_u64(1, 2);
+    let tx3 = db.begin_tx();
+    db.insert(
+        tx3,
+        Row {
+            id: deleted_id.clone(),
+            data: "World".to_string(),
+        },
+    )
+    .unwrap();
+    db.commit_tx(tx3).unwrap();
+    let tx4 = db.begin_tx();
+    db.delete(tx4, deleted_id.clone()).unwrap();
     db.commit_tx(tx4).unwrap();
 
-    let clock = LocalClock::new();
-    let storage = crate::persistent_storage::Storage::new_json_on_disk(path);
-    let db: Database<LocalClock, String> = Database::new(clock, storage);
+    let db: Database<LocalClock, String> = Database::new(LocalClock::new(), log);
     db.recover().unwrap();
-    println!("{:#?}", db);
+    let tx = db.begin_tx();
+    assert_eq!(db.read(tx, id).unwrap().unwrap().data, "Hello");
+    assert_eq!(db.read(tx, deleted_id).unwrap(), None);
+}
+
+#[traced_test]
+#[test]
+fn test_storage_replicated() {
+    use crate::persistent_storage::{JsonOnDisk, MemoryStorage, ReplicatedStorage};
+
+    let row = |row_id| Row {
+        id: RowID::from_u64(1, row_id),
+        data: "Hello".to_string(),
+    };
+    // Writing to a file in a directory that does not exist fails.
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-replicated-test-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+    ));
+    path.push("log");
+    let failing = || Box::new(JsonOnDisk::new(path.clone()));
+
+    let (log_a, log_b) = (MemoryStorage::new(), MemoryStorage::new());
+    let storage = ReplicatedStorage::new(vec![Box::new(log_a.clone()), Box::new(log_b.clone())]);
+    let db = Database::new(LocalClock::new(), storage);
+    db.transaction(|tx| db.insert(tx, row(1))).unwrap();
+    assert_eq!((log_a.len(), log_b.len()), (1, 1));
+
+    // By default, all backends must succeed.
+    let storage = ReplicatedStorage::new(vec![failing(), Box::new(MemoryStorage::new())]);
+    let db = Database::new(LocalClock::new(), storage);
+    let result = db.transaction(|tx| db.insert(tx, row(1)));
+    assert!(matches!(result, Err(DatabaseError::StorageError(_))));
+
+    // With a quorum of one, the failing backend is tolerated on writes and
+    // skipped on reads.
+    let log = MemoryStorage::new();
+    let storage =
+        ReplicatedStorage::new(vec![failing(), Box::new(log.clone())]).with_write_quorum(1);
+    let db = Database::new(LocalClock::new(), storage);
+    db.transaction(|tx| db.insert(tx, row(1))).unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(db.storage.read_tx_log().unwrap().len(), 1);
+}
+
+#[traced_test]
+#[test]
+fn test_checkpoint() {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-checkpoint-test-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+    ));
+    let storage = crate::persistent_storage::WalStorage::new(path.clone());
+    let db = Database::new(LocalClock::new(), storage);
+
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    for row_id in 1..=3 {
+        db.insert(tx1, row(row_id, "Hello")).unwrap();
+    }
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    db.update(tx2, row(1, "World")).unwrap();
+    db.delete(tx2, id(2)).unwrap();
+    db.commit_tx(tx2).unwrap();
+
+    // A read-write transaction in progress blocks the checkpoint, a read-only one doesn't.
+    let tx3 = db.begin_tx();
+    assert_eq!(db.checkpoint(), Err(DatabaseError::CheckpointBusy));
+    db.rollback_tx(tx3);
+    let tx4 = db.begin_read_only_tx();
+    db.checkpoint().unwrap();
+    db.commit_tx(tx4).unwrap();
+    assert!(db.storage.read_tx_log().unwrap().is_empty());
 
     let tx5 = db.begin_tx();
-    println!(
-        "{:#?}",
-        db.read(
-            tx5,
-            RowID {
-                table_id: 1,
-                row_id: 1
-            }
-        )
+    db.delete(tx5, id(3)).unwrap();
+    db.insert(tx5, row(4, "Hello")).unwrap();
+    db.commit_tx(tx5).unwrap();
+    assert_eq!(db.storage.read_tx_log().unwrap().len(), 1);
+
+    let storage = crate::persistent_storage::WalStorage::new(path);
+    let db: Database<LocalClock, String> = Database::new(LocalClock::new(), storage);
+    db.recover().unwrap();
+    let tx = db.begin_tx();
+    assert_eq!(db.read(tx, id(1)).unwrap().unwrap().data, "World");
+    assert_eq!(db.read(tx, id(2)).unwrap(), None);
+    assert_eq!(db.read(tx, id(3)).unwrap(), None);
+    assert_eq!(db.read(tx, id(4)).unwrap().unwrap().data, "Hello");
+}
+
+#[traced_test]
+#[test]
+fn test_replay_transaction_log() {
+    use crate::persistent_storage::{ReplayEvent, TransactionLog};
+
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "World")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    db.update(tx2, row(1, "Again")).unwrap();
+    db.commit_tx(tx2).unwrap();
+    let tx3 = db.begin_tx();
+    db.delete(tx3, id(2)).unwrap();
+    db.commit_tx(tx3).unwrap();
+
+    let mut records = db.storage.read_tx_log().unwrap();
+    let [ts1, ts2, ts3] = [0, 1, 2].map(|i| records[i].tx_timestamp());
+    // Replay orders the records by commit timestamp.
+    records.reverse();
+    let events: Vec<_> = TransactionLog::replay(records).collect();
+    assert_eq!(
+        events,
+        vec![
+            ReplayEvent::Insert {
+                row: row(1, "Hello"),
+                ts: ts1
+            },
+            ReplayEvent::Insert {
+                row: row(2, "World"),
+                ts: ts1
+            },
+            ReplayEvent::Delete {
+                row_id: id(1),
+                ts: ts2
+            },
+            ReplayEvent::Insert {
+                row: row(1, "Again"),
+                ts: ts2
+            },
+            ReplayEvent::Delete {
+                row_id: id(2),
+                ts: ts3
+            },
+        ]
     );
     assert_eq!(
-        db.read(
-            tx5,
-            RowID {
-                table_id: 1,
-                row_id: 1
-            }
-        )
-        .unwrap()
-        .unwrap()
-        .data,
-        "testme"
+        events[0].print_summary(),
+        format!("ts {ts1}: INSERT {:?} \"Hello\"", id(1))
     );
     assert_eq!(
-        db.read(
-            tx5,
-            RowID {
-                table_id: 1,
-                row_id: 2
-            }
-        )
-        .unwrap()
-        .unwrap()
-        .data,
-        "testme2"
+        events[4].print_summary(),
+        format!("ts {ts3}: DELETE {:?}", id(2))
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_vacuum() {
+    let mut db = test_db();
+    db.set_gc_batch_size(0);
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    for row_id in 1..=3 {
+        db.insert(tx1, row(row_id, "Hello")).unwrap();
+    }
+    db.commit_tx(tx1).unwrap();
+    for data in ["A", "B"] {
+        let tx = db.begin_tx();
+        db.update(tx, row(1, data)).unwrap();
+        db.commit_tx(tx).unwrap();
+    }
+    let tx4 = db.begin_tx();
+    db.delete(tx4, id(2)).unwrap();
+    db.commit_tx(tx4).unwrap();
+
+    // A read-write transaction in progress blocks the checkpoint.
+    let tx5 = db.begin_tx();
+    assert_eq!(db.vacuum(), Err(DatabaseError::CheckpointBusy));
+    db.rollback_tx(tx5);
+    assert_eq!(db.storage.read_tx_log().unwrap().len(), 4);
+
+    let stats = db.vacuum().unwrap();
+    assert_eq!(stats.versions_before, 5);
+    assert_eq!(stats.versions_after, 2);
+    assert!(stats.bytes_freed > 0);
+    assert!(db.storage.read_tx_log().unwrap().is_empty());
+
+    let tx6 = db.begin_tx();
+    assert_eq!(
+        db.scan_rows_for_table(tx6, 1).unwrap(),
+        vec![row(1, "B"), row(3, "Hello")]
     );
+}
+
+#[traced_test]
+#[test]
+fn test_compact_log() {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "mvcc-rs-compact-log-test-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos(),
+    ));
+    let storage = crate::persistent_storage::WalStorage::new(path.clone());
+    let db = Database::new(LocalClock::new(), storage);
+
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id, data: &str| Row {
+        id: id(row_id),
+        data: data.to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.insert(tx1, row(2, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    for data in ["A", "B", "C"] {
+        let tx = db.begin_tx();
+        db.update(tx, row(1, data)).unwrap();
+        db.commit_tx(tx).unwrap();
+    }
+    let tx5 = db.begin_tx();
+    db.delete(tx5, id(2)).unwrap();
+    db.commit_tx(tx5).unwrap();
+
+    // Only the last update of row 1 is kept, so the records of the
+    // first two updates are dropped.
     assert_eq!(
-        db.read(
-            tx5,
-            RowID {
-                table_id: 1,
-                row_id: 3
-            }
-        )
-        .unwrap()
-        .unwrap()
-        .data,
-        "testme3"
+        db.compact_log().unwrap(),
+        CompactionStats {
+            records_before: 5,
+            records_after: 3,
+            versions_dropped: 5,
+        }
+    );
+
+    let storage = crate::persistent_storage::WalStorage::new(path);
+    let db: Database<LocalClock, String> = Database::new(LocalClock::new(), storage);
+    db.recover().unwrap();
+    let tx = db.begin_tx();
+    assert_eq!(db.read(tx, id(1)).unwrap().unwrap().data, "C");
+    assert_eq!(db.read(tx, id(2)).unwrap(), None);
+    assert_eq!(db.estimate_row_count(1), 1);
+}
+
+#[traced_test]
+#[test]
+fn test_repair() {
+    let db = test_db();
+    let id = |row_id| RowID::from_u64(1, row_id);
+    let row = |row_id| Row {
+        id: id(row_id),
+        data: "Hello".to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1)).unwrap();
+    db.insert(tx1, row(2)).unwrap();
+    db.commit_tx(tx1).unwrap();
+
+    // T2 was in flight when the database crashed, T3 is still in progress.
+    let tx2 = db.begin_tx();
+    db.update(tx2, row(1)).unwrap();
+    db.insert(tx2, row(3)).unwrap();
+    let tx3 = db.begin_tx();
+    db.delete(tx3, id(2)).unwrap();
+    db.txs.remove(&tx2);
+    assert_eq!(
+        db.repair().unwrap(),
+        RepairReport {
+            versions_removed: 2,
+            deletions_undone: 1,
+            rows_removed: 1,
+        }
+    );
+    assert_eq!(db.version_chain_length(id(1)).unwrap(), 1);
+    assert_eq!(db.version_chain_length(id(3)).unwrap(), 0);
+
+    db.txs.remove(&tx3);
+    assert_eq!(
+        db.repair().unwrap(),
+        RepairReport {
+            deletions_undone: 1,
+            ..Default::default()
+        }
     );
+
+    let tx4 = db.begin_tx();
+    assert_eq!(db.read(tx4, id(1)).unwrap(), Some(row(1)));
+    assert_eq!(db.read(tx4, id(2)).unwrap(), Some(row(2)));
+    assert_eq!(db.read(tx4, id(3)).unwrap(), None);
+}
+
+#[traced_test]
+#[test]
+fn test_recover_clock() {
+    let log = crate::persistent_storage::MemoryStorage::new();
+    for (tx_timestamp, row_id) in [(5, 1), (3, 2)] {
+        let mut record = LogRecord::new(tx_timestamp, tx_timestamp - 1, 0, None);
+        record.row_versions.push(RowVersion {
+            begin: TxTimestampOrID::Timestamp(tx_timestamp),
+            end: None,
+            row: Row {
+                id: RowID::from_u64(1, row_id),
+                data: "Hello".to_string(),
+            },
+        });
+        log.log_tx(record).unwrap();
+    }
+    let db: Database<LocalClock, String> = Database::new(LocalClock::new(), log);
+    db.recover().unwrap();
+    assert_eq!(db.get_timestamp(), 6);
+}
+
+#[traced_test]
+#[test]
+fn test_log_record_metadata() {
+    let db = test_db();
+
+    let row = |row_id| Row {
+        id: RowID::from_u64(1, row_id),
+        data: "Hello".to_string(),
+    };
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1)).unwrap();
+    db.insert(tx1, row(2)).unwrap();
+    db.set_causality_token(tx1, [7; 16]).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1));
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    db.delete(tx2, row(1).id).unwrap();
+    db.commit_tx(tx2).unwrap();
+
+    let records: Vec<LogRecord<String>> = db.storage.read_tx_log().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].begin_ts(), 0);
+    assert_eq!(records[0].tx_timestamp(), 1);
+    assert!(records[0].duration_us() >= 1000);
+    assert_eq!(records[0].causality_token(), Some([7; 16]));
+    assert_eq!(records[0].row_count(), 2);
+    assert!(records[1].begin_ts() > records[0].tx_timestamp());
+    assert_eq!(records[1].causality_token(), None);
+    assert_eq!(records[1].row_count(), 1);
 }
 
 /* States described in the Hekaton paper *for serializability*:
@@ -827,8 +3233,15 @@ fn new_tx(tx_id: TxID, begin_ts: u64, state: TransactionState) -> RwLock<Transac
         state,
         tx_id,
         begin_ts,
+        isolation_level: IsolationLevel::SnapshotIsolation,
+        read_only: false,
         write_set: SkipSet::new(),
         read_set: SkipSet::new(),
+        predicate_set: SkipSet::new(),
+        lock_set: SkipSet::new(),
+        savepoints: Vec::new(),
+        begin_wall_time: Instant::now(),
+        causality_token: None,
     })
 }
 
@@ -836,90 +3249,87 @@ fn new_tx(tx_id: TxID, begin_ts: u64, state: TransactionState) -> RwLock<Transac
 #[test]
 fn test_snapshot_isolation_tx_visible1() {
     let txs: SkipMap<TxID, RwLock<Transaction>> = SkipMap::from_iter([
-        (1, new_tx(1, 1, TransactionState::Committed(2))),
-        (2, new_tx(2, 2, TransactionState::Committed(5))),
-        (3, new_tx(3, 3, TransactionState::Aborted)),
-        (5, new_tx(5, 5, TransactionState::Preparing)),
-        (6, new_tx(6, 6, TransactionState::Committed(10))),
-        (7, new_tx(7, 7, TransactionState::Active)),
+        (TxID(1), new_tx(TxID(1), 1, TransactionState::Committed(2))),
+        (TxID(2), new_tx(TxID(2), 2, TransactionState::Committed(5))),
+        (TxID(3), new_tx(TxID(3), 3, TransactionState::Aborted)),
+        (TxID(5), new_tx(TxID(5), 5, TransactionState::Preparing)),
+        (TxID(6), new_tx(TxID(6), 6, TransactionState::Committed(10))),
+        (TxID(7), new_tx(TxID(7), 7, TransactionState::Active)),
     ]);
 
-    let current_tx = new_tx(4, 4, TransactionState::Preparing);
-    let current_tx = current_tx.read().unwrap();
+    let current_tx = new_tx(TxID(4), 4, TransactionState::Preparing);
+    let current_tx = current_tx.read();
 
     let rv_visible = |begin: TxTimestampOrID, end: Option<TxTimestampOrID>| {
         let row_version = RowVersion {
             begin,
             end,
             row: Row {
-                id: RowID {
-                    table_id: 1,
-                    row_id: 1,
-                },
+                id: RowID::from_u64(1, 1),
                 data: "testme".to_string(),
             },
         };
         tracing::debug!("Testing visibility of {row_version:?}");
-        is_version_visible(&txs, &current_tx, &row_version)
+        is_version_visible(&txs, &current_tx, current_tx.begin_ts, &row_version).unwrap()
     };
 
     // begin visible:   transaction committed with ts < current_tx.begin_ts
     // end visible:     inf
-    assert!(rv_visible(TxTimestampOrID::TxID(1), None));
+    assert!(rv_visible(TxTimestampOrID::TxID(TxID(1)), None));
 
     // begin invisible: transaction committed with ts > current_tx.begin_ts
-    assert!(!rv_visible(TxTimestampOrID::TxID(2), None));
+    assert!(!rv_visible(TxTimestampOrID::TxID(TxID(2)), None));
 
     // begin invisible: transaction aborted
-    assert!(!rv_visible(TxTimestampOrID::TxID(3), None));
+    assert!(!rv_visible(TxTimestampOrID::TxID(TxID(3)), None));
 
     // begin visible:   timestamp < current_tx.begin_ts
     // end invisible:   transaction committed with ts > current_tx.begin_ts
     assert!(!rv_visible(
         TxTimestampOrID::Timestamp(0),
-        Some(TxTimestampOrID::TxID(1))
+        Some(TxTimestampOrID::TxID(TxID(1)))
     ));
 
     // begin visible:   timestamp < current_tx.begin_ts
     // end visible:     transaction committed with ts < current_tx.begin_ts
     assert!(rv_visible(
         TxTimestampOrID::Timestamp(0),
-        Some(TxTimestampOrID::TxID(2))
+        Some(TxTimestampOrID::TxID(TxID(2)))
     ));
 
     // begin visible:   timestamp < current_tx.begin_ts
     // end invisible:   transaction aborted
     assert!(!rv_visible(
         TxTimestampOrID::Timestamp(0),
-        Some(TxTimestampOrID::TxID(3))
+        Some(TxTimestampOrID::TxID(TxID(3)))
     ));
 
     // begin invisible: transaction preparing
-    assert!(!rv_visible(TxTimestampOrID::TxID(5), None));
+    assert!(!rv_visible(TxTimestampOrID::TxID(TxID(5)), None));
 
     // begin invisible: transaction committed with ts > current_tx.begin_ts
-    assert!(!rv_visible(TxTimestampOrID::TxID(6), None));
+    assert!(!rv_visible(TxTimestampOrID::TxID(TxID(6)), None));
 
     // begin invisible: transaction active
-    assert!(!rv_visible(TxTimestampOrID::TxID(7), None));
+    assert!(!rv_visible(TxTimestampOrID::TxID(TxID(7)), None));
 
     // begin invisible: transaction committed with ts > current_tx.begin_ts
-    assert!(!rv_visible(TxTimestampOrID::TxID(6), None));
+    assert!(!rv_visible(TxTimestampOrID::TxID(TxID(6)), None));
 
     // begin invisible:   transaction active
-    assert!(!rv_visible(TxTimestampOrID::TxID(7), None));
+    assert!(!rv_visible(TxTimestampOrID::TxID(TxID(7)), None));
 
     // begin visible:   timestamp < current_tx.begin_ts
     // end invisible:     transaction preparing
     assert!(!rv_visible(
         TxTimestampOrID::Timestamp(0),
-        Some(TxTimestampOrID::TxID(5))
+        Some(TxTimestampOrID::TxID(TxID(5)))
     ));
 
     // begin invisible: timestamp > current_tx.begin_ts
     assert!(!rv_visible(
         TxTimestampOrID::Timestamp(6),
-        Some(TxTimestampOrID::TxID(6))
+        Some(TxTimestampOrID::TxID(TxID(6)))
     ));
 
     // begin visible:   timestamp < current_tx.begin_ts
@@ -928,6 +3338,637 @@ fn test_snapshot_isolation_tx_visible1() {
     //                  (this is the https://avi.im/blag/2023/hekaton-paper-typo/ case, I believe!)
     assert!(rv_visible(
         TxTimestampOrID::Timestamp(0),
-        Some(TxTimestampOrID::TxID(7))
+        Some(TxTimestampOrID::TxID(TxID(7)))
+    ));
+}
+
+#[traced_test]
+#[test]
+fn test_preparing_tx_visibility() {
+    let txs: SkipMap<TxID, RwLock<Transaction>> = SkipMap::from_iter([
+        (TxID(1), new_tx(TxID(1), 1, TransactionState::Preparing)),
+        (TxID(2), new_tx(TxID(2), 2, TransactionState::Active)),
+    ]);
+
+    let rv_visible = |tx_id: TxID, begin: TxTimestampOrID, end: Option<TxTimestampOrID>| {
+        let row_version = RowVersion {
+            begin,
+            end,
+            row: Row {
+                id: RowID::from_u64(1, 1),
+                data: "testme".to_string(),
+            },
+        };
+        let tx = txs.get(&tx_id).unwrap();
+        let tx = tx.value().read();
+        is_version_visible(&txs, &tx, tx.begin_ts, &row_version).unwrap()
+    };
+
+    // A preparing transaction sees the versions it inserted...
+    assert!(rv_visible(TxID(1), TxTimestampOrID::TxID(TxID(1)), None));
+    // ...but not the ones it deleted.
+    assert!(!rv_visible(
+        TxID(1),
+        TxTimestampOrID::TxID(TxID(1)),
+        Some(TxTimestampOrID::TxID(TxID(1)))
+    ));
+    assert!(!rv_visible(
+        TxID(1),
+        TxTimestampOrID::Timestamp(0),
+        Some(TxTimestampOrID::TxID(TxID(1)))
+    ));
+
+    // Other transactions do not see the versions it inserted.
+    assert!(!rv_visible(TxID(2), TxTimestampOrID::TxID(TxID(1)), None));
+}
+
+#[traced_test]
+#[test]
+fn test_write_write_conflict_states() {
+    let txs: SkipMap<TxID, RwLock<Transaction>> = SkipMap::from_iter([
+        (TxID(1), new_tx(TxID(1), 1, TransactionState::Active)),
+        (TxID(2), new_tx(TxID(2), 2, TransactionState::Preparing)),
+        (TxID(3), new_tx(TxID(3), 3, TransactionState::Committed(5))),
+        (TxID(4), new_tx(TxID(4), 4, TransactionState::Aborted)),
+        (TxID(5), new_tx(TxID(5), 5, TransactionState::Terminated)),
+        (TxID(6), new_tx(TxID(6), 6, TransactionState::Active)),
+    ]);
+
+    let current_tx = txs.get(&TxID(6)).unwrap();
+    let current_tx = current_tx.value().read();
+
+    let conflicts = |end: Option<TxTimestampOrID>| {
+        let row_version = RowVersion {
+            begin: TxTimestampOrID::Timestamp(0),
+            end,
+            row: Row {
+                id: RowID::from_u64(1, 1),
+                data: "testme".to_string(),
+            },
+        };
+        is_write_write_conflict(&txs, &current_tx, &row_version).unwrap()
+    };
+
+    // The row is live.
+    assert_eq!(conflicts(None), None);
+    // The row was already updated by a committed transaction.
+    assert_eq!(conflicts(Some(TxTimestampOrID::Timestamp(5))), None);
+    // Another transaction is updating the row.
+    assert_eq!(
+        conflicts(Some(TxTimestampOrID::TxID(TxID(1)))),
+        Some(TxID(1))
+    );
+    // Another transaction updated the row and is committing.
+    assert_eq!(
+        conflicts(Some(TxTimestampOrID::TxID(TxID(2)))),
+        Some(TxID(2))
+    );
+    // The update already landed, so the row is closed.
+    assert_eq!(conflicts(Some(TxTimestampOrID::TxID(TxID(3)))), None);
+    assert_eq!(conflicts(Some(TxTimestampOrID::TxID(TxID(5)))), None);
+    // The update was aborted, so the row is still live.
+    assert_eq!(conflicts(Some(TxTimestampOrID::TxID(TxID(4)))), None);
+    // The current transaction updating the row again is not a conflict.
+    assert_eq!(conflicts(Some(TxTimestampOrID::TxID(TxID(6)))), None);
+}
+
+#[traced_test]
+#[test]
+fn test_unknown_tx_in_row_version() {
+    let txs: SkipMap<TxID, RwLock<Transaction>> =
+        SkipMap::from_iter([(TxID(1), new_tx(TxID(1), 1, TransactionState::Active))]);
+    let current_tx = txs.get(&TxID(1)).unwrap();
+    let current_tx = current_tx.value().read();
+    let row_version = RowVersion {
+        begin: TxTimestampOrID::TxID(TxID(2)),
+        end: Some(TxTimestampOrID::TxID(TxID(2))),
+        row: Row {
+            id: RowID::from_u64(1, 1),
+            data: "testme".to_string(),
+        },
+    };
+    assert!(matches!(
+        is_version_visible(&txs, &current_tx, current_tx.begin_ts, &row_version),
+        Err(DatabaseError::InternalStateError(_))
+    ));
+    assert!(matches!(
+        is_write_write_conflict(&txs, &current_tx, &row_version),
+        Err(DatabaseError::InternalStateError(_))
     ));
 }
+
+#[traced_test]
+#[test]
+fn test_row_version_state() {
+    let version = |begin, end| RowVersion {
+        begin,
+        end,
+        row: Row {
+            id: RowID::from_u64(1, 1),
+            data: "testme".to_string(),
+        },
+    };
+    let rv = version(TxTimestampOrID::TxID(TxID(1)), None);
+    assert!(!rv.is_committed());
+    assert!(!rv.is_deleted());
+    assert_eq!(rv.begin_timestamp(), None);
+    assert_eq!(rv.end_timestamp(), None);
+    let rv = version(
+        TxTimestampOrID::Timestamp(2),
+        Some(TxTimestampOrID::TxID(TxID(3))),
+    );
+    assert!(rv.is_committed());
+    assert!(rv.is_deleted());
+    assert_eq!(rv.begin_timestamp(), Some(2));
+    assert_eq!(rv.end_timestamp(), None);
+    let rv = version(
+        TxTimestampOrID::Timestamp(2),
+        Some(TxTimestampOrID::Timestamp(4)),
+    );
+    assert!(rv.is_committed());
+    assert!(rv.is_deleted());
+    assert_eq!(rv.begin_timestamp(), Some(2));
+    assert_eq!(rv.end_timestamp(), Some(4));
+}
+
+#[traced_test]
+#[test]
+fn test_tx_id_wraparound() {
+    let db = test_db();
+    let tx1 = db.begin_tx();
+    assert_eq!(tx1, TxID(1));
+    db.tx_ids.store(u64::MAX, Ordering::SeqCst);
+    let tx2 = db.begin_tx();
+    assert_eq!(tx2, TxID(u64::MAX));
+    // Transaction 0 is reserved and transaction 1 is still active.
+    let tx3 = db.begin_tx();
+    assert_eq!(tx3, TxID(2));
+    db.commit_tx(tx1).unwrap();
+    db.tx_ids.store(u64::MAX, Ordering::SeqCst);
+    // Transaction 1 has retired, so its ID is reused.
+    let tx4 = db.begin_tx();
+    assert_eq!(tx4, TxID(1));
+    db.rollback_tx(tx2);
+    db.rollback_tx(tx3);
+    db.rollback_tx(tx4);
+}
+
+#[traced_test]
+#[test]
+fn test_set_gc_watermark() {
+    let mut db = test_db();
+    db.set_gc_batch_size(0);
+    let id = RowID::from_u64(1, 1);
+    let row = |data: &str| Row {
+        id: id.clone(),
+        data: data.to_string(),
+    };
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row("Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    // A transaction on another node began before the update.
+    db.set_gc_watermark(db.get_timestamp());
+    let tx2 = db.begin_tx();
+    db.update(tx2, row("World")).unwrap();
+    db.commit_tx(tx2).unwrap();
+
+    // There are no local transactions, but the old version is still needed.
+    assert_eq!(db.run_gc().versions_dropped, 0);
+    assert_eq!(db.version_chain_length(id.clone()).unwrap(), 2);
+
+    db.set_gc_watermark(u64::MAX);
+    assert_eq!(db.run_gc().versions_dropped, 1);
+    assert_eq!(db.version_chain_length(id).unwrap(), 1);
+}
+
+#[traced_test]
+#[test]
+fn test_explain_visibility() {
+    let db = test_db();
+    let id = RowID::from_u64(1, 1);
+    let row = |data: &str| Row {
+        id: id.clone(),
+        data: data.to_string(),
+    };
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row("Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    let tx3 = db.begin_tx();
+    db.update(tx3, row("World")).unwrap();
+
+    let explain = |tx_id| {
+        db.explain_visibility(tx_id, id.clone())
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.is_visible, e.reason))
+            .collect::<Vec<_>>()
+    };
+    let explanation = explain(tx2);
+    assert_eq!(explanation.len(), 2);
+    assert!(explanation[0].0);
+    assert!(explanation[0]
+        .1
+        .contains(&format!("end {tx3} is Active and is not this transaction")));
+    assert!(!explanation[1].0);
+    assert!(explanation[1].1.contains(&format!(
+        "begin {tx3} is Active and is not this transaction"
+    )));
+    let explanation = explain(tx3);
+    assert!(!explanation[0].0);
+    assert!(explanation[0]
+        .1
+        .contains(&format!("end {tx3} is this transaction")));
+    assert!(explanation[1].0);
+
+    assert!(db
+        .explain_visibility(tx2, RowID::from_u64(1, 2))
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.explain_visibility(TxID(42), id.clone()),
+        Err(DatabaseError::NoSuchTransactionID { tx_id: TxID(42) })
+    );
+}
+
+#[traced_test]
+#[test]
+#[should_panic(expected = "begins with unknown transaction Tx#42")]
+fn test_assert_no_version_leaks() {
+    let db = test_db();
+    let tx1 = db.begin_tx();
+    db.insert(
+        tx1,
+        Row {
+            id: RowID::from_u64(1, 1),
+            data: "Hello".to_string(),
+        },
+    )
+    .unwrap();
+    // Versions of active transactions are not leaks.
+    db.assert_no_version_leaks();
+    db.commit_tx(tx1).unwrap();
+    db.assert_no_version_leaks();
+    db.insert_version(
+        RowID::from_u64(1, 2),
+        RowVersion {
+            begin: TxTimestampOrID::TxID(TxID(42)),
+            end: None,
+            row: Row {
+                id: RowID::from_u64(1, 2),
+                data: "World".to_string(),
+            },
+        },
+    );
+    db.assert_no_version_leaks();
+}
+
+#[traced_test]
+#[test]
+fn test_subscribe() {
+    use futures::{FutureExt, StreamExt};
+
+    let db = test_db();
+    let row = |table_id, row_id, data: &str| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: data.to_string(),
+    };
+    let change = |kind, new_row: Option<Row<String>>| ChangeEvent::Change {
+        table_id: 1,
+        row_id: RowID::from_u64(1, 1),
+        kind,
+        new_row,
+    };
+    let mut events = Box::pin(db.subscribe(1));
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, 1, "Hello")).unwrap();
+    db.insert(tx1, row(2, 1, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    let tx2 = db.begin_tx();
+    db.update(tx2, row(1, 1, "World")).unwrap();
+    db.commit_tx(tx2).unwrap();
+    // Rolled back transactions and rows inserted and deleted by the same
+    // transaction are not changes.
+    let tx3 = db.begin_tx();
+    db.delete(tx3, RowID::from_u64(1, 1)).unwrap();
+    db.rollback_tx(tx3);
+    let tx4 = db.begin_tx();
+    db.insert(tx4, row(1, 2, "Hello")).unwrap();
+    db.delete(tx4, RowID::from_u64(1, 2)).unwrap();
+    db.commit_tx(tx4).unwrap();
+    let tx5 = db.begin_tx();
+    db.delete(tx5, RowID::from_u64(1, 1)).unwrap();
+    db.commit_tx(tx5).unwrap();
+
+    let mut next = || events.next().now_or_never().flatten();
+    assert_eq!(
+        next(),
+        Some(change(ChangeKind::Insert, Some(row(1, 1, "Hello"))))
+    );
+    assert_eq!(
+        next(),
+        Some(change(ChangeKind::Update, Some(row(1, 1, "World"))))
+    );
+    assert_eq!(next(), Some(change(ChangeKind::Delete, None)));
+    assert_eq!(next(), None);
+
+    // A subscriber that falls behind is told how many events it missed.
+    for _ in 0..SUBSCRIBER_BUFFER_SIZE + 3 {
+        let tx = db.begin_tx();
+        db.upsert(tx, row(1, 1, "Again")).unwrap();
+        db.commit_tx(tx).unwrap();
+    }
+    let mut delivered = 0;
+    while next().is_some() {
+        delivered += 1;
+    }
+    let tx = db.begin_tx();
+    db.upsert(tx, row(1, 1, "Caught up")).unwrap();
+    db.commit_tx(tx).unwrap();
+    assert_eq!(
+        next(),
+        Some(ChangeEvent::Lagged(
+            (SUBSCRIBER_BUFFER_SIZE + 3 - delivered) as u64
+        ))
+    );
+    assert_eq!(
+        next(),
+        Some(change(ChangeKind::Update, Some(row(1, 1, "Caught up"))))
+    );
+
+    // Dropping the stream unsubscribes.
+    drop(events);
+    let tx = db.begin_tx();
+    db.upsert(tx, row(1, 1, "Gone")).unwrap();
+    db.commit_tx(tx).unwrap();
+    assert!(db.subscribers.lock().is_empty());
+}
+
+#[traced_test]
+#[test]
+fn test_revert_to_snapshot() {
+    let db = test_db();
+    let row = |row_id, data: &str| Row {
+        id: RowID::from_u64(1, row_id),
+        data: data.to_string(),
+    };
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, "Hello")).unwrap();
+    db.commit_tx(tx1).unwrap();
+    let snapshot = db.export_snapshot().unwrap();
+
+    let tx2 = db.begin_tx();
+    db.update(tx2, row(1, "World")).unwrap();
+    db.insert(tx2, row(2, "World")).unwrap();
+    db.commit_tx(tx2).unwrap();
+
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.revert_to_snapshot(snapshot.clone()),
+        Err(DatabaseError::ActiveTransactionsExist)
+    );
+    db.rollback_tx(tx3);
+    db.revert_to_snapshot(snapshot).unwrap();
+
+    let tx4 = db.begin_tx();
+    assert_eq!(
+        db.scan_rows_for_table(tx4, 1).unwrap(),
+        vec![row(1, "Hello")]
+    );
+    assert_eq!(db.version_chain_length(RowID::from_u64(1, 1)).unwrap(), 1);
+    db.update(tx4, row(1, "Again")).unwrap();
+    db.commit_tx(tx4).unwrap();
+}
+
+#[traced_test]
+#[test]
+fn test_kill_transaction() {
+    let db: Database<LocalClock, String> = DatabaseBuilder::new()
+        .max_tx_age(std::time::Duration::from_millis(50))
+        .build();
+    let row = |row_id| Row {
+        id: RowID::from_u64(1, row_id),
+        data: "Hello".to_string(),
+    };
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1)).unwrap();
+    assert!(db.transaction_age(tx1).is_some());
+    db.kill_transaction(tx1).unwrap();
+    assert_eq!(
+        db.kill_transaction(tx1),
+        Err(DatabaseError::TransactionKilled)
+    );
+    // The transaction is only rolled back by its owner.
+    assert_eq!(
+        db.transaction_info(tx1).unwrap().state,
+        TransactionState::Killed
+    );
+    assert_eq!(
+        db.insert(tx1, row(2)),
+        Err(DatabaseError::TransactionKilled)
+    );
+    assert_eq!(
+        db.read(tx1, RowID::from_u64(1, 1)),
+        Err(DatabaseError::TransactionKilled)
+    );
+    // Even when the row does not exist.
+    assert_eq!(
+        db.delete(tx1, RowID::from_u64(1, 3)),
+        Err(DatabaseError::TransactionKilled)
+    );
+    assert_eq!(
+        db.update(tx1, row(3)),
+        Err(DatabaseError::TransactionKilled)
+    );
+    assert_eq!(db.commit_tx(tx1), Err(DatabaseError::TransactionKilled));
+    assert_eq!(db.transaction_age(tx1), None);
+    db.rollback_tx(tx1);
+    db.assert_no_version_leaks();
+
+    // Transactions older than the maximum age are killed when another one begins.
+    let tx2 = db.begin_tx();
+    db.insert(tx2, row(1)).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let tx3 = db.begin_tx();
+    assert_eq!(
+        db.insert(tx2, row(2)),
+        Err(DatabaseError::TransactionKilled)
+    );
+    assert_eq!(db.read(tx3, RowID::from_u64(1, 1)).unwrap(), None);
+    db.commit_tx(tx3).unwrap();
+    assert!(db.kill_stale_transactions().is_empty());
+    db.rollback_tx(tx2);
+    db.assert_no_version_leaks();
+}
+
+#[traced_test]
+#[test]
+fn test_kill_transaction_while_inserting() {
+    let db = test_db();
+    let tx1 = db.begin_tx();
+    std::thread::scope(|s| {
+        let inserter = s.spawn(|| {
+            for row_id in 0.. {
+                let row = Row {
+                    id: RowID::from_u64(1, row_id),
+                    data: "Hello".to_string(),
+                };
+                if let Err(e) = db.insert(tx1, row) {
+                    return e;
+                }
+            }
+            unreachable!()
+        });
+        while db.transaction_info(tx1).unwrap().write_set_size < 100 {
+            std::thread::yield_now();
+        }
+        db.kill_transaction(tx1).unwrap();
+        assert_eq!(inserter.join().unwrap(), DatabaseError::TransactionKilled);
+    });
+
+    // The owner rolls back every row it inserted, including one that was
+    // in progress when the transaction was killed.
+    db.rollback_tx(tx1);
+    db.assert_no_version_leaks();
+    assert!(db.rows.is_empty());
+    let tx2 = db.begin_tx();
+    assert_eq!(db.scan_rows(tx2).unwrap(), vec![]);
+    db.commit_tx(tx2).unwrap();
+}
+
+#[traced_test]
+#[test]
+fn test_estimate_row_count() {
+    let storage = crate::persistent_storage::MemoryStorage::new();
+    let db: Database<LocalClock, String> = DatabaseBuilder::new().storage(storage.clone()).build();
+    let row = |table_id, row_id, data: &str| Row {
+        id: RowID::from_u64(table_id, row_id),
+        data: data.to_string(),
+    };
+
+    let tx1 = db.begin_tx();
+    db.insert(tx1, row(1, 1, "Hello")).unwrap();
+    db.insert(tx1, row(1, 2, "Hello")).unwrap();
+    db.insert(tx1, row(2, 1, "Hello")).unwrap();
+    // Uncommitted inserts are not counted.
+    assert_eq!(db.estimate_row_count(1), 0);
+    db.commit_tx(tx1).unwrap();
+    assert_eq!(db.estimate_row_count(1), 2);
+    assert_eq!(db.estimate_row_count(2), 1);
+
+    let tx2 = db.begin_tx();
+    db.update(tx2, row(1, 1, "World")).unwrap();
+    db.delete(tx2, RowID::from_u64(1, 2)).unwrap();
+    db.commit_tx(tx2).unwrap();
+    assert_eq!(db.estimate_row_count(1), 1);
+
+    let tx3 = db.begin_tx();
+    db.insert(tx3, row(1, 3, "Hello")).unwrap();
+    db.delete(tx3, RowID::from_u64(2, 1)).unwrap();
+    db.rollback_tx(tx3);
+    assert_eq!(db.estimate_row_count(1), 1);
+    assert_eq!(db.estimate_row_count(2), 1);
+    assert_eq!(db.estimate_row_count(3), 0);
+    assert_eq!(db.branch().estimate_row_count(1), 1);
+
+    // Recovery replays the counts from the log.
+    let recovered: Database<LocalClock, String> = DatabaseBuilder::new().storage(storage).build();
+    recovered.recover().unwrap();
+    assert_eq!(recovered.estimate_row_count(1), 1);
+    assert_eq!(recovered.estimate_row_count(2), 1);
+}
+
+#[traced_test]
+#[test]
+fn test_transaction_with_policy() {
+    let db = test_db();
+    let id = RowID::from_u64(1, 1);
+    let row = |data: &str| Row {
+        id: id.clone(),
+        data: data.to_string(),
+    };
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: std::time::Duration::from_millis(1),
+        backoff_factor: 2.0,
+        jitter: false,
+    };
+    assert_eq!(policy.delay(1), std::time::Duration::from_millis(1));
+    assert_eq!(policy.delay(3), std::time::Duration::from_millis(4));
+    let jittered = RetryPolicy {
+        jitter: true,
+        ..policy.clone()
+    };
+    assert!(jittered.delay(3) <= std::time::Duration::from_millis(4));
+
+    db.transaction(|tx| db.insert(tx, row("Hello"))).unwrap();
+    let tx1 = db.begin_tx();
+    db.update(tx1, row("Conflict")).unwrap();
+
+    // Every attempt conflicts with T1 until the policy gives up.
+    let mut attempts = 0;
+    let result = db.transaction_with_policy(&policy, |tx| {
+        attempts += 1;
+        db.update(tx, row("World"))
+    });
+    assert_eq!(
+        result,
+        Err(DatabaseError::WriteWriteConflict { with_tx_id: tx1 })
+    );
+    assert_eq!(attempts, 3);
+
+    // The conflict goes away once T1 rolls back.
+    let mut attempts = 0;
+    let result = db.transaction_with_policy(&policy, |tx| {
+        attempts += 1;
+        let result = db.update(tx, row("World"));
+        if attempts == 2 {
+            db.rollback_tx(tx1);
+        }
+        result
+    });
+    assert_eq!(result, Ok(true));
+    assert_eq!(attempts, 3);
+
+    // Other errors are not retried.
+    let mut attempts = 0;
+    let result = db.transaction_with_policy(&policy, |_| {
+        attempts += 1;
+        Err::<(), _>(DatabaseError::Io("oops".to_string()))
+    });
+    assert_eq!(result, Err(DatabaseError::Io("oops".to_string())));
+    assert_eq!(attempts, 1);
+}
+
+#[traced_test]
+#[test]
+fn test_sim_clock_timestamps() {
+    let clock = Arc::new(crate::clock::SimClock::new());
+    let db: Database<Arc<crate::clock::SimClock>, String> = Database::new(clock.clone(), Noop);
+    let id = RowID::from_u64(1, 1);
+    let row = |data: &str| Row {
+        id: id.clone(),
+        data: data.to_string(),
+    };
+
+    // The row is inserted by a transaction that begins at 60 and commits at 61.
+    clock.set(60);
+    let tx60 = db.begin_tx();
+    db.insert(tx60, row("Hello")).unwrap();
+    db.commit_tx(tx60).unwrap();
+    assert_eq!(
+        db.iter_versions(id.clone()).unwrap()[0].begin,
+        TxTimestampOrID::Timestamp(61)
+    );
+
+    clock.set(75);
+    let tx75 = db.begin_tx();
+    assert_eq!(db.transaction_info(tx75).unwrap().begin_ts, 75);
+    assert_eq!(db.read_at_timestamp(id.clone(), 60).unwrap(), None);
+    assert_eq!(db.read(tx75, id.clone()).unwrap(), Some(row("Hello")));
+}
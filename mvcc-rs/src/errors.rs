@@ -1,13 +1,96 @@
+use crate::database::{RowID, TxID};
 use thiserror::Error;
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug)]
 pub enum DatabaseError {
-    #[error("no such transaction ID: `{0}`")]
-    NoSuchTransactionID(u64),
-    #[error("transaction aborted because of a write-write conflict")]
-    WriteWriteConflict,
+    #[error("no such transaction ID: `{tx_id}`")]
+    NoSuchTransactionID { tx_id: TxID },
+    #[error(
+        "transaction aborted because of a write-write conflict with transaction `{with_tx_id}`"
+    )]
+    WriteWriteConflict { with_tx_id: TxID },
+    #[error("transaction aborted because of a phantom read")]
+    PhantomRead,
+    #[error("transaction aborted because of a write skew")]
+    WriteSkewConflict,
+    #[error("transaction aborted because it could not be serialized")]
+    SerializationFailure,
     #[error("transaction is terminated")]
     TxTerminated,
+    #[error("transaction was killed")]
+    TransactionKilled,
+    #[error("transaction is read-only")]
+    ReadOnlyTransaction,
+    #[error("no such savepoint ID: `{0}`")]
+    NoSuchSavepoint(u64),
+    #[error("table `{0}` is not empty")]
+    TableNotEmpty(u64),
+    #[error("cannot checkpoint while read-write transactions are in progress")]
+    CheckpointBusy,
+    #[error("cannot revert to a snapshot while transactions are in progress")]
+    ActiveTransactionsExist,
+    #[error("commit rejected by hook: {0}")]
+    CommitRejected(String),
+    #[error("row `{child_id:?}` references missing row `{parent_id:?}`")]
+    ForeignKeyError { child_id: RowID, parent_id: RowID },
+    #[error("row `{0:?}` already exists")]
+    RowAlreadyExists(RowID),
+    #[error("row ID `{actual:?}` does not match `{expected:?}`")]
+    RowIdMismatch { expected: RowID, actual: RowID },
+    #[error("internal state error: {0}")]
+    InternalStateError(String),
+    #[error("storage error: {0}")]
+    StorageError(#[from] std::io::Error),
     #[error("I/O error: {0}")]
     Io(String),
 }
+
+// `std::io::Error` cannot be compared, so storage errors are equal if they
+// are of the same kind and have the same message.
+impl PartialEq for DatabaseError {
+    fn eq(&self, other: &Self) -> bool {
+        use DatabaseError::*;
+        match (self, other) {
+            (NoSuchTransactionID { tx_id: a }, NoSuchTransactionID { tx_id: b }) => a == b,
+            (WriteWriteConflict { with_tx_id: a }, WriteWriteConflict { with_tx_id: b }) => a == b,
+            (PhantomRead, PhantomRead)
+            | (WriteSkewConflict, WriteSkewConflict)
+            | (SerializationFailure, SerializationFailure)
+            | (TxTerminated, TxTerminated)
+            | (TransactionKilled, TransactionKilled)
+            | (ReadOnlyTransaction, ReadOnlyTransaction)
+            | (CheckpointBusy, CheckpointBusy)
+            | (ActiveTransactionsExist, ActiveTransactionsExist) => true,
+            (NoSuchSavepoint(a), NoSuchSavepoint(b)) => a == b,
+            (TableNotEmpty(a), TableNotEmpty(b)) => a == b,
+            (CommitRejected(a), CommitRejected(b)) => a == b,
+            (
+                ForeignKeyError {
+                    child_id: a,
+                    parent_id: a_parent,
+                },
+                ForeignKeyError {
+                    child_id: b,
+                    parent_id: b_parent,
+                },
+            ) => a == b && a_parent == b_parent,
+            (RowAlreadyExists(a), RowAlreadyExists(b)) => a == b,
+            (
+                RowIdMismatch {
+                    expected: a,
+                    actual: a_actual,
+                },
+                RowIdMismatch {
+                    expected: b,
+                    actual: b_actual,
+                },
+            ) => a == b && a_actual == b_actual,
+            (InternalStateError(a), InternalStateError(b)) => a == b,
+            (StorageError(a), StorageError(b)) => {
+                a.kind() == b.kind() && a.to_string() == b.to_string()
+            }
+            (Io(a), Io(b)) => a == b,
+            _ => false,
+        }
+    }
+}
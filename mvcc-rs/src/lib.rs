@@ -31,8 +31,14 @@
 //! * Optimistic reads and writes
 //! * Garbage collection
 
+// Only checked when targeting WebAssembly, so that `--all-features` builds
+// still work on other targets.
+#[cfg(all(feature = "c_bindings", feature = "wasm", target_arch = "wasm32"))]
+compile_error!("the `c_bindings` and `wasm` features are mutually exclusive");
+
 pub mod clock;
 pub mod cursor;
 pub mod database;
 pub mod errors;
 pub mod persistent_storage;
+pub mod table;
@@ -0,0 +1,74 @@
+use crate::database::{LogRecord, Result, Snapshot};
+use crate::persistent_storage::{CompactionStats, Storage};
+use parking_lot::Mutex;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// A storage backend that keeps the log records in memory.
+///
+/// Clones share the same log, so a clone can be kept around to inspect the
+/// records logged by a database, or to recover another database from them.
+#[derive(Clone, Debug)]
+pub struct MemoryStorage<T> {
+    records: Arc<Mutex<Vec<LogRecord<T>>>>,
+    checkpoint: Arc<Mutex<Option<Snapshot<T>>>>,
+}
+
+impl<T> MemoryStorage<T> {
+    pub fn new() -> Self {
+        Self {
+            records: Arc::new(Mutex::new(Vec::new())),
+            checkpoint: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns the number of log records.
+    pub fn len(&self) -> usize {
+        self.records.lock().len()
+    }
+
+    /// Returns `true` if no records have been logged.
+    pub fn is_empty(&self) -> bool {
+        self.records.lock().is_empty()
+    }
+}
+
+impl<T> Default for MemoryStorage<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Debug + Send> Storage<T> for MemoryStorage<T> {
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()> {
+        self.records.lock().push(record);
+        Ok(())
+    }
+
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        Ok(self.records.lock().clone())
+    }
+
+    fn checkpoint(&self, snapshot: &Snapshot<T>) -> Result<()> {
+        *self.checkpoint.lock() = Some(snapshot.clone());
+        Ok(())
+    }
+
+    fn truncate_before(&self, lsn: u64) -> Result<()> {
+        self.records
+            .lock()
+            .retain(|record| record.tx_timestamp >= lsn);
+        Ok(())
+    }
+
+    fn read_checkpoint(&self) -> Result<Option<Snapshot<T>>> {
+        Ok(self.checkpoint.lock().clone())
+    }
+
+    fn compact_log(&self) -> Result<CompactionStats> {
+        let mut records = self.records.lock();
+        let (compacted, stats) = LogRecord::compact(std::mem::take(&mut *records));
+        *records = compacted;
+        Ok(stats)
+    }
+}
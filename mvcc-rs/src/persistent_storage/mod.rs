@@ -1,82 +1,243 @@
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 use std::fmt::Debug;
+use std::path::PathBuf;
 
-use crate::database::{LogRecord, Result};
+use crate::database::{LogRecord, Result, Snapshot};
 use crate::errors::DatabaseError;
 
+mod memory;
+mod replay;
+mod replicated;
+#[cfg(feature = "rocksdb")]
+mod rocksdb;
+#[cfg(feature = "s3")]
 pub mod s3;
+mod wal;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-#[derive(Debug)]
-pub enum Storage {
-    Noop,
-    JsonOnDisk(std::path::PathBuf),
-    S3(s3::Replicator),
+#[cfg(feature = "rocksdb")]
+pub use self::rocksdb::RocksDbStorage;
+pub use memory::MemoryStorage;
+pub use replay::{ReplayEvent, TransactionLog};
+pub use replicated::ReplicatedStorage;
+#[cfg(feature = "wasm")]
+pub use wasm::WasmStorage;
+
+/// The changes made by `Storage::compact_log`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompactionStats {
+    /// The number of log records before compaction.
+    pub records_before: usize,
+    /// The number of log records after compaction.
+    pub records_after: usize,
+    /// The number of row versions dropped from the log.
+    pub versions_dropped: usize,
 }
 
-impl Storage {
-    pub fn new_noop() -> Self {
-        Self::Noop
+/// A persistent storage backend for the transaction log.
+pub trait Storage<T>: Debug + Send + Sync {
+    /// Appends the log record of a committed transaction.
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()>;
+
+    /// Reads all log records, in the order they were logged.
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>>;
+
+    /// Writes a checkpoint with the rows of `snapshot`, replacing any previous checkpoint.
+    fn checkpoint(&self, _snapshot: &Snapshot<T>) -> Result<()> {
+        Err(DatabaseError::Io(
+            "storage does not support checkpoints".to_string(),
+        ))
+    }
+
+    /// Drops the log records of transactions that committed before `lsn`.
+    fn truncate_before(&self, _lsn: u64) -> Result<()> {
+        Err(DatabaseError::Io(
+            "storage does not support truncation".to_string(),
+        ))
     }
 
-    pub fn new_json_on_disk(path: impl Into<std::path::PathBuf>) -> Self {
-        let path = path.into();
-        Self::JsonOnDisk(path)
+    /// Reads the most recent checkpoint, if there is one.
+    fn read_checkpoint(&self) -> Result<Option<Snapshot<T>>> {
+        Ok(None)
     }
 
-    pub fn new_s3(options: s3::Options) -> Result<Self> {
-        let replicator = futures::executor::block_on(s3::Replicator::new(options))?;
-        Ok(Self::S3(replicator))
+    /// Drops all but the most recent versions of every row from the log,
+    /// and the log records left without versions.
+    fn compact_log(&self) -> Result<CompactionStats> {
+        Err(DatabaseError::Io(
+            "storage does not support compaction".to_string(),
+        ))
     }
 }
 
-impl Storage {
-    pub fn log_tx<T: Serialize>(&self, m: LogRecord<T>) -> Result<()> {
-        match self {
-            Self::JsonOnDisk(path) => {
-                use std::io::Write;
-                let t = serde_json::to_vec(&m).map_err(|e| DatabaseError::Io(e.to_string()))?;
-                let mut file = std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(path)
-                    .map_err(|e| DatabaseError::Io(e.to_string()))?;
-                file.write_all(&t)
-                    .map_err(|e| DatabaseError::Io(e.to_string()))?;
-                file.write_all(b"\n")
-                    .map_err(|e| DatabaseError::Io(e.to_string()))?;
-            }
-            Self::S3(replicator) => {
-                futures::executor::block_on(replicator.replicate_tx(m))?;
-            }
-            Self::Noop => (),
-        }
+impl<T, S: Storage<T> + ?Sized> Storage<T> for Box<S> {
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()> {
+        (**self).log_tx(record)
+    }
+
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        (**self).read_tx_log()
+    }
+
+    fn checkpoint(&self, snapshot: &Snapshot<T>) -> Result<()> {
+        (**self).checkpoint(snapshot)
+    }
+
+    fn truncate_before(&self, lsn: u64) -> Result<()> {
+        (**self).truncate_before(lsn)
+    }
+
+    fn read_checkpoint(&self) -> Result<Option<Snapshot<T>>> {
+        (**self).read_checkpoint()
+    }
+
+    fn compact_log(&self) -> Result<CompactionStats> {
+        (**self).compact_log()
+    }
+}
+
+/// A storage backend that discards all log records.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Noop;
+
+impl<T> Storage<T> for Noop {
+    fn log_tx(&self, _record: LogRecord<T>) -> Result<()> {
         Ok(())
     }
 
-    pub fn read_tx_log<T: DeserializeOwned + Debug>(&self) -> Result<Vec<LogRecord<T>>> {
-        match self {
-            Self::JsonOnDisk(path) => {
-                use std::io::BufRead;
-                let file = std::fs::OpenOptions::new()
-                    .read(true)
-                    .open(path)
-                    .map_err(|e| DatabaseError::Io(e.to_string()))?;
-
-                let mut records: Vec<LogRecord<T>> = Vec::new();
-                let mut lines = std::io::BufReader::new(file).lines();
-                while let Some(Ok(line)) = lines.next() {
-                    records.push(
-                        serde_json::from_str(&line)
-                            .map_err(|e| DatabaseError::Io(e.to_string()))?,
-                    )
-                }
-                Ok(records)
-            }
-            Self::S3(replicator) => futures::executor::block_on(replicator.read_tx_log()),
-            Self::Noop => Err(crate::errors::DatabaseError::Io(
-                "cannot read from Noop storage".to_string(),
-            )),
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        Err(DatabaseError::Io(
+            "cannot read from Noop storage".to_string(),
+        ))
+    }
+}
+
+/// A storage backend that appends log records to a file as JSON lines.
+#[derive(Clone, Debug)]
+pub struct JsonOnDisk {
+    path: PathBuf,
+}
+
+impl JsonOnDisk {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Storage<T> for JsonOnDisk {
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()> {
+        use std::io::Write;
+        let t = serde_json::to_vec(&record).map_err(|e| DatabaseError::Io(e.to_string()))?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(DatabaseError::StorageError)?;
+        file.write_all(&t).map_err(DatabaseError::StorageError)?;
+        file.write_all(b"\n").map_err(DatabaseError::StorageError)?;
+        Ok(())
+    }
+
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        use std::io::BufRead;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .open(&self.path)
+            .map_err(DatabaseError::StorageError)?;
+
+        let mut records: Vec<LogRecord<T>> = Vec::new();
+        let mut lines = std::io::BufReader::new(file).lines();
+        while let Some(Ok(line)) = lines.next() {
+            records.push(serde_json::from_str(&line).map_err(|e| DatabaseError::Io(e.to_string()))?)
+        }
+        Ok(records)
+    }
+}
+
+/// A storage backend that appends log records to a write-ahead log as bincode frames.
+///
+/// Checkpoints are stored next to the log, in a file with a `.checkpoint` suffix.
+#[derive(Clone, Debug)]
+pub struct WalStorage {
+    log: wal::FramedLog,
+}
+
+/// An alias of `WalStorage`, which encodes log records with bincode as well.
+pub type BincodeOnDisk = WalStorage;
+
+impl WalStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            log: wal::FramedLog::new(path.into()),
         }
     }
 }
+
+impl<T: Serialize + DeserializeOwned> Storage<T> for WalStorage {
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()> {
+        let t = bincode::serialize(&record).map_err(|e| DatabaseError::Io(e.to_string()))?;
+        self.log.append(&t)
+    }
+
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        self.log
+            .read()?
+            .iter()
+            .map(|frame| bincode::deserialize(frame).map_err(|e| DatabaseError::Io(e.to_string())))
+            .collect()
+    }
+
+    fn checkpoint(&self, snapshot: &Snapshot<T>) -> Result<()> {
+        let t = bincode::serialize(snapshot).map_err(|e| DatabaseError::Io(e.to_string()))?;
+        self.log.write_checkpoint(&t)
+    }
+
+    fn truncate_before(&self, lsn: u64) -> Result<()> {
+        self.log.retain(|frame| {
+            let record: LogRecord<T> =
+                bincode::deserialize(frame).map_err(|e| DatabaseError::Io(e.to_string()))?;
+            Ok(record.tx_timestamp >= lsn)
+        })
+    }
+
+    fn read_checkpoint(&self) -> Result<Option<Snapshot<T>>> {
+        self.log
+            .read_checkpoint()?
+            .map(|t| bincode::deserialize(&t).map_err(|e| DatabaseError::Io(e.to_string())))
+            .transpose()
+    }
+
+    fn compact_log(&self) -> Result<CompactionStats> {
+        let mut stats = CompactionStats::default();
+        self.log.rewrite(|frames| {
+            let records = frames
+                .iter()
+                .map(|frame| {
+                    bincode::deserialize(frame).map_err(|e| DatabaseError::Io(e.to_string()))
+                })
+                .collect::<Result<Vec<LogRecord<T>>>>()?;
+            let (records, compaction) = LogRecord::compact(records);
+            stats = compaction;
+            records
+                .iter()
+                .map(|record| {
+                    bincode::serialize(record).map_err(|e| DatabaseError::Io(e.to_string()))
+                })
+                .collect()
+        })?;
+        Ok(stats)
+    }
+}
+
+#[cfg(feature = "s3")]
+impl<T: Serialize + DeserializeOwned + Debug> Storage<T> for s3::Replicator {
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()> {
+        futures::executor::block_on(self.replicate_tx(record))
+    }
+
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        futures::executor::block_on(s3::Replicator::read_tx_log(self))
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt::Debug;
+
+use crate::database::{LogRecord, Row, RowID};
+
+/// A single operation recorded in the transaction log.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayEvent<T> {
+    /// The row was inserted, or updated to `row`, by the transaction that
+    /// committed at `ts`.
+    Insert { row: Row<T>, ts: u64 },
+    /// The version of the row `row_id` was ended by the transaction that
+    /// committed at `ts`.
+    Delete { row_id: RowID, ts: u64 },
+}
+
+impl<T: Debug> ReplayEvent<T> {
+    /// Returns the commit timestamp of the transaction that made the change.
+    pub fn ts(&self) -> u64 {
+        match self {
+            Self::Insert { ts, .. } | Self::Delete { ts, .. } => *ts,
+        }
+    }
+
+    /// Formats the event for debugging on a single line.
+    pub fn print_summary(&self) -> String {
+        match self {
+            Self::Insert { row, ts } => format!("ts {ts}: INSERT {:?} {:?}", row.id, row.data),
+            Self::Delete { row_id, ts } => format!("ts {ts}: DELETE {row_id:?}"),
+        }
+    }
+}
+
+/// Replays a transaction log without instantiating a database.
+#[derive(Clone, Copy, Debug)]
+pub struct TransactionLog;
+
+impl TransactionLog {
+    /// Splits `records` into the individual changes they contain, ordered
+    /// by the commit timestamps of the transactions.
+    ///
+    /// Changes of the same transaction keep the order in which they were
+    /// logged. An update shows up as the deletion of the old version
+    /// followed by the insertion of the new one.
+    pub fn replay<T>(mut records: Vec<LogRecord<T>>) -> impl Iterator<Item = ReplayEvent<T>> {
+        records.sort_by_key(|record| record.tx_timestamp);
+        records.into_iter().flat_map(|record| {
+            let ts = record.tx_timestamp;
+            record.row_versions.into_iter().filter_map(move |version| {
+                if version.end_timestamp() == Some(ts) {
+                    Some(ReplayEvent::Delete {
+                        row_id: version.row.id,
+                        ts,
+                    })
+                } else if version.begin_timestamp() == Some(ts) {
+                    Some(ReplayEvent::Insert {
+                        row: version.row,
+                        ts,
+                    })
+                } else {
+                    None
+                }
+            })
+        })
+    }
+}
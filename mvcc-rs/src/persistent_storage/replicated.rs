@@ -0,0 +1,114 @@
+use crate::database::{LogRecord, Result, Snapshot};
+use crate::persistent_storage::{CompactionStats, Storage};
+use std::fmt::Debug;
+
+/// A storage backend that writes every log record to several backends, like
+/// RAID-1 does for disks.
+///
+/// Writes succeed if at least `write_quorum` backends succeed, which is all
+/// of them by default. Reads are served by the first backend that succeeds,
+/// so with a smaller quorum, put the backends that are least likely to miss
+/// a write first.
+#[derive(Debug)]
+pub struct ReplicatedStorage<T> {
+    backends: Vec<Box<dyn Storage<T>>>,
+    write_quorum: usize,
+}
+
+impl<T> ReplicatedStorage<T> {
+    /// Creates a storage that writes to all of `backends`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `backends` is empty.
+    pub fn new(backends: Vec<Box<dyn Storage<T>>>) -> Self {
+        assert!(!backends.is_empty(), "no storage backends to replicate to");
+        let write_quorum = backends.len();
+        Self {
+            backends,
+            write_quorum,
+        }
+    }
+
+    /// Sets the number of backends that must succeed for a write to succeed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `write_quorum` is zero or greater than the number of backends.
+    pub fn with_write_quorum(mut self, write_quorum: usize) -> Self {
+        assert!(
+            (1..=self.backends.len()).contains(&write_quorum),
+            "write quorum {write_quorum} out of range for {} backends",
+            self.backends.len()
+        );
+        self.write_quorum = write_quorum;
+        self
+    }
+
+    /// Runs `op` on every backend and returns the result of the first
+    /// backend that succeeded, or the first error if fewer than
+    /// `write_quorum` backends succeeded.
+    fn write<R>(&self, op: impl Fn(&dyn Storage<T>) -> Result<R>) -> Result<R> {
+        let mut succeeded = 0;
+        let mut first_ok = None;
+        let mut first_err = None;
+        for (i, backend) in self.backends.iter().enumerate() {
+            match op(backend.as_ref()) {
+                Ok(result) => {
+                    succeeded += 1;
+                    first_ok.get_or_insert(result);
+                }
+                Err(e) => {
+                    tracing::warn!("Storage backend {i} failed: {e}");
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+        match first_ok {
+            Some(result) if succeeded >= self.write_quorum => Ok(result),
+            _ => Err(first_err.expect("fewer backends than the quorum succeeded")),
+        }
+    }
+
+    /// Runs `op` on the backends in order until one of them succeeds, and
+    /// returns the first error if none of them does.
+    fn read<R>(&self, op: impl Fn(&dyn Storage<T>) -> Result<R>) -> Result<R> {
+        let mut first_err = None;
+        for (i, backend) in self.backends.iter().enumerate() {
+            match op(backend.as_ref()) {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    tracing::warn!("Storage backend {i} failed: {e}");
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_err.expect("there is at least one backend"))
+    }
+}
+
+impl<T: Clone + Debug> Storage<T> for ReplicatedStorage<T> {
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()> {
+        self.write(|backend| backend.log_tx(record.clone()))
+    }
+
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        self.read(|backend| backend.read_tx_log())
+    }
+
+    fn checkpoint(&self, snapshot: &Snapshot<T>) -> Result<()> {
+        self.write(|backend| backend.checkpoint(snapshot))
+    }
+
+    fn truncate_before(&self, lsn: u64) -> Result<()> {
+        self.write(|backend| backend.truncate_before(lsn))
+    }
+
+    fn read_checkpoint(&self) -> Result<Option<Snapshot<T>>> {
+        self.read(|backend| backend.read_checkpoint())
+    }
+
+    fn compact_log(&self) -> Result<CompactionStats> {
+        self.write(|backend| backend.compact_log())
+    }
+}
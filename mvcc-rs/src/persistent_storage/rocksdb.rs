@@ -0,0 +1,99 @@
+use crate::database::{LogRecord, Result, Snapshot};
+use crate::errors::DatabaseError;
+use crate::persistent_storage::{CompactionStats, Storage};
+use rocksdb::{ColumnFamily, IteratorMode, Options, WriteBatch, DB};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+
+/// The column family holding the log records, keyed by commit timestamp.
+const LOG_CF: &str = "log";
+/// The column family holding the checkpoint under `CHECKPOINT_KEY`.
+const CHECKPOINT_CF: &str = "checkpoint";
+const CHECKPOINT_KEY: &[u8] = b"checkpoint";
+
+/// A storage backend that keeps bincode-encoded log records in RocksDB.
+///
+/// Every record is stored under the big-endian commit timestamp of its
+/// transaction, so iterating over the keys returns the records in timestamp
+/// order. The checkpoint lives in a column family of its own.
+#[derive(Debug)]
+pub struct RocksDbStorage {
+    db: DB,
+}
+
+impl RocksDbStorage {
+    /// Opens the database at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = DB::open_cf(&options, path, [LOG_CF, CHECKPOINT_CF]).map_err(to_io_error)?;
+        Ok(Self { db })
+    }
+
+    fn cf(&self, name: &str) -> Result<&ColumnFamily> {
+        self.db
+            .cf_handle(name)
+            .ok_or_else(|| DatabaseError::Io(format!("missing column family {name}")))
+    }
+}
+
+fn to_io_error(e: rocksdb::Error) -> DatabaseError {
+    DatabaseError::Io(e.to_string())
+}
+
+impl<T: Serialize + DeserializeOwned> Storage<T> for RocksDbStorage {
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()> {
+        let t = bincode::serialize(&record).map_err(|e| DatabaseError::Io(e.to_string()))?;
+        self.db
+            .put_cf(self.cf(LOG_CF)?, record.tx_timestamp.to_be_bytes(), t)
+            .map_err(to_io_error)
+    }
+
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        self.db
+            .iterator_cf(self.cf(LOG_CF)?, IteratorMode::Start)
+            .map(|item| {
+                let (_, value) = item.map_err(to_io_error)?;
+                bincode::deserialize(&value).map_err(|e| DatabaseError::Io(e.to_string()))
+            })
+            .collect()
+    }
+
+    fn checkpoint(&self, snapshot: &Snapshot<T>) -> Result<()> {
+        let t = bincode::serialize(snapshot).map_err(|e| DatabaseError::Io(e.to_string()))?;
+        self.db
+            .put_cf(self.cf(CHECKPOINT_CF)?, CHECKPOINT_KEY, t)
+            .map_err(to_io_error)
+    }
+
+    fn truncate_before(&self, lsn: u64) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        batch.delete_range_cf(self.cf(LOG_CF)?, 0u64.to_be_bytes(), lsn.to_be_bytes());
+        self.db.write(batch).map_err(to_io_error)
+    }
+
+    fn read_checkpoint(&self) -> Result<Option<Snapshot<T>>> {
+        self.db
+            .get_cf(self.cf(CHECKPOINT_CF)?, CHECKPOINT_KEY)
+            .map_err(to_io_error)?
+            .map(|t| bincode::deserialize(&t).map_err(|e| DatabaseError::Io(e.to_string())))
+            .transpose()
+    }
+
+    fn compact_log(&self) -> Result<CompactionStats> {
+        let records = Storage::<T>::read_tx_log(self)?;
+        let mut batch = WriteBatch::default();
+        for record in &records {
+            batch.delete_cf(self.cf(LOG_CF)?, record.tx_timestamp.to_be_bytes());
+        }
+        let (records, stats) = LogRecord::compact(records);
+        for record in &records {
+            let t = bincode::serialize(record).map_err(|e| DatabaseError::Io(e.to_string()))?;
+            batch.put_cf(self.cf(LOG_CF)?, record.tx_timestamp.to_be_bytes(), t);
+        }
+        self.db.write(batch).map_err(to_io_error)?;
+        Ok(stats)
+    }
+}
@@ -27,6 +27,11 @@ pub struct Replicator {
 }
 
 impl Replicator {
+    /// Creates a new replicator, blocking the current thread until it is connected.
+    pub fn new_blocking(options: Options) -> Result<Self> {
+        futures::executor::block_on(Self::new(options))
+    }
+
     pub async fn new(options: Options) -> Result<Self> {
         let mut loader = aws_config::from_env();
         if let Ok(endpoint) = std::env::var("MVCCRS_ENDPOINT") {
@@ -0,0 +1,201 @@
+//! Write-ahead log framing.
+//!
+//! The log is a sequence of frames, each of which is a little-endian `u32`
+//! payload length followed by the payload itself. A frame is appended with a
+//! single write followed by an fsync, so a crash can only leave a truncated
+//! frame at the tail of the log, which is ignored when the log is read, and
+//! cut off before the next frame is appended.
+//!
+//! A log can be checkpointed, which writes a snapshot to a file next to the
+//! log and drops the frames the snapshot covers. Both the checkpoint and the
+//! truncated log are written to a temporary file first and then renamed
+//! over the original, so a crash leaves either the old or the new version.
+
+use crate::database::Result;
+use crate::errors::DatabaseError;
+use parking_lot::Mutex;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const HEADER_LEN: usize = std::mem::size_of::<u32>();
+
+/// A log on disk together with its checkpoint.
+#[derive(Clone, Debug)]
+pub(crate) struct FramedLog {
+    path: PathBuf,
+    checkpoint_path: PathBuf,
+    /// Serializes appends with truncation, which replaces the log file. The
+    /// flag is set once the log is known to end with a complete frame.
+    lock: Arc<Mutex<bool>>,
+}
+
+impl FramedLog {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        let mut checkpoint_path = path.clone().into_os_string();
+        checkpoint_path.push(".checkpoint");
+        Self {
+            path,
+            checkpoint_path: checkpoint_path.into(),
+            lock: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    pub(crate) fn append(&self, payload: &[u8]) -> Result<()> {
+        let mut tail_complete = self.lock.lock();
+        // A frame appended after a truncated one would be read as part of it.
+        if !*tail_complete {
+            truncate_incomplete_frame(&self.path)?;
+            *tail_complete = true;
+        }
+        append_frame(&self.path, payload)
+    }
+
+    pub(crate) fn read(&self) -> Result<Vec<Vec<u8>>> {
+        read_frames(&self.path)
+    }
+
+    /// Drops the frames for which `f` returns `false`.
+    pub(crate) fn retain(&self, mut f: impl FnMut(&[u8]) -> Result<bool>) -> Result<()> {
+        let mut tail_complete = self.lock.lock();
+        let mut data = Vec::new();
+        for payload in read_frames(&self.path)? {
+            if f(&payload)? {
+                data.extend_from_slice(&encode_frame(&payload)?);
+            }
+        }
+        write_atomic(&self.path, &data)?;
+        *tail_complete = true;
+        Ok(())
+    }
+
+    /// Replaces the frames of the log with the ones `f` returns for them.
+    pub(crate) fn rewrite(
+        &self,
+        f: impl FnOnce(Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>>,
+    ) -> Result<()> {
+        let mut tail_complete = self.lock.lock();
+        let mut data = Vec::new();
+        for payload in f(read_frames(&self.path)?)? {
+            data.extend_from_slice(&encode_frame(&payload)?);
+        }
+        write_atomic(&self.path, &data)?;
+        *tail_complete = true;
+        Ok(())
+    }
+
+    pub(crate) fn write_checkpoint(&self, data: &[u8]) -> Result<()> {
+        write_atomic(&self.checkpoint_path, data)
+    }
+
+    /// Reads the checkpoint, or returns `None` if the log was never checkpointed.
+    pub(crate) fn read_checkpoint(&self) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(&self.checkpoint_path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(DatabaseError::StorageError(e)),
+        }
+    }
+}
+
+fn encode_frame(payload: &[u8]) -> Result<Vec<u8>> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| DatabaseError::Io(format!("log record too large: {}", payload.len())))?;
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Replaces the contents of the file at `path` with `data`.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_path = path.to_path_buf().into_os_string();
+    tmp_path.push(".tmp");
+    let mut file = std::fs::File::create(&tmp_path).map_err(DatabaseError::StorageError)?;
+    file.write_all(data).map_err(DatabaseError::StorageError)?;
+    file.sync_data().map_err(DatabaseError::StorageError)?;
+    std::fs::rename(&tmp_path, path).map_err(DatabaseError::StorageError)
+}
+
+/// Appends a frame with `payload` to the log at `path`.
+fn append_frame(path: &Path, payload: &[u8]) -> Result<()> {
+    let frame = encode_frame(payload)?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(DatabaseError::StorageError)?;
+    file.write_all(&frame)
+        .map_err(DatabaseError::StorageError)?;
+    file.sync_data().map_err(DatabaseError::StorageError)?;
+    Ok(())
+}
+
+/// Reads the contents of the log at `path`.
+///
+/// A log that does not exist yet is empty.
+fn read_log(path: &Path) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    match std::fs::File::open(path) {
+        Ok(mut file) => {
+            file.read_to_end(&mut buf)
+                .map_err(DatabaseError::StorageError)?;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(DatabaseError::StorageError(e)),
+    }
+    Ok(buf)
+}
+
+/// Splits `buf` into the payloads of its complete frames, and returns them
+/// together with the number of bytes they take up.
+fn split_frames(buf: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut frames = Vec::new();
+    let mut rest = buf;
+    while rest.len() >= HEADER_LEN {
+        let (header, payload) = rest.split_at(HEADER_LEN);
+        let len = u32::from_le_bytes(header.try_into().unwrap()) as usize;
+        if payload.len() < len {
+            break;
+        }
+        let (payload, next) = payload.split_at(len);
+        frames.push(payload);
+        rest = next;
+    }
+    (frames, buf.len() - rest.len())
+}
+
+/// Reads the payloads of all complete frames from the log at `path`.
+fn read_frames(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let buf = read_log(path)?;
+    let (frames, len) = split_frames(&buf);
+    if len < buf.len() {
+        tracing::warn!(
+            "Ignoring truncated frame of {} bytes at the end of {}",
+            buf.len() - len,
+            path.display()
+        );
+    }
+    Ok(frames.into_iter().map(|payload| payload.to_vec()).collect())
+}
+
+/// Cuts off a truncated frame at the end of the log at `path`.
+fn truncate_incomplete_frame(path: &Path) -> Result<()> {
+    let buf = read_log(path)?;
+    let (_, len) = split_frames(&buf);
+    if len == buf.len() {
+        return Ok(());
+    }
+    tracing::warn!(
+        "Dropping truncated frame of {} bytes at the end of {}",
+        buf.len() - len,
+        path.display()
+    );
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(DatabaseError::StorageError)?;
+    file.set_len(len as u64)
+        .map_err(DatabaseError::StorageError)?;
+    file.sync_data().map_err(DatabaseError::StorageError)
+}
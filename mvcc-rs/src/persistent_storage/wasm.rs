@@ -0,0 +1,100 @@
+use crate::database::{LogRecord, Result, Snapshot};
+use crate::errors::DatabaseError;
+use crate::persistent_storage::{CompactionStats, Storage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A storage backend that keeps the log records in the browser's
+/// `localStorage`, as JSON lines under a single key.
+///
+/// The checkpoint is stored under the same key with a `.checkpoint` suffix.
+/// The `localStorage` handle is looked up on every access, because JavaScript
+/// values cannot be shared between threads.
+#[derive(Clone, Debug)]
+pub struct WasmStorage {
+    key: String,
+}
+
+impl WasmStorage {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self { key: key.into() }
+    }
+
+    fn checkpoint_key(&self) -> String {
+        format!("{}.checkpoint", self.key)
+    }
+}
+
+fn local_storage() -> Result<web_sys::Storage> {
+    web_sys::window()
+        .ok_or_else(|| DatabaseError::Io("no window object".to_string()))?
+        .local_storage()
+        .map_err(|e| DatabaseError::Io(format!("{e:?}")))?
+        .ok_or_else(|| DatabaseError::Io("localStorage is not available".to_string()))
+}
+
+fn get_item(key: &str) -> Result<Option<String>> {
+    local_storage()?
+        .get_item(key)
+        .map_err(|e| DatabaseError::Io(format!("{e:?}")))
+}
+
+fn set_item(key: &str, value: &str) -> Result<()> {
+    local_storage()?
+        .set_item(key, value)
+        .map_err(|e| DatabaseError::Io(format!("{e:?}")))
+}
+
+impl<T: Serialize + DeserializeOwned> Storage<T> for WasmStorage {
+    fn log_tx(&self, record: LogRecord<T>) -> Result<()> {
+        let t = serde_json::to_string(&record).map_err(|e| DatabaseError::Io(e.to_string()))?;
+        let mut log = get_item(&self.key)?.unwrap_or_default();
+        log.push_str(&t);
+        log.push('\n');
+        set_item(&self.key, &log)
+    }
+
+    fn read_tx_log(&self) -> Result<Vec<LogRecord<T>>> {
+        get_item(&self.key)?
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).map_err(|e| DatabaseError::Io(e.to_string())))
+            .collect()
+    }
+
+    fn checkpoint(&self, snapshot: &Snapshot<T>) -> Result<()> {
+        let t = serde_json::to_string(snapshot).map_err(|e| DatabaseError::Io(e.to_string()))?;
+        set_item(&self.checkpoint_key(), &t)
+    }
+
+    fn truncate_before(&self, lsn: u64) -> Result<()> {
+        let mut log = String::new();
+        for line in get_item(&self.key)?.unwrap_or_default().lines() {
+            let record: LogRecord<T> =
+                serde_json::from_str(line).map_err(|e| DatabaseError::Io(e.to_string()))?;
+            if record.tx_timestamp >= lsn {
+                log.push_str(line);
+                log.push('\n');
+            }
+        }
+        set_item(&self.key, &log)
+    }
+
+    fn read_checkpoint(&self) -> Result<Option<Snapshot<T>>> {
+        get_item(&self.checkpoint_key())?
+            .map(|t| serde_json::from_str(&t).map_err(|e| DatabaseError::Io(e.to_string())))
+            .transpose()
+    }
+
+    fn compact_log(&self) -> Result<CompactionStats> {
+        let (records, stats) = LogRecord::compact(Storage::<T>::read_tx_log(self)?);
+        let mut log = String::new();
+        for record in records {
+            let t = serde_json::to_string(&record).map_err(|e| DatabaseError::Io(e.to_string()))?;
+            log.push_str(&t);
+            log.push('\n');
+        }
+        set_item(&self.key, &log)?;
+        Ok(stats)
+    }
+}
@@ -0,0 +1,77 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::clock::LogicalClock;
+use crate::database::{Database, Result, Row, RowID, TxID};
+use std::fmt::Debug;
+
+/// A handle to a single table of a database.
+///
+/// The handle builds the `RowID` of every row from its own table ID, so
+/// callers only pass the row ID within the table.
+#[derive(Debug)]
+pub struct Table<
+    'a,
+    Clock: LogicalClock,
+    T: Sync + Send + Clone + Serialize + DeserializeOwned + Debug,
+> {
+    db: &'a Database<Clock, T>,
+    table_id: u64,
+}
+
+impl<
+        'a,
+        Clock: LogicalClock,
+        T: Sync + Send + Clone + Serialize + DeserializeOwned + Debug + 'static,
+    > Table<'a, Clock, T>
+{
+    pub fn new(db: &'a Database<Clock, T>, table_id: u64) -> Self {
+        Self { db, table_id }
+    }
+
+    pub fn table_id(&self) -> u64 {
+        self.table_id
+    }
+
+    /// Returns the `RowID` of the row `row_id` of this table.
+    pub fn row_id(&self, row_id: u64) -> RowID {
+        RowID::from_u64(self.table_id, row_id)
+    }
+
+    /// Inserts a new row, see `Database::insert`.
+    pub fn insert(&self, tx_id: TxID, row_id: u64, data: T) -> Result<()> {
+        self.db.insert(
+            tx_id,
+            Row {
+                id: self.row_id(row_id),
+                data,
+            },
+        )
+    }
+
+    /// Reads a row, see `Database::read`.
+    pub fn read(&self, tx_id: TxID, row_id: u64) -> Result<Option<Row<T>>> {
+        self.db.read(tx_id, self.row_id(row_id))
+    }
+
+    /// Updates a row, see `Database::update`.
+    pub fn update(&self, tx_id: TxID, row_id: u64, data: T) -> Result<bool> {
+        self.db.update(
+            tx_id,
+            Row {
+                id: self.row_id(row_id),
+                data,
+            },
+        )
+    }
+
+    /// Deletes a row, see `Database::delete`.
+    pub fn delete(&self, tx_id: TxID, row_id: u64) -> Result<bool> {
+        self.db.delete(tx_id, self.row_id(row_id))
+    }
+
+    /// Returns all rows of the table visible to the transaction, see `Database::scan_rows_for_table`.
+    pub fn scan_rows(&self, tx_id: TxID) -> Result<Vec<Row<T>>> {
+        self.db.scan_rows_for_table(tx_id, self.table_id)
+    }
+}
@@ -16,7 +16,7 @@ fn test_non_overlapping_concurrent_inserts() {
     // Two threads insert to the database concurrently using non-overlapping
     // row IDs.
     let clock = LocalClock::default();
-    let storage = mvcc_rs::persistent_storage::Storage::new_noop();
+    let storage = mvcc_rs::persistent_storage::Noop;
     let db = Arc::new(Database::new(clock, storage));
     let iterations = 100000;
 
@@ -26,12 +26,9 @@ fn test_non_overlapping_concurrent_inserts() {
             for _ in 0..iterations {
                 let tx = db.begin_tx();
                 let id = IDS.fetch_add(1, Ordering::SeqCst);
-                let id = RowID {
-                    table_id: 1,
-                    row_id: id,
-                };
+                let id = RowID::from_u64(1, id);
                 let row = Row {
-                    id,
+                    id: id.clone(),
                     data: "Hello".to_string(),
                 };
                 db.insert(tx, row.clone()).unwrap();
@@ -48,12 +45,9 @@ fn test_non_overlapping_concurrent_inserts() {
             for _ in 0..iterations {
                 let tx = db.begin_tx();
                 let id = IDS.fetch_add(1, Ordering::SeqCst);
-                let id = RowID {
-                    table_id: 1,
-                    row_id: id,
-                };
+                let id = RowID::from_u64(1, id);
                 let row = Row {
-                    id,
+                    id: id.clone(),
                     data: "World".to_string(),
                 };
                 db.insert(tx, row.clone()).unwrap();
@@ -75,7 +69,7 @@ fn test_overlapping_concurrent_inserts_read_your_writes() {
         tracing_subscriber::fmt::init();
     }); // Two threads insert to the database concurrently using overlapping row IDs.
     let clock = LocalClock::default();
-    let storage = mvcc_rs::persistent_storage::Storage::new_noop();
+    let storage = mvcc_rs::persistent_storage::Noop;
     let db = Arc::new(Database::new(clock, storage));
     let iterations = 100000;
 
@@ -93,12 +87,9 @@ fn test_overlapping_concurrent_inserts_read_your_writes() {
                 }
                 let tx = db.begin_tx();
                 let id = i % 16;
-                let id = RowID {
-                    table_id: 1,
-                    row_id: id,
-                };
+                let id = RowID::from_u64(1, id);
                 let row = Row {
-                    id,
+                    id: id.clone(),
                     data: format!("{prefix} @{tx}"),
                 };
                 if let Err(e) = db.upsert(tx, row.clone()) {